@@ -1,19 +1,19 @@
 use generational_arena::Index;
-use gltf::Gltf;
+use js_sys::Error;
 use log::info;
-use na::{Point2, Point3, UnitQuaternion, Vector2, Vector3, Vector4};
+use na::{Point2, Point3, Vector2, Vector3, Vector4};
 use ncollide3d::procedural::{unit_quad, TriMesh};
 use std::f32::consts::PI;
 use std::result::Result as StdResult;
 use wasm_bindgen::prelude::*;
 use web_sys::HtmlImageElement;
 
-use crate::renderer::webgl::context::{Context, TexParam, TextureFormat, TextureKind};
+use crate::renderer::webgl::context::{Context, TexParam, TextureFormat};
 use crate::renderer::webgl::material::{PbrMaterial, SkyboxMaterial};
 use crate::renderer::webgl::pass::Pass;
-use crate::renderer::webgl::renderer::{Camera, Renderer, Sampler};
+use crate::renderer::webgl::renderer::{Camera, DirectionalLight, Renderer, Sampler};
 use crate::renderer::webgl::turntable::Turntable;
-use crate::scene::node::{compose_matrix, Node};
+use crate::scene::node::Node;
 
 use super::perlin::get_perlin_data;
 use super::webgl_canvas::WebGlCanvas;
@@ -43,7 +43,6 @@ impl GLTFRendererDemo {
   ) -> StdResult<GLTFRendererDemo, JsValue> {
     let canvas = WebGlCanvas::new()?;
     let ctx = Context::new(canvas.gl.clone());
-    let gltf = Gltf::from_slice(gltf_data).unwrap();
     let mut turntable = Turntable::new(20.0, 0.01);
 
     turntable.roll = PI / 4.0;
@@ -52,35 +51,40 @@ impl GLTFRendererDemo {
 
     let camera_handle = renderer.cameras.insert(Camera::default());
 
-    let whale_handles = renderer.bake_gltf(&gltf);
+    renderer.insert_light(DirectionalLight::new(
+      Vector3::new(-0.5, -1.0, -0.3),
+      Vector3::new(1.0, 1.0, 1.0),
+      1.0,
+    ));
 
-    renderer
-      .scene
-      .set_parent(whale_handles[0], renderer.scene.get_root_handle());
+    let whale_handles = renderer
+      .bake_gltf_from_slice(gltf_data)
+      .map_err(|e| Error::new(&format!("{}", e)))?;
+
+    renderer.scene.set_parent(whale_handles[0], None);
 
     let whale_node = renderer.scene.get_node_mut(whale_handles[0]).unwrap();
 
-    whale_node.matrix_local = compose_matrix(
-      None,
-      Some(UnitQuaternion::from_euler_angles(PI / 2.0, 0.0, 0.0)),
-      Some(Vector3::new(1.4, 1.4, 1.4)),
-    );
+    whale_node
+      .set_rotation_euler(PI / 2.0, 0.0, 0.0)
+      .set_scale(Vector3::new(1.4, 1.4, 1.4));
 
     // info!("whale_handles {:#?}", whale_handles);
     // info!("renderer {:#?}", renderer);
 
-    let skybox_texture = renderer.bake_cube_map_texture(
-      TextureFormat::RGB,
-      Sampler::default(),
-      &[
-        (TextureKind::CubeMapNX, skybox_nx_image),
-        (TextureKind::CubeMapPX, skybox_px_image),
-        (TextureKind::CubeMapNY, skybox_ny_image),
-        (TextureKind::CubeMapPY, skybox_py_image),
-        (TextureKind::CubeMapNZ, skybox_nz_image),
-        (TextureKind::CubeMapPZ, skybox_pz_image),
-      ],
-    );
+    let skybox_texture = renderer
+      .bake_cube_map(
+        TextureFormat::RGB,
+        [
+          skybox_px_image,
+          skybox_nx_image,
+          skybox_py_image,
+          skybox_ny_image,
+          skybox_pz_image,
+          skybox_nz_image,
+        ],
+      )
+      .map_err(|e| Error::new(&format!("{}", e)))?;
 
     let skybox_geometry_handle = renderer.bake_cuboid_geometry(Vector3::new(1.0, 1.0, 1.0));
     let skybox_material_handle =
@@ -117,7 +121,7 @@ impl GLTFRendererDemo {
 
     let mut cuboid_node = Node::new(Some(renderer.scene.get_root_handle()));
 
-    cuboid_node.matrix_local = compose_matrix(Some(Vector3::new(-5.0, 0.0, 0.0)), None, None);
+    cuboid_node.set_translation(Vector3::new(-5.0, 0.0, 0.0));
     cuboid_node.mesh = Some(cuboid_mesh_handle);
     cuboid_node.name = Some(String::from("cuboid"));
 
@@ -135,6 +139,7 @@ impl GLTFRendererDemo {
     let ground_material_handle = renderer.bake_material(
       PbrMaterial::new()
         .set_color(Vector3::new(0.0, 0.8, 0.2))
+        .set_metallic(0.0)
         .set_cull_face(false)
         .set_color_map(Some(ground_texture_handle))
         .set_uv_repeating(Vector2::new(8.0, 8.0))
@@ -153,7 +158,7 @@ impl GLTFRendererDemo {
 
     let mut ground_node = Node::new(Some(renderer.scene.get_root_handle()));
 
-    ground_node.matrix_local = compose_matrix(Some(Vector3::new(0.0, -15.0, 0.0)), None, None);
+    ground_node.set_translation(Vector3::new(0.0, -15.0, 0.0));
     ground_node.mesh = Some(ground_mesh_handle);
     ground_node.name = Some(String::from("ground"));
 
@@ -192,8 +197,8 @@ impl GLTFRendererDemo {
         .viewport(0, 0, self.canvas.width as i32, self.canvas.height as i32);
     }
 
-    self.renderer.ctx.clear_color(1.0, 1.0, 1.0, 1.0);
-    self.renderer.ctx.clear(true, true);
+    self.renderer.set_clear_color(1.0, 1.0, 1.0, 1.0);
+    self.renderer.clear();
 
     self.renderer.scene.update_matrix_world();
 
@@ -160,7 +160,7 @@ fn create_skinning_stuff(ctx: &Context) -> Result<(Shader, Option<WebGlTexture>)
     BufferTarget::ArrayBuffer,
     BufferUsage::StaticDraw,
     &position,
-  );
+  )?;
 
   let bone_ndx: Vec<f32> = vec![
     0.0, 0.0, 0.0, 0.0, // 0
@@ -179,7 +179,7 @@ fn create_skinning_stuff(ctx: &Context) -> Result<(Shader, Option<WebGlTexture>)
     BufferTarget::ArrayBuffer,
     BufferUsage::StaticDraw,
     &bone_ndx,
-  );
+  )?;
 
   let weight: Vec<f32> = vec![
     1.0, 0.0, 0.0, 0.0, // 0
@@ -195,7 +195,7 @@ fn create_skinning_stuff(ctx: &Context) -> Result<(Shader, Option<WebGlTexture>)
   ];
 
   let weight_buffer =
-    ctx.create_buffer(BufferTarget::ArrayBuffer, BufferUsage::StaticDraw, &weight);
+    ctx.create_buffer(BufferTarget::ArrayBuffer, BufferUsage::StaticDraw, &weight)?;
 
   let indices: Vec<u16> = vec![
     0, 1, 0, 2, 1, 3, 2, 3, //
@@ -207,7 +207,7 @@ fn create_skinning_stuff(ctx: &Context) -> Result<(Shader, Option<WebGlTexture>)
     BufferTarget::ElementArrayBuffer,
     BufferUsage::StaticDraw,
     &indices,
-  );
+  )?;
 
   let bone_matrix_texture = ctx.create_texture();
 
@@ -236,27 +236,27 @@ fn create_skinning_stuff(ctx: &Context) -> Result<(Shader, Option<WebGlTexture>)
 
   shader.bind();
 
-  ctx.switch_attributes(3);
+  ctx.switch_attributes(&[0, 1, 2]);
 
-  ctx.bind_buffer(BufferTarget::ArrayBuffer, position_buffer.as_ref());
+  ctx.bind_buffer(BufferTarget::ArrayBuffer, Some(&position_buffer));
   shader.bind_attribute(
     &AttributeName::Position,
     &AttributeOptions::new(TypedArrayKind::Float32, 2),
   );
 
-  ctx.bind_buffer(BufferTarget::ArrayBuffer, bone_ndx_buffer.as_ref());
+  ctx.bind_buffer(BufferTarget::ArrayBuffer, Some(&bone_ndx_buffer));
   shader.bind_attribute(
     &AttributeName::from_string("boneNdx"),
     &AttributeOptions::new(TypedArrayKind::Float32, 4),
   );
 
-  ctx.bind_buffer(BufferTarget::ArrayBuffer, weight_buffer.as_ref());
+  ctx.bind_buffer(BufferTarget::ArrayBuffer, Some(&weight_buffer));
   shader.bind_attribute(
     &AttributeName::from_string("weight"),
     &AttributeOptions::new(TypedArrayKind::Float32, 4),
   );
 
-  ctx.bind_buffer(BufferTarget::ElementArrayBuffer, indices_buffer.as_ref());
+  ctx.bind_buffer(BufferTarget::ElementArrayBuffer, Some(&indices_buffer));
 
   Ok((shader, bone_matrix_texture))
 }
@@ -4,3 +4,4 @@ extern crate nalgebra as na;
 pub mod demo;
 pub mod renderer;
 pub mod scene;
+pub mod viewer;
@@ -1,9 +1,10 @@
 use generational_arena::Index;
-use na::{Matrix4, Perspective3};
+use na::Matrix4;
 
-use super::renderer::Renderer;
+use super::renderer::{Projection, Renderer};
 
 impl Renderer {
+  /// Raw matrices replace the camera parameters, later parameter based rebuilds don't apply
   pub fn update_camera(
     &mut self,
     handle: Index,
@@ -13,11 +14,11 @@ impl Renderer {
     let camera = self.cameras.get_mut(handle).unwrap();
 
     if let Some(v) = view {
-      camera.view = v;
+      camera.set_view_matrix(v);
     }
 
     if let Some(p) = projection {
-      camera.projection = p;
+      camera.set_projection_matrix(p);
     }
   }
 
@@ -29,8 +30,45 @@ impl Renderer {
     near: f32,
     far: f32,
   ) {
-    let perspective = Perspective3::new(aspect, fovy, near, far);
+    self
+      .cameras
+      .get_mut(handle)
+      .unwrap()
+      .set_projection(Projection::Perspective {
+        fov_y: fovy,
+        aspect,
+        near,
+        far,
+      });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::renderer::Camera;
+  use na::{Matrix4, Point3, Vector3};
+  use std::f32::consts::PI;
+
+  #[test]
+  fn raw_matrices_survive_parameter_rebuilds() {
+    let mut camera = Camera::perspective(PI / 4.0, 1.0, 0.1, 100.0).look_at(
+      &Point3::new(0.0, 0.0, 5.0),
+      &Point3::origin(),
+      &Vector3::y(),
+    );
+    let projection = Matrix4::new_scaling(2.0);
+
+    camera.set_projection_matrix(projection);
+    camera.set_aspect(2.0);
+
+    assert_eq!(camera.projection, projection);
+    assert!(camera.projection_params.is_none());
+
+    let view = Matrix4::new_translation(&Vector3::new(1.0, 2.0, 3.0));
+
+    camera.set_view_matrix(view);
 
-    self.update_camera(handle, None, Some(perspective.to_homogeneous()))
+    assert_eq!(camera.view, view);
+    assert!(camera.view_params.is_none());
   }
 }
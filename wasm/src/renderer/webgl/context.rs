@@ -1,43 +1,224 @@
 use super::define::Define;
+use super::renderer::RenderError;
 use super::shader::Shader;
 use anyhow::{anyhow, Result};
 use js_sys::{
-  Float32Array, Int16Array, Int8Array, Object, Uint16Array, Uint32Array, Uint8Array, WebAssembly,
+  Float32Array, Int16Array, Int32Array, Int8Array, Object, Uint16Array, Uint32Array, Uint8Array,
+  WebAssembly,
 };
 use num_traits::Num;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::default::Default;
+use std::fmt;
+use std::rc::Rc;
+use wasm_bindgen::closure::Closure;
 use wasm_bindgen::{JsCast, JsValue};
 use web_sys::{
-  HtmlImageElement, WebGlBuffer, WebGlFramebuffer, WebGlRenderingContext, WebGlTexture,
+  AngleInstancedArrays, Event, EventTarget, ExtTextureFilterAnisotropic, HtmlImageElement,
+  ImageBitmap, OesVertexArrayObject, WebGl2RenderingContext, WebGlBuffer, WebGlFramebuffer,
+  WebGlProgram, WebGlRenderbuffer, WebGlRenderingContext, WebGlTexture, WebGlVertexArrayObject,
+  WebglCompressedTextureAstc, WebglCompressedTextureEtc, WebglCompressedTextureS3tc,
 };
 
 #[derive(Debug)]
 pub struct Context {
   gl: WebGlRenderingContext,
-  attrib_amount: RefCell<u32>,
+  /// Locations enabled by the last switch_attributes, outside of vertex array objects
+  enabled_attributes: RefCell<Vec<u32>>,
+  instanced_arrays: Option<AngleInstancedArrays>,
+  vertex_array_object: Option<OesVertexArrayObject>,
+  limits: ContextLimits,
+  /// Uint32 indices are core in WebGL2, WebGL1 needs OES_element_index_uint which older mobile
+  /// GPUs lack
+  element_index_uint: bool,
+  /// None when the canvas can't be listened to
+  loss: Option<ContextLoss>,
+  compression: CompressionSupport,
+}
+
+/// Device capabilities, queried once
+#[derive(Debug, Clone, Copy)]
+pub struct ContextLimits {
+  pub max_texture_size: u32,
+  pub max_cube_map_size: u32,
+  pub max_vertex_attribs: u32,
+  /// Per fragment shader
+  pub max_texture_image_units: u32,
+  /// None when EXT_texture_filter_anisotropic is missing
+  pub max_anisotropy: Option<f32>,
+}
+
+/// Compressed texture families the device can sample, queried once
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CompressionSupport {
+  pub s3tc: bool,
+  pub etc: bool,
+  pub astc: bool,
+}
+
+type RestoreCallback = Rc<RefCell<Option<Box<dyn FnMut()>>>>;
+type EventListener = (&'static str, Closure<dyn FnMut(Event)>);
+
+/// Tracks webglcontextlost / webglcontextrestored on the canvas. Every GL object
+/// and extension is invalid after a restore, so the callback has to rebuild them.
+struct ContextLoss {
+  lost: Rc<Cell<bool>>,
+  on_restored: RestoreCallback,
+  target: EventTarget,
+  listeners: [EventListener; 2],
+}
+
+impl ContextLoss {
+  fn new(target: EventTarget) -> Self {
+    let lost = Rc::new(Cell::new(false));
+    let on_restored: RestoreCallback = Rc::new(RefCell::new(None));
+
+    let on_lost_listener = {
+      let lost = lost.clone();
+
+      Closure::wrap(Box::new(move |event: Event| {
+        // without it the browser never fires webglcontextrestored
+        event.prevent_default();
+        lost.set(true);
+      }) as Box<dyn FnMut(Event)>)
+    };
+
+    let on_restored_listener = {
+      let lost = lost.clone();
+      let on_restored = on_restored.clone();
+
+      Closure::wrap(Box::new(move |_: Event| {
+        lost.set(false);
+
+        if let Some(callback) = on_restored.borrow_mut().as_mut() {
+          callback();
+        }
+      }) as Box<dyn FnMut(Event)>)
+    };
+
+    let listeners = [
+      ("webglcontextlost", on_lost_listener),
+      ("webglcontextrestored", on_restored_listener),
+    ];
+
+    for (event, listener) in &listeners {
+      target
+        .add_event_listener_with_callback(event, listener.as_ref().unchecked_ref())
+        .ok();
+    }
+
+    ContextLoss {
+      lost,
+      on_restored,
+      target,
+      listeners,
+    }
+  }
+}
+
+impl Drop for ContextLoss {
+  fn drop(&mut self) {
+    for (event, listener) in &self.listeners {
+      self
+        .target
+        .remove_event_listener_with_callback(event, listener.as_ref().unchecked_ref())
+        .ok();
+    }
+  }
+}
+
+impl fmt::Debug for ContextLoss {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("ContextLoss")
+      .field("lost", &self.lost.get())
+      .finish()
+  }
 }
 
 impl Context {
   pub fn new(gl: WebGlRenderingContext) -> Context {
+    let instanced_arrays = gl
+      .get_extension("ANGLE_instanced_arrays")
+      .ok()
+      .flatten()
+      .map(|ext| ext.unchecked_into::<AngleInstancedArrays>());
+    let vertex_array_object = gl
+      .get_extension("OES_vertex_array_object")
+      .ok()
+      .flatten()
+      .map(|ext| ext.unchecked_into::<OesVertexArrayObject>());
+
+    let webgl2 = gl.dyn_ref::<WebGl2RenderingContext>().is_some();
+    let element_index_uint = has_extension_or_core(webgl2, "OES_element_index_uint", |name| {
+      matches!(gl.get_extension(name), Ok(Some(_)))
+    });
+
+    let max_anisotropy = gl
+      .get_extension("EXT_texture_filter_anisotropic")
+      .ok()
+      .flatten()
+      .and_then(|_| {
+        gl.get_parameter(ExtTextureFilterAnisotropic::MAX_TEXTURE_MAX_ANISOTROPY_EXT)
+          .ok()
+      })
+      .and_then(|value| value.as_f64())
+      .map(|value| value as f32);
+
+    // a lost context answers null, the fallbacks are the WebGL1 minimums
+    let get_limit = |name: u32, minimum: u32| {
+      gl.get_parameter(name)
+        .ok()
+        .and_then(|value| value.as_f64())
+        .map_or(minimum, |value| value as u32)
+    };
+    let limits = ContextLimits {
+      max_texture_size: get_limit(WebGlRenderingContext::MAX_TEXTURE_SIZE, 64),
+      max_cube_map_size: get_limit(WebGlRenderingContext::MAX_CUBE_MAP_TEXTURE_SIZE, 16),
+      max_vertex_attribs: get_limit(WebGlRenderingContext::MAX_VERTEX_ATTRIBS, 8),
+      max_texture_image_units: get_limit(WebGlRenderingContext::MAX_TEXTURE_IMAGE_UNITS, 8),
+      max_anisotropy,
+    };
+
+    let has_extension = |name: &str| matches!(gl.get_extension(name), Ok(Some(_)));
+    let compression = CompressionSupport {
+      s3tc: has_extension(CompressedTextureFormat::RgbS3tcDxt1.get_extension_name()),
+      etc: has_extension(CompressedTextureFormat::Rgb8Etc2.get_extension_name()),
+      astc: has_extension(CompressedTextureFormat::RgbaAstc4x4.get_extension_name()),
+    };
+
+    let loss = gl
+      .canvas()
+      .and_then(|canvas| canvas.dyn_into::<EventTarget>().ok())
+      .map(ContextLoss::new);
+
     Context {
       gl,
-      attrib_amount: RefCell::new(0),
+      enabled_attributes: RefCell::new(vec![]),
+      instanced_arrays,
+      vertex_array_object,
+      limits,
+      element_index_uint,
+      loss,
+      compression,
     }
   }
 
-  pub fn get_extension(&self, name: &str) -> Result<Object> {
-    self
-      .gl
-      .get_extension(name)
-      .map_err(|e| anyhow!("{:?}", e))?
-      .ok_or_else(|| anyhow!("Unable to get extension {}", name))
+  pub fn get_extension(&self, name: &str) -> Result<Object, RenderError> {
+    match self.gl.get_extension(name) {
+      Ok(Some(extension)) => Ok(extension),
+      _ => Err(RenderError::MissingExtension(name.to_string())),
+    }
   }
 
   pub fn viewport(&self, x: i32, y: i32, width: i32, height: i32) {
     self.gl.viewport(x, y, width, height);
   }
 
+  /// Only affects drawing while Feature::ScissorTest is on
+  pub fn scissor(&self, x: i32, y: i32, width: i32, height: i32) {
+    self.gl.scissor(x, y, width, height);
+  }
+
   pub fn clear(&self, color: bool, depth: bool) {
     let mut clear = 0;
 
@@ -56,15 +237,17 @@ impl Context {
     self.gl.clear_color(r, g, b, a);
   }
 
+  pub fn clear_depth(&self, depth: f32) {
+    self.gl.clear_depth(depth);
+  }
+
   pub fn create_shader(
     &self,
     vertex_src: &str,
     fragment_src: &str,
     defines: &[Define],
-  ) -> Result<Shader> {
-    let shader = Shader::new(&self.gl, vertex_src, fragment_src, defines)?;
-
-    Ok(shader)
+  ) -> Result<Shader, RenderError> {
+    Shader::new(&self.gl, vertex_src, fragment_src, defines)
   }
 
   pub fn create_buffer<T: BufferItem>(
@@ -72,8 +255,8 @@ impl Context {
     target: BufferTarget,
     usage: BufferUsage,
     data: &[T],
-  ) -> Option<WebGlBuffer> {
-    let buffer = self.gl.create_buffer()?;
+  ) -> Result<WebGlBuffer, RenderError> {
+    let buffer = self.gl.create_buffer().ok_or(RenderError::BufferCreate)?;
 
     self.gl.bind_buffer(target.as_u32(), Some(&buffer));
 
@@ -85,18 +268,89 @@ impl Context {
 
     self.gl.bind_buffer(target.as_u32(), None);
 
-    Some(buffer)
+    Ok(buffer)
+  }
+
+  /// Allocated size in bytes, as uploaded by `create_buffer`
+  pub fn get_buffer_size(&self, target: BufferTarget, buffer: &WebGlBuffer) -> usize {
+    self.gl.bind_buffer(target.as_u32(), Some(buffer));
+
+    let size = self
+      .gl
+      .get_buffer_parameter(target.as_u32(), WebGlRenderingContext::BUFFER_SIZE)
+      .as_f64()
+      .unwrap_or(0.0) as usize;
+
+    self.gl.bind_buffer(target.as_u32(), None);
+
+    size
+  }
+
+  /// Overwrites bytes from offset on, the caller keeps the range inside the allocation
+  pub fn update_buffer<T: BufferItem>(
+    &self,
+    target: BufferTarget,
+    buffer: &WebGlBuffer,
+    offset: usize,
+    data: &[T],
+  ) {
+    self.gl.bind_buffer(target.as_u32(), Some(buffer));
+
+    let array = get_typed_array_from_slice(data);
+
+    self
+      .gl
+      .buffer_sub_data_with_i32_and_array_buffer_view(target.as_u32(), offset as i32, &array);
+
+    self.gl.bind_buffer(target.as_u32(), None);
+  }
+
+  /// Empty buffer for `update_uniform_buffer`, WebGL2 only
+  pub fn create_uniform_buffer(&self) -> Result<WebGlBuffer, RenderError> {
+    self
+      .get_webgl2()
+      .ok_or_else(|| RenderError::MissingExtension("WebGL2".to_string()))?;
+    self.gl.create_buffer().ok_or(RenderError::BufferCreate)
+  }
+
+  /// Replaces the buffer contents and attaches it to the uniform block binding point
+  pub fn update_uniform_buffer(&self, buffer: &WebGlBuffer, binding: u32, data: &[f32]) {
+    if let Some(gl) = self.get_webgl2() {
+      let array = get_typed_array_from_slice(data);
+
+      gl.bind_buffer(WebGl2RenderingContext::UNIFORM_BUFFER, Some(buffer));
+      gl.buffer_data_with_array_buffer_view(
+        WebGl2RenderingContext::UNIFORM_BUFFER,
+        &array,
+        WebGl2RenderingContext::DYNAMIC_DRAW,
+      );
+      gl.bind_buffer_base(
+        WebGl2RenderingContext::UNIFORM_BUFFER,
+        binding,
+        Some(buffer),
+      );
+    }
   }
 
   pub fn bind_buffer(&self, target: BufferTarget, buffer: Option<&WebGlBuffer>) {
     self.gl.bind_buffer(target.as_u32(), buffer);
   }
 
+  pub fn delete_buffer(&self, buffer: Option<&WebGlBuffer>) {
+    self.gl.delete_buffer(buffer);
+  }
+
   pub fn create_texture(&self) -> Option<WebGlTexture> {
     self.gl.create_texture()
   }
 
   pub fn active_texture(&self, unit: u32) {
+    debug_assert!(
+      unit < self.limits.max_texture_image_units,
+      "texture unit {} is out of device range",
+      unit
+    );
+
     self
       .gl
       .active_texture(WebGlRenderingContext::TEXTURE0 + unit);
@@ -112,6 +366,46 @@ impl Context {
       .tex_parameteri(target.as_u32(), name.as_u32(), param.as_u32() as i32)
   }
 
+  /// Clamped to the supported maximum, ignored without the extension
+  pub fn texture_anisotropy(&self, target: TextureKind, level: f32) {
+    if let Some(max_anisotropy) = self.limits.max_anisotropy {
+      self.gl.tex_parameterf(
+        target.as_u32(),
+        ExtTextureFilterAnisotropic::TEXTURE_MAX_ANISOTROPY_EXT,
+        level.max(1.0).min(max_anisotropy),
+      );
+    }
+  }
+
+  pub fn get_max_anisotropy(&self) -> Option<f32> {
+    self.limits.max_anisotropy
+  }
+
+  pub fn limits(&self) -> ContextLimits {
+    self.limits
+  }
+
+  /// Data must be the already transcoded blocks of one mip level
+  pub fn compressed_texture_data(
+    &self,
+    target: TextureKind,
+    level: i32,
+    format: CompressedTextureFormat,
+    width: i32,
+    height: i32,
+    data: &[u8],
+  ) {
+    self.gl.compressed_tex_image_2d_with_u8_array(
+      target.as_u32(),
+      level,
+      format.as_u32(),
+      width,
+      height,
+      0,
+      data,
+    );
+  }
+
   pub fn texture_data<T: BufferItem>(
     &self,
     target: TextureKind,
@@ -190,6 +484,28 @@ impl Context {
       .map_err(|e| anyhow!("{:?}", e))
   }
 
+  pub fn texture_image_bitmap_data(
+    &self,
+    target: TextureKind,
+    level: i32,
+    internal_format: TextureFormat,
+    format: TextureFormat,
+    array_kind: TypedArrayKind,
+    image: &ImageBitmap,
+  ) -> Result<()> {
+    self
+      .gl
+      .tex_image_2d_with_u32_and_u32_and_image_bitmap(
+        target.as_u32(),
+        level,
+        internal_format.as_u32() as i32,
+        format.as_u32(),
+        array_kind.as_u32(),
+        image,
+      )
+      .map_err(|e| anyhow!("{:?}", e))
+  }
+
   pub fn generate_mipmap(&self, target: TextureKind) {
     self.gl.generate_mipmap(target.as_u32())
   }
@@ -218,6 +534,225 @@ impl Context {
     );
   }
 
+  pub fn delete_framebuffer(&self, fb: Option<&WebGlFramebuffer>) {
+    self.gl.delete_framebuffer(fb);
+  }
+
+  pub fn delete_program(&self, program: Option<&WebGlProgram>) {
+    self.gl.delete_program(program);
+  }
+
+  pub fn delete_texture(&self, texture: Option<&WebGlTexture>) {
+    self.gl.delete_texture(texture);
+  }
+
+  pub fn create_renderbuffer(&self) -> Option<WebGlRenderbuffer> {
+    self.gl.create_renderbuffer()
+  }
+
+  pub fn delete_renderbuffer(&self, renderbuffer: Option<&WebGlRenderbuffer>) {
+    self.gl.delete_renderbuffer(renderbuffer);
+  }
+
+  pub fn depth_renderbuffer_storage(
+    &self,
+    renderbuffer: &WebGlRenderbuffer,
+    width: i32,
+    height: i32,
+  ) {
+    self
+      .gl
+      .bind_renderbuffer(WebGlRenderingContext::RENDERBUFFER, Some(renderbuffer));
+    self.gl.renderbuffer_storage(
+      WebGlRenderingContext::RENDERBUFFER,
+      WebGlRenderingContext::DEPTH_COMPONENT16,
+      width,
+      height,
+    );
+    self
+      .gl
+      .bind_renderbuffer(WebGlRenderingContext::RENDERBUFFER, None);
+  }
+
+  pub fn depth_stencil_renderbuffer_storage(
+    &self,
+    renderbuffer: &WebGlRenderbuffer,
+    width: i32,
+    height: i32,
+  ) {
+    self
+      .gl
+      .bind_renderbuffer(WebGlRenderingContext::RENDERBUFFER, Some(renderbuffer));
+    self.gl.renderbuffer_storage(
+      WebGlRenderingContext::RENDERBUFFER,
+      WebGlRenderingContext::DEPTH_STENCIL,
+      width,
+      height,
+    );
+    self
+      .gl
+      .bind_renderbuffer(WebGlRenderingContext::RENDERBUFFER, None);
+  }
+
+  pub fn framebuffer_renderbuffer(
+    &self,
+    attachment: FramebufferAttachment,
+    renderbuffer: Option<&WebGlRenderbuffer>,
+  ) {
+    self.gl.framebuffer_renderbuffer(
+      WebGlRenderingContext::FRAMEBUFFER,
+      attachment.as_u32(),
+      WebGlRenderingContext::RENDERBUFFER,
+      renderbuffer,
+    );
+  }
+
+  pub fn get_clear_color(&self) -> [f32; 4] {
+    let mut color = [0.0; 4];
+
+    if let Ok(value) = self
+      .gl
+      .get_parameter(WebGlRenderingContext::COLOR_CLEAR_VALUE)
+    {
+      Float32Array::from(value).copy_to(&mut color);
+    }
+
+    color
+  }
+
+  /// Reads RGBA bytes from the bound framebuffer, origin is bottom-left
+  pub fn read_pixels(
+    &self,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    data: &mut [u8],
+  ) -> Option<()> {
+    self
+      .gl
+      .read_pixels_with_opt_u8_array(
+        x,
+        y,
+        width,
+        height,
+        WebGlRenderingContext::RGBA,
+        WebGlRenderingContext::UNSIGNED_BYTE,
+        Some(data),
+      )
+      .ok()
+  }
+
+  pub fn get_viewport(&self) -> [i32; 4] {
+    let mut viewport = [0; 4];
+
+    if let Ok(value) = self.gl.get_parameter(WebGlRenderingContext::VIEWPORT) {
+      Int32Array::from(value).copy_to(&mut viewport);
+    }
+
+    viewport
+  }
+
+  /// Box of the scissor test, kept even while the test is off
+  pub fn get_scissor(&self) -> [i32; 4] {
+    let mut scissor = [0; 4];
+
+    if let Ok(value) = self.gl.get_parameter(WebGlRenderingContext::SCISSOR_BOX) {
+      Int32Array::from(value).copy_to(&mut scissor);
+    }
+
+    scissor
+  }
+
+  /// None while drawing to the canvas
+  pub fn get_framebuffer(&self) -> Option<WebGlFramebuffer> {
+    self
+      .gl
+      .get_parameter(WebGlRenderingContext::FRAMEBUFFER_BINDING)
+      .ok()
+      .and_then(|value| value.dyn_into::<WebGlFramebuffer>().ok())
+  }
+
+  /// Some when the canvas was asked for "webgl2", unlocks multisampled renderbuffers and blitting
+  pub fn get_webgl2(&self) -> Option<&WebGl2RenderingContext> {
+    self.gl.dyn_ref::<WebGl2RenderingContext>()
+  }
+
+  pub fn get_max_samples(&self) -> i32 {
+    self
+      .get_webgl2()
+      .and_then(|gl| gl.get_parameter(WebGl2RenderingContext::MAX_SAMPLES).ok())
+      .and_then(|value| value.as_f64())
+      .map(|value| value as i32)
+      .unwrap_or(0)
+  }
+
+  pub fn renderbuffer_storage_multisample(
+    &self,
+    renderbuffer: &WebGlRenderbuffer,
+    samples: i32,
+    format: RenderbufferFormat,
+    width: i32,
+    height: i32,
+  ) -> Option<()> {
+    let gl = self.get_webgl2()?;
+
+    gl.bind_renderbuffer(WebGl2RenderingContext::RENDERBUFFER, Some(renderbuffer));
+    gl.renderbuffer_storage_multisample(
+      WebGl2RenderingContext::RENDERBUFFER,
+      samples,
+      format.as_u32(),
+      width,
+      height,
+    );
+    gl.bind_renderbuffer(WebGl2RenderingContext::RENDERBUFFER, None);
+
+    Some(())
+  }
+
+  /// Copies the color buffer, leaves no framebuffer bound
+  pub fn blit_framebuffer(
+    &self,
+    read: &WebGlFramebuffer,
+    draw: &WebGlFramebuffer,
+    width: i32,
+    height: i32,
+  ) -> Option<()> {
+    let gl = self.get_webgl2()?;
+
+    gl.bind_framebuffer(WebGl2RenderingContext::READ_FRAMEBUFFER, Some(read));
+    gl.bind_framebuffer(WebGl2RenderingContext::DRAW_FRAMEBUFFER, Some(draw));
+    gl.blit_framebuffer(
+      0,
+      0,
+      width,
+      height,
+      0,
+      0,
+      width,
+      height,
+      WebGl2RenderingContext::COLOR_BUFFER_BIT,
+      WebGl2RenderingContext::NEAREST,
+    );
+    gl.bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+
+    Some(())
+  }
+
+  /// Copies from the bound framebuffer into the bound 2d texture
+  pub fn copy_tex_sub_image_2d(&self, width: i32, height: i32) {
+    self.gl.copy_tex_sub_image_2d(
+      WebGlRenderingContext::TEXTURE_2D,
+      0,
+      0,
+      0,
+      0,
+      0,
+      width,
+      height,
+    );
+  }
+
   pub fn check_framebuffer_complete(&self) -> bool {
     let status = self
       .gl
@@ -226,20 +761,105 @@ impl Context {
     status == WebGlRenderingContext::FRAMEBUFFER_COMPLETE
   }
 
-  pub fn switch_attributes(&self, amount: u32) {
-    let current_amount = *self.attrib_amount.borrow();
+  /// Enables exactly these locations, disabling whatever the previous call left on
+  pub fn switch_attributes(&self, locations: &[u32]) {
+    debug_assert!(
+      locations
+        .iter()
+        .all(|location| *location < self.limits.max_vertex_attribs),
+      "vertex attribute locations {:?} exceed the device limit",
+      locations
+    );
 
-    if current_amount < amount {
-      for location in current_amount..amount {
-        self.gl.enable_vertex_attrib_array(location);
+    let mut enabled = self.enabled_attributes.borrow_mut();
+
+    for location in enabled.iter() {
+      if !locations.contains(location) {
+        self.gl.disable_vertex_attrib_array(*location);
+      }
+    }
+
+    for location in locations {
+      if !enabled.contains(location) {
+        self.gl.enable_vertex_attrib_array(*location);
       }
-    } else if current_amount > amount {
-      for location in amount..current_amount {
-        self.gl.disable_vertex_attrib_array(location);
+    }
+
+    *enabled = locations.to_vec();
+  }
+
+  pub fn enable_attribute(&self, location: u32) {
+    self.gl.enable_vertex_attrib_array(location);
+  }
+
+  /// A disabled attribute reads the constant (0, 0, 0, 1)
+  pub fn disable_attribute(&self, location: u32) {
+    self.gl.disable_vertex_attrib_array(location);
+  }
+
+  /// GL calls are silently dropped while the context is lost
+  pub fn is_lost(&self) -> bool {
+    match &self.loss {
+      Some(loss) => loss.lost.get(),
+      None => self.gl.is_context_lost(),
+    }
+  }
+
+  /// Called once the context is back. Buffers, textures and shaders are gone by then, so the
+  /// callback should flag the renderer for a rebuild rather than drop this context from inside it.
+  pub fn set_on_restored<F: FnMut() + 'static>(&self, callback: F) {
+    if let Some(loss) = &self.loss {
+      *loss.on_restored.borrow_mut() = Some(Box::new(callback));
+    }
+  }
+
+  pub fn get_compression_support(&self) -> CompressionSupport {
+    self.compression
+  }
+
+  pub fn supports_compressed_format(&self, format: CompressedTextureFormat) -> bool {
+    match format {
+      CompressedTextureFormat::RgbS3tcDxt1
+      | CompressedTextureFormat::RgbaS3tcDxt1
+      | CompressedTextureFormat::RgbaS3tcDxt3
+      | CompressedTextureFormat::RgbaS3tcDxt5 => self.compression.s3tc,
+      CompressedTextureFormat::Rgb8Etc2 | CompressedTextureFormat::Rgba8Etc2Eac => {
+        self.compression.etc
       }
+      CompressedTextureFormat::RgbaAstc4x4 => self.compression.astc,
     }
+  }
 
-    self.attrib_amount.replace(amount);
+  pub fn has_element_index_uint(&self) -> bool {
+    self.element_index_uint
+  }
+
+  /// Core in WebGL2, WebGL1 falls back to OES_vertex_array_object
+  pub fn has_vertex_arrays(&self) -> bool {
+    self.get_webgl2().is_some() || self.vertex_array_object.is_some()
+  }
+
+  pub fn create_vertex_array(&self) -> Option<WebGlVertexArrayObject> {
+    match self.get_webgl2() {
+      Some(gl) => gl.create_vertex_array(),
+      None => self.vertex_array_object.as_ref()?.create_vertex_array_oes(),
+    }
+  }
+
+  pub fn bind_vertex_array(&self, vertex_array: Option<&WebGlVertexArrayObject>) {
+    if let Some(gl) = self.get_webgl2() {
+      gl.bind_vertex_array(vertex_array);
+    } else if let Some(ext) = &self.vertex_array_object {
+      ext.bind_vertex_array_oes(vertex_array);
+    }
+  }
+
+  pub fn delete_vertex_array(&self, vertex_array: Option<&WebGlVertexArrayObject>) {
+    if let Some(gl) = self.get_webgl2() {
+      gl.delete_vertex_array(vertex_array);
+    } else if let Some(ext) = &self.vertex_array_object {
+      ext.delete_vertex_array_oes(vertex_array);
+    }
   }
 
   pub fn enable(&self, feature: Feature) {
@@ -250,6 +870,10 @@ impl Context {
     self.gl.disable(feature.as_u32())
   }
 
+  pub fn is_enabled(&self, feature: Feature) -> bool {
+    self.gl.is_enabled(feature.as_u32())
+  }
+
   pub fn set(&self, feature: Feature, enabled: bool) {
     if enabled {
       self.enable(feature);
@@ -262,6 +886,52 @@ impl Context {
     self.gl.depth_func(func.as_u32());
   }
 
+  pub fn set_front_face(&self, front_face: FrontFace) {
+    self.gl.front_face(front_face.as_u32());
+  }
+
+  /// Only affects drawing while Feature::PolygonOffsetFill is on
+  pub fn set_polygon_offset(&self, factor: f32, units: f32) {
+    self.gl.polygon_offset(factor, units);
+  }
+
+  /// Most browsers, ANGLE in particular, clamp the width to 1 and ignore anything wider
+  pub fn set_line_width(&self, width: f32) {
+    self.gl.line_width(width);
+  }
+
+  pub fn color_mask(&self, enabled: bool) {
+    self.gl.color_mask(enabled, enabled, enabled, enabled);
+  }
+
+  pub fn depth_mask(&self, enabled: bool) {
+    self.gl.depth_mask(enabled);
+  }
+
+  pub fn blend_func(&self, src: BlendFactor, dst: BlendFactor) {
+    self.gl.blend_func(src.as_u32(), dst.as_u32());
+  }
+
+  pub fn set_stencil_func(&self, func: StencilFunc, reference: i32, mask: u32) {
+    self.gl.stencil_func(func.as_u32(), reference, mask);
+  }
+
+  pub fn set_stencil_op(&self, sfail: StencilOp, dpfail: StencilOp, dppass: StencilOp) {
+    self
+      .gl
+      .stencil_op(sfail.as_u32(), dpfail.as_u32(), dppass.as_u32());
+  }
+
+  pub fn stencil_mask(&self, mask: u32) {
+    self.gl.stencil_mask(mask);
+  }
+
+  /// Fills the stencil buffer with `value`
+  pub fn clear_stencil(&self, value: i32) {
+    self.gl.clear_stencil(value);
+    self.gl.clear(Cleaning::Stencil.as_u32());
+  }
+
   pub fn draw_arrays(&self, mode: DrawMode, first: i32, count: i32) {
     self.gl.draw_arrays(mode.as_u32(), first, count);
   }
@@ -271,11 +941,68 @@ impl Context {
       .gl
       .draw_elements_with_i32(mode.as_u32(), count, kind.as_u32(), offset);
   }
+
+  /// Instancing is core in WebGL2, WebGL1 goes through ANGLE_instanced_arrays
+  pub fn vertex_attrib_divisor(&self, location: u32, divisor: u32) -> Option<()> {
+    if let Some(gl) = self.get_webgl2() {
+      gl.vertex_attrib_divisor(location, divisor);
+    } else {
+      let ext = self.instanced_arrays.as_ref()?;
+
+      ext.vertex_attrib_divisor_angle(location, divisor);
+    }
+
+    Some(())
+  }
+
+  pub fn draw_arrays_instanced(
+    &self,
+    mode: DrawMode,
+    first: i32,
+    count: i32,
+    instances: i32,
+  ) -> Option<()> {
+    if let Some(gl) = self.get_webgl2() {
+      gl.draw_arrays_instanced(mode.as_u32(), first, count, instances);
+    } else {
+      let ext = self.instanced_arrays.as_ref()?;
+
+      ext.draw_arrays_instanced_angle(mode.as_u32(), first, count, instances);
+    }
+
+    Some(())
+  }
+
+  pub fn draw_elements_instanced(
+    &self,
+    mode: DrawMode,
+    count: i32,
+    kind: TypedArrayKind,
+    offset: i32,
+    instances: i32,
+  ) -> Option<()> {
+    if let Some(gl) = self.get_webgl2() {
+      gl.draw_elements_instanced_with_i32(mode.as_u32(), count, kind.as_u32(), offset, instances);
+    } else {
+      let ext = self.instanced_arrays.as_ref()?;
+
+      ext.draw_elements_instanced_angle_with_i32(
+        mode.as_u32(),
+        count,
+        kind.as_u32(),
+        offset,
+        instances,
+      );
+    }
+
+    Some(())
+  }
 }
 
 pub enum Cleaning {
   Color,
   Depth,
+  Stencil,
 }
 
 impl Cleaning {
@@ -283,11 +1010,12 @@ impl Cleaning {
     match self {
       Self::Color => WebGlRenderingContext::COLOR_BUFFER_BIT,
       Self::Depth => WebGlRenderingContext::DEPTH_BUFFER_BIT,
+      Self::Stencil => WebGlRenderingContext::STENCIL_BUFFER_BIT,
     }
   }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum BufferTarget {
   ArrayBuffer,        // for generic data
   ElementArrayBuffer, // for indices only
@@ -318,24 +1046,42 @@ impl BufferUsage {
   }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DrawMode {
-  Triangles,
+  Points,
   Lines,
+  LineLoop,
+  LineStrip,
+  Triangles,
+  TriangleStrip,
+  TriangleFan,
 }
 
 impl DrawMode {
   pub fn as_u32(&self) -> u32 {
     match self {
-      Self::Triangles => WebGlRenderingContext::TRIANGLES,
+      Self::Points => WebGlRenderingContext::POINTS,
       Self::Lines => WebGlRenderingContext::LINES,
+      Self::LineLoop => WebGlRenderingContext::LINE_LOOP,
+      Self::LineStrip => WebGlRenderingContext::LINE_STRIP,
+      Self::Triangles => WebGlRenderingContext::TRIANGLES,
+      Self::TriangleStrip => WebGlRenderingContext::TRIANGLE_STRIP,
+      Self::TriangleFan => WebGlRenderingContext::TRIANGLE_FAN,
     }
   }
+
+  pub fn is_lines(&self) -> bool {
+    matches!(self, Self::Lines | Self::LineLoop | Self::LineStrip)
+  }
 }
 
 pub enum Feature {
   CullFace,
   DepthTest,
+  Blend,
+  ScissorTest,
+  StencilTest,
+  PolygonOffsetFill,
 }
 
 impl Feature {
@@ -343,20 +1089,145 @@ impl Feature {
     match self {
       Self::CullFace => WebGlRenderingContext::CULL_FACE,
       Self::DepthTest => WebGlRenderingContext::DEPTH_TEST,
+      Self::Blend => WebGlRenderingContext::BLEND,
+      Self::ScissorTest => WebGlRenderingContext::SCISSOR_TEST,
+      Self::StencilTest => WebGlRenderingContext::STENCIL_TEST,
+      Self::PolygonOffsetFill => WebGlRenderingContext::POLYGON_OFFSET_FILL,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlendFactor {
+  Zero,
+  One,
+  SrcColor,
+  OneMinusSrcColor,
+  SrcAlpha,
+  OneMinusSrcAlpha,
+  DstAlpha,
+  OneMinusDstAlpha,
+}
+
+impl BlendFactor {
+  pub fn as_u32(&self) -> u32 {
+    match self {
+      Self::Zero => WebGlRenderingContext::ZERO,
+      Self::One => WebGlRenderingContext::ONE,
+      Self::SrcColor => WebGlRenderingContext::SRC_COLOR,
+      Self::OneMinusSrcColor => WebGlRenderingContext::ONE_MINUS_SRC_COLOR,
+      Self::SrcAlpha => WebGlRenderingContext::SRC_ALPHA,
+      Self::OneMinusSrcAlpha => WebGlRenderingContext::ONE_MINUS_SRC_ALPHA,
+      Self::DstAlpha => WebGlRenderingContext::DST_ALPHA,
+      Self::OneMinusDstAlpha => WebGlRenderingContext::ONE_MINUS_DST_ALPHA,
     }
   }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DepthFunc {
+  Never,
   Less,
   Lequal,
+  Greater,
+  Gequal,
+  Equal,
+  NotEqual,
+  Always,
 }
 
 impl DepthFunc {
   pub fn as_u32(&self) -> u32 {
     match self {
+      Self::Never => WebGlRenderingContext::NEVER,
+      Self::Less => WebGlRenderingContext::LESS,
+      Self::Lequal => WebGlRenderingContext::LEQUAL,
+      Self::Greater => WebGlRenderingContext::GREATER,
+      Self::Gequal => WebGlRenderingContext::GEQUAL,
+      Self::Equal => WebGlRenderingContext::EQUAL,
+      Self::NotEqual => WebGlRenderingContext::NOTEQUAL,
+      Self::Always => WebGlRenderingContext::ALWAYS,
+    }
+  }
+
+  /// Same comparison with near and far swapped, for reverse-Z depth
+  pub fn reversed(&self) -> Self {
+    match self {
+      Self::Less => Self::Greater,
+      Self::Lequal => Self::Gequal,
+      Self::Greater => Self::Less,
+      Self::Gequal => Self::Lequal,
+      other => *other,
+    }
+  }
+}
+
+/// Winding of front facing triangles, GL defaults to counter-clockwise
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrontFace {
+  Ccw,
+  Cw,
+}
+
+impl FrontFace {
+  pub fn as_u32(&self) -> u32 {
+    match self {
+      Self::Ccw => WebGlRenderingContext::CCW,
+      Self::Cw => WebGlRenderingContext::CW,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StencilFunc {
+  Never,
+  Less,
+  Lequal,
+  Greater,
+  Gequal,
+  Equal,
+  NotEqual,
+  Always,
+}
+
+impl StencilFunc {
+  pub fn as_u32(&self) -> u32 {
+    match self {
+      Self::Never => WebGlRenderingContext::NEVER,
       Self::Less => WebGlRenderingContext::LESS,
       Self::Lequal => WebGlRenderingContext::LEQUAL,
+      Self::Greater => WebGlRenderingContext::GREATER,
+      Self::Gequal => WebGlRenderingContext::GEQUAL,
+      Self::Equal => WebGlRenderingContext::EQUAL,
+      Self::NotEqual => WebGlRenderingContext::NOTEQUAL,
+      Self::Always => WebGlRenderingContext::ALWAYS,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StencilOp {
+  Keep,
+  Zero,
+  Replace,
+  Incr,
+  IncrWrap,
+  Decr,
+  DecrWrap,
+  Invert,
+}
+
+impl StencilOp {
+  pub fn as_u32(&self) -> u32 {
+    match self {
+      Self::Keep => WebGlRenderingContext::KEEP,
+      Self::Zero => WebGlRenderingContext::ZERO,
+      Self::Replace => WebGlRenderingContext::REPLACE,
+      Self::Incr => WebGlRenderingContext::INCR,
+      Self::IncrWrap => WebGlRenderingContext::INCR_WRAP,
+      Self::Decr => WebGlRenderingContext::DECR,
+      Self::DecrWrap => WebGlRenderingContext::DECR_WRAP,
+      Self::Invert => WebGlRenderingContext::INVERT,
     }
   }
 }
@@ -416,6 +1287,7 @@ pub enum TexParam {
   LinearMipMapLinear,
   ClampToEdge,
   Repeat,
+  MirroredRepeat,
 }
 
 impl TexParam {
@@ -429,6 +1301,7 @@ impl TexParam {
       Self::LinearMipMapLinear => WebGlRenderingContext::LINEAR_MIPMAP_LINEAR,
       Self::ClampToEdge => WebGlRenderingContext::CLAMP_TO_EDGE,
       Self::Repeat => WebGlRenderingContext::REPEAT,
+      Self::MirroredRepeat => WebGlRenderingContext::MIRRORED_REPEAT,
     }
   }
 }
@@ -450,10 +1323,63 @@ impl TextureFormat {
   }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompressedTextureFormat {
+  RgbS3tcDxt1,
+  RgbaS3tcDxt1,
+  RgbaS3tcDxt3,
+  RgbaS3tcDxt5,
+  Rgb8Etc2,
+  Rgba8Etc2Eac,
+  RgbaAstc4x4,
+}
+
+impl CompressedTextureFormat {
+  pub fn as_u32(&self) -> u32 {
+    match self {
+      Self::RgbS3tcDxt1 => WebglCompressedTextureS3tc::COMPRESSED_RGB_S3TC_DXT1_EXT,
+      Self::RgbaS3tcDxt1 => WebglCompressedTextureS3tc::COMPRESSED_RGBA_S3TC_DXT1_EXT,
+      Self::RgbaS3tcDxt3 => WebglCompressedTextureS3tc::COMPRESSED_RGBA_S3TC_DXT3_EXT,
+      Self::RgbaS3tcDxt5 => WebglCompressedTextureS3tc::COMPRESSED_RGBA_S3TC_DXT5_EXT,
+      Self::Rgb8Etc2 => WebglCompressedTextureEtc::COMPRESSED_RGB8_ETC2,
+      Self::Rgba8Etc2Eac => WebglCompressedTextureEtc::COMPRESSED_RGBA8_ETC2_EAC,
+      Self::RgbaAstc4x4 => WebglCompressedTextureAstc::COMPRESSED_RGBA_ASTC_4X4_KHR,
+    }
+  }
+
+  pub fn get_extension_name(&self) -> &'static str {
+    match self {
+      Self::RgbS3tcDxt1 | Self::RgbaS3tcDxt1 | Self::RgbaS3tcDxt3 | Self::RgbaS3tcDxt5 => {
+        "WEBGL_compressed_texture_s3tc"
+      }
+      Self::Rgb8Etc2 | Self::Rgba8Etc2Eac => "WEBGL_compressed_texture_etc",
+      Self::RgbaAstc4x4 => "WEBGL_compressed_texture_astc",
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum RenderbufferFormat {
+  Rgba8,
+  Depth16,
+  Depth24Stencil8,
+}
+
+impl RenderbufferFormat {
+  pub fn as_u32(&self) -> u32 {
+    match self {
+      Self::Rgba8 => WebGl2RenderingContext::RGBA8,
+      Self::Depth16 => WebGl2RenderingContext::DEPTH_COMPONENT16,
+      Self::Depth24Stencil8 => WebGl2RenderingContext::DEPTH24_STENCIL8,
+    }
+  }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum FramebufferAttachment {
   ColorAttachment0,
   DepthAttachment,
+  DepthStencilAttachment,
 }
 
 impl FramebufferAttachment {
@@ -461,6 +1387,7 @@ impl FramebufferAttachment {
     match self {
       Self::ColorAttachment0 => WebGlRenderingContext::COLOR_ATTACHMENT0,
       Self::DepthAttachment => WebGlRenderingContext::DEPTH_ATTACHMENT,
+      Self::DepthStencilAttachment => WebGlRenderingContext::DEPTH_STENCIL_ATTACHMENT,
     }
   }
 }
@@ -498,6 +1425,23 @@ fn size_u32<T>() -> u32 {
   std::mem::size_of::<T>() as u32
 }
 
+/// Extensions WebGL2 folded into the core API, its contexts don't expose them
+pub const WEBGL2_CORE_EXTENSIONS: &[&str] = &[
+  "ANGLE_instanced_arrays",
+  "OES_element_index_uint",
+  "OES_vertex_array_object",
+  "OES_texture_float",
+  "WEBGL_depth_texture",
+];
+
+pub fn has_extension_or_core(
+  webgl2: bool,
+  name: &str,
+  has_extension: impl Fn(&str) -> bool,
+) -> bool {
+  (webgl2 && WEBGL2_CORE_EXTENSIONS.contains(&name)) || has_extension(name)
+}
+
 pub fn get_memory_buffer() -> JsValue {
   wasm_bindgen::memory()
     .dyn_into::<WebAssembly::Memory>()
@@ -556,3 +1500,30 @@ impl BufferItem for f32 {
     TypedArrayKind::Float32
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn webgl2_has_core_extensions_without_advertising_them() {
+    let none = |_: &str| false;
+
+    assert!(has_extension_or_core(true, "OES_element_index_uint", none));
+    assert!(!has_extension_or_core(
+      false,
+      "OES_element_index_uint",
+      none
+    ));
+    assert!(has_extension_or_core(
+      false,
+      "OES_element_index_uint",
+      |_| true
+    ));
+    assert!(!has_extension_or_core(
+      true,
+      "EXT_texture_filter_anisotropic",
+      none
+    ));
+  }
+}
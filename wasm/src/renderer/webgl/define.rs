@@ -1,3 +1,4 @@
+#[derive(Debug, Clone)]
 pub struct Define {
   pub name: String,
   pub value: Option<String>,
@@ -1,6 +1,11 @@
-use super::context::{FramebufferAttachment, TexParam, TextureFormat, TextureKind, TypedArrayKind};
-use super::renderer::{RenderTarget, Renderer, Sampler};
+use super::context::{
+  FramebufferAttachment, RenderbufferFormat, TexParam, TextureFormat, TextureKind, TypedArrayKind,
+};
+use super::renderer::{
+  DepthAttachment, Multisample, RenderError, RenderResult, RenderTarget, Renderer, Sampler,
+};
 use generational_arena::Index;
+use log::warn;
 
 impl Renderer {
   pub fn bake_render_target(
@@ -8,8 +13,8 @@ impl Renderer {
     width: u32,
     height: u32,
     sampler: Sampler,
-    depth: bool,
-  ) -> Index {
+    depth: DepthAttachment,
+  ) -> Result<Index, RenderError> {
     let fb = self.ctx.create_framebuffer().unwrap();
     let color_image = self.ctx.create_texture().unwrap();
 
@@ -38,7 +43,7 @@ impl Renderer {
       .framebuffer_texture_2d(FramebufferAttachment::ColorAttachment0, Some(&color_image));
 
     let depth_image_option = match depth {
-      true => {
+      DepthAttachment::Texture => {
         let depth_image = self.ctx.create_texture().unwrap();
 
         self
@@ -65,15 +70,52 @@ impl Renderer {
 
         Some(depth_image)
       }
-      false => None,
+      _ => None,
+    };
+
+    let depth_renderbuffer_option = match depth {
+      DepthAttachment::Renderbuffer => {
+        let renderbuffer = self.ctx.create_renderbuffer().unwrap();
+
+        self
+          .ctx
+          .depth_renderbuffer_storage(&renderbuffer, width as i32, height as i32);
+        self
+          .ctx
+          .framebuffer_renderbuffer(FramebufferAttachment::DepthAttachment, Some(&renderbuffer));
+
+        Some(renderbuffer)
+      }
+      DepthAttachment::StencilRenderbuffer => {
+        let renderbuffer = self.ctx.create_renderbuffer().unwrap();
+
+        self
+          .ctx
+          .depth_stencil_renderbuffer_storage(&renderbuffer, width as i32, height as i32);
+        self.ctx.framebuffer_renderbuffer(
+          FramebufferAttachment::DepthStencilAttachment,
+          Some(&renderbuffer),
+        );
+
+        Some(renderbuffer)
+      }
+      _ => None,
     };
 
     let complete = self.ctx.check_framebuffer_complete();
 
+    self.ctx.bind_texture(TextureKind::Texture2d, None);
     self.ctx.bind_framebuffer(None);
 
     if !complete {
-      panic!("framebuffer is incomplete");
+      self.ctx.delete_framebuffer(Some(&fb));
+      self.ctx.delete_texture(Some(&color_image));
+      self.ctx.delete_texture(depth_image_option.as_ref());
+      self
+        .ctx
+        .delete_renderbuffer(depth_renderbuffer_option.as_ref());
+
+      return Err(RenderError::IncompleteFramebuffer);
     }
 
     // fb
@@ -91,16 +133,214 @@ impl Renderer {
           wrap_t: TexParam::ClampToEdge,
           min_filter: TexParam::Nearest,
           mag_filter: TexParam::Nearest,
+          mipmaps: false,
+          anisotropy: 1.0,
         },
       ))
     } else {
       None
     };
 
-    self.insert_render_target(RenderTarget {
+    // depth renderbuffer
+    let depth_renderbuffer_handle =
+      depth_renderbuffer_option.map(|renderbuffer| self.insert_renderbuffer(renderbuffer));
+
+    Ok(self.insert_render_target(RenderTarget {
       fb: fb_handle,
+      width,
+      height,
       color_texture: color_texture_handle,
       depth_texture: depth_texture_handle,
-    })
+      depth_renderbuffer: depth_renderbuffer_handle,
+      multisample: None,
+    }))
+  }
+
+  /// Needs WebGL2, falls back to a plain target otherwise. Multisampled depth is always a
+  /// renderbuffer, a depth texture request only keeps it on the fallback path.
+  pub fn bake_multisampled_render_target(
+    &mut self,
+    width: u32,
+    height: u32,
+    samples: i32,
+    sampler: Sampler,
+    depth: DepthAttachment,
+  ) -> Result<Index, RenderError> {
+    let samples = samples.min(self.ctx.get_max_samples());
+
+    if samples < 2 {
+      warn!("multisampling is unavailable, baking a single sampled render target");
+
+      return self.bake_render_target(width, height, sampler, depth);
+    }
+
+    let handle = self.bake_render_target(width, height, sampler, DepthAttachment::None)?;
+
+    let fb = self.ctx.create_framebuffer().unwrap();
+    let color_renderbuffer = self.ctx.create_renderbuffer().unwrap();
+    let depth_format = match depth {
+      DepthAttachment::None => None,
+      DepthAttachment::Texture | DepthAttachment::Renderbuffer => Some((
+        RenderbufferFormat::Depth16,
+        FramebufferAttachment::DepthAttachment,
+      )),
+      DepthAttachment::StencilRenderbuffer => Some((
+        RenderbufferFormat::Depth24Stencil8,
+        FramebufferAttachment::DepthStencilAttachment,
+      )),
+    };
+
+    self.ctx.bind_framebuffer(Some(&fb));
+
+    self.ctx.renderbuffer_storage_multisample(
+      &color_renderbuffer,
+      samples,
+      RenderbufferFormat::Rgba8,
+      width as i32,
+      height as i32,
+    );
+    self.ctx.framebuffer_renderbuffer(
+      FramebufferAttachment::ColorAttachment0,
+      Some(&color_renderbuffer),
+    );
+
+    let depth_renderbuffer_option = depth_format.map(|(format, attachment)| {
+      let renderbuffer = self.ctx.create_renderbuffer().unwrap();
+
+      self.ctx.renderbuffer_storage_multisample(
+        &renderbuffer,
+        samples,
+        format,
+        width as i32,
+        height as i32,
+      );
+      self
+        .ctx
+        .framebuffer_renderbuffer(attachment, Some(&renderbuffer));
+
+      renderbuffer
+    });
+
+    let complete = self.ctx.check_framebuffer_complete();
+
+    self.ctx.bind_framebuffer(None);
+
+    if !complete {
+      self.ctx.delete_framebuffer(Some(&fb));
+      self.ctx.delete_renderbuffer(Some(&color_renderbuffer));
+      self
+        .ctx
+        .delete_renderbuffer(depth_renderbuffer_option.as_ref());
+      self.remove_render_target(handle);
+
+      return Err(RenderError::IncompleteFramebuffer);
+    }
+
+    let multisample = Multisample {
+      fb: self.insert_framebuffer(fb),
+      samples,
+      color_renderbuffer: self.insert_renderbuffer(color_renderbuffer),
+      depth_renderbuffer: depth_renderbuffer_option
+        .map(|renderbuffer| self.insert_renderbuffer(renderbuffer)),
+    };
+
+    self.targets.get_mut(handle).unwrap().multisample = Some(multisample);
+
+    Ok(handle)
+  }
+
+  /// Copies the rendered color into the destination color texture, a multisampled target
+  /// resolves into itself when both handles are the same
+  pub fn resolve_render_target(&self, source: Index, destination: Index) -> RenderResult {
+    let source_target = self
+      .targets
+      .get(source)
+      .ok_or(RenderError::MissingTarget(source))?;
+    let destination_target = self
+      .targets
+      .get(destination)
+      .ok_or(RenderError::MissingTarget(destination))?;
+
+    let read_handle = source_target.get_draw_framebuffer();
+
+    if read_handle == destination_target.fb {
+      return Ok(());
+    }
+
+    let read = self
+      .framebuffers
+      .get(read_handle)
+      .ok_or(RenderError::MissingTarget(source))?;
+    let draw = self
+      .framebuffers
+      .get(destination_target.fb)
+      .ok_or(RenderError::MissingTarget(destination))?;
+    let width = source_target.width.min(destination_target.width) as i32;
+    let height = source_target.height.min(destination_target.height) as i32;
+
+    if self
+      .ctx
+      .blit_framebuffer(read, draw, width, height)
+      .is_some()
+    {
+      return Ok(());
+    }
+
+    // WebGL1 has no blitting, but then the source is never multisampled
+    let texture = self
+      .textures
+      .get(destination_target.color_texture)
+      .ok_or(RenderError::MissingTarget(destination))?;
+    let image = self
+      .images
+      .get(texture.source)
+      .ok_or(RenderError::MissingTarget(destination))?;
+
+    self.ctx.bind_framebuffer(Some(read));
+    self.ctx.bind_texture(TextureKind::Texture2d, Some(image));
+    self.ctx.copy_tex_sub_image_2d(width, height);
+    self.ctx.bind_texture(TextureKind::Texture2d, None);
+    self.ctx.bind_framebuffer(None);
+
+    Ok(())
+  }
+
+  pub fn remove_render_target(&mut self, handle: Index) -> Option<()> {
+    let target = self.targets.remove(handle)?;
+
+    if let Some(fb) = self.framebuffers.remove(target.fb) {
+      self.ctx.delete_framebuffer(Some(&fb));
+    }
+
+    self.remove_texture(target.color_texture);
+
+    if let Some(depth_texture) = target.depth_texture {
+      self.remove_texture(depth_texture);
+    }
+
+    if let Some(renderbuffer) = target
+      .depth_renderbuffer
+      .and_then(|handle| self.renderbuffers.remove(handle))
+    {
+      self.ctx.delete_renderbuffer(Some(&renderbuffer));
+    }
+
+    if let Some(multisample) = target.multisample {
+      if let Some(fb) = self.framebuffers.remove(multisample.fb) {
+        self.ctx.delete_framebuffer(Some(&fb));
+      }
+
+      let renderbuffers = Some(multisample.color_renderbuffer)
+        .into_iter()
+        .chain(multisample.depth_renderbuffer);
+
+      for handle in renderbuffers {
+        if let Some(renderbuffer) = self.renderbuffers.remove(handle) {
+          self.ctx.delete_renderbuffer(Some(&renderbuffer));
+        }
+      }
+    }
+
+    Some(())
   }
 }
@@ -0,0 +1,62 @@
+use na::{Matrix4, Vector3, Vector4};
+
+use super::renderer::{Aabb, BoundingSphere};
+
+#[derive(Debug, Clone)]
+pub struct Frustum {
+  pub planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+  pub fn from_matrix(m: &Matrix4<f32>) -> Self {
+    let row = |i: usize| -> Vector4<f32> { m.row(i).transpose() };
+    let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+
+    let mut planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r3 + r2, r3 - r2];
+
+    for plane in planes.iter_mut() {
+      let length = plane.xyz().norm();
+
+      if length > 0.0 {
+        *plane /= length;
+      }
+    }
+
+    Frustum { planes }
+  }
+
+  pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+    self
+      .planes
+      .iter()
+      .all(|plane| plane.xyz().dot(&sphere.center) + plane.w >= -sphere.radius)
+  }
+
+  pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+    for plane in &self.planes {
+      let positive = Vector3::new(
+        if plane.x >= 0.0 {
+          aabb.max.x
+        } else {
+          aabb.min.x
+        },
+        if plane.y >= 0.0 {
+          aabb.max.y
+        } else {
+          aabb.min.y
+        },
+        if plane.z >= 0.0 {
+          aabb.max.z
+        } else {
+          aabb.min.z
+        },
+      );
+
+      if plane.xyz().dot(&positive) + plane.w < 0.0 {
+        return false;
+      }
+    }
+
+    true
+  }
+}
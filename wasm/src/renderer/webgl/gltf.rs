@@ -1,59 +1,315 @@
+use anyhow::{anyhow, bail, Result};
 use generational_arena::Index;
-use gltf::accessor::DataType;
-use gltf::mesh::Semantic;
+use gltf::accessor::sparse::{IndexType, Sparse};
+use gltf::accessor::{Accessor as AccessorDef, DataType};
+use gltf::animation::{Interpolation as GltfInterpolation, Property};
+use gltf::buffer::{Source as BufferSource, Target, View};
+use gltf::camera::Projection;
+use gltf::image::{Image as ImageDef, Source};
+use gltf::material::AlphaMode as GltfAlphaMode;
+use gltf::mesh::{Mode, Semantic};
 use gltf::scene::Transform;
-use gltf::Gltf;
-use na::{Matrix4, Quaternion, UnitQuaternion, Vector3, Vector4};
-use std::collections::HashMap;
+use gltf::texture::{
+  MagFilter, MinFilter, Sampler as SamplerDef, TextureTransform as TextureTransformDef,
+  WrappingMode,
+};
+use gltf::{Glb, Gltf};
+use log::warn;
+use na::{
+  Matrix3, Matrix4, Orthographic3, Perspective3, Quaternion, UnitQuaternion, Vector2, Vector3,
+  Vector4,
+};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 
-use crate::scene::node::{compose_matrix, Node};
+use crate::scene::animation::{Animation, Channel, ChannelValues, Interpolation};
+use crate::scene::node::Node;
 
-use super::context::{BufferTarget, BufferUsage, TypedArrayKind};
-use super::material::PbrMaterial;
-use super::renderer::{Accessor, Geometry, Mesh, Primitive, Renderer};
+use super::context::{
+  BufferTarget, BufferUsage, DrawMode, TexParam, TextureFormat, TypedArrayKind,
+};
+use super::material::{AlphaMode, Material, PbrMaterial, UnlitMaterial};
+use super::renderer::{
+  Aabb, Accessor, BoundingSphere, Camera, Geometry, Mesh, Primitive, Renderer, Sampler, Skin,
+  Texture,
+};
 use super::shader::{AttributeName, AttributeOptions};
 
 pub type IndexMap = HashMap<usize, Index>;
 
+/// Baked glTF materials by material index and the variant a primitive needs
+pub type MaterialIndex = HashMap<(usize, MaterialVariant), Index>;
+
+/// Shader features of a material that depend on the attributes of the primitive drawing it,
+/// a material shared by primitives with different attributes gets one bake per variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MaterialVariant {
+  pub vertex_color: bool,
+  pub skinning: bool,
+}
+
+impl MaterialVariant {
+  pub fn from_primitive(primitive_def: &gltf::Primitive) -> Self {
+    MaterialVariant {
+      vertex_color: primitive_def.get(&Semantic::Colors(0)).is_some(),
+      skinning: is_skinned_primitive(primitive_def),
+    }
+  }
+}
+
+/// Extensions the parsed document carries, applied on every load path
+pub const SUPPORTED_EXTENSIONS: &[&str] = &["KHR_materials_unlit"];
+
+/// Extensions the gltf crate drops, applied only when GltfExtensions was read from the JSON
+pub const JSON_EXTENSIONS: &[&str] = &["KHR_materials_emissive_strength", "KHR_texture_transform"];
+
+/// Handles created by one import, vectors follow the glTF order
+#[derive(Debug, Clone, Default)]
+pub struct GltfImportResult {
+  pub meshes: Vec<Index>,
+  pub nodes: Vec<Index>,
+  pub cameras: Vec<Index>,
+  pub animations: Vec<Index>,
+  pub scenes: Vec<Index>,
+  /// Root node of the default scene, or of the first one
+  pub root: Option<Index>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextureSlot {
+  BaseColor,
+  MetallicRoughness,
+  Normal,
+  Occlusion,
+  Emissive,
+}
+
+impl TextureSlot {
+  /// Slots the gltf crate parses without their extensions, the others come with
+  /// texture_transform
+  const JSON_ONLY: [TextureSlot; 2] = [TextureSlot::Normal, TextureSlot::Occlusion];
+
+  fn get_json_info<'a>(&self, material: &'a Value) -> &'a Value {
+    match self {
+      TextureSlot::BaseColor => &material["pbrMetallicRoughness"]["baseColorTexture"],
+      TextureSlot::MetallicRoughness => {
+        &material["pbrMetallicRoughness"]["metallicRoughnessTexture"]
+      }
+      TextureSlot::Normal => &material["normalTexture"],
+      TextureSlot::Occlusion => &material["occlusionTexture"],
+      TextureSlot::Emissive => &material["emissiveTexture"],
+    }
+  }
+}
+
+/// KHR_texture_transform of one texture reference
+#[derive(Debug, Clone, Copy)]
+pub struct TextureTransform {
+  pub offset: Vector2<f32>,
+  /// Radians, counter-clockwise in UV space
+  pub rotation: f32,
+  pub scale: Vector2<f32>,
+  /// Replaces the texCoord of the texture reference
+  pub tex_coord: Option<u32>,
+}
+
+impl TextureTransform {
+  pub fn from_json(json: &Value) -> Self {
+    let get_vector = |value: &Value, default: f32| {
+      let component = |i: usize| value[i].as_f64().map_or(default, |v| v as f32);
+
+      Vector2::new(component(0), component(1))
+    };
+
+    TextureTransform {
+      offset: get_vector(&json["offset"], 0.0),
+      rotation: json["rotation"].as_f64().unwrap_or(0.0) as f32,
+      scale: get_vector(&json["scale"], 1.0),
+      tex_coord: json["texCoord"].as_u64().map(|t| t as u32),
+    }
+  }
+
+  pub fn from_def(transform_def: &TextureTransformDef) -> Self {
+    TextureTransform {
+      offset: Vector2::from(transform_def.offset()),
+      rotation: transform_def.rotation(),
+      scale: Vector2::from(transform_def.scale()),
+      tex_coord: transform_def.tex_coord(),
+    }
+  }
+
+  /// Translation * rotation * scale, as the extension defines it
+  pub fn get_matrix(&self) -> Matrix3<f32> {
+    let (sin, cos) = self.rotation.sin_cos();
+    let translation = Matrix3::new(
+      1.0,
+      0.0,
+      self.offset.x,
+      0.0,
+      1.0,
+      self.offset.y,
+      0.0,
+      0.0,
+      1.0,
+    );
+    let rotation = Matrix3::new(cos, sin, 0.0, -sin, cos, 0.0, 0.0, 0.0, 1.0);
+    let scale = Matrix3::new(
+      self.scale.x,
+      0.0,
+      0.0,
+      0.0,
+      self.scale.y,
+      0.0,
+      0.0,
+      0.0,
+      1.0,
+    );
+
+    translation * rotation * scale
+  }
+}
+
+/// Material extensions the gltf crate drops while parsing, read from the raw JSON
+#[derive(Debug, Clone, Default)]
+pub struct GltfExtensions {
+  /// KHR_materials_emissive_strength by material index
+  pub emissive_strengths: HashMap<usize, f32>,
+  /// KHR_texture_transform of normal and occlusion textures by material index, the other
+  /// slots keep theirs in the parsed document
+  pub texture_transforms: HashMap<(usize, TextureSlot), TextureTransform>,
+  read_from_json: bool,
+}
+
+impl GltfExtensions {
+  /// Accepts the same .gltf or .glb bytes that were given to Gltf::from_slice
+  pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+    Ok(Self::from_json(&read_gltf_json(bytes)?))
+  }
+
+  pub fn from_json(root: &Value) -> Self {
+    let mut emissive_strengths = HashMap::new();
+    let mut texture_transforms = HashMap::new();
+
+    if let Some(materials) = root["materials"].as_array() {
+      for (index, material) in materials.iter().enumerate() {
+        let strength =
+          &material["extensions"]["KHR_materials_emissive_strength"]["emissiveStrength"];
+
+        if let Some(strength) = strength.as_f64() {
+          emissive_strengths.insert(index, strength as f32);
+        }
+
+        for slot in TextureSlot::JSON_ONLY.iter() {
+          let transform = &slot.get_json_info(material)["extensions"]["KHR_texture_transform"];
+
+          if transform.is_object() {
+            texture_transforms.insert((index, *slot), TextureTransform::from_json(transform));
+          }
+        }
+      }
+    }
+
+    GltfExtensions {
+      emissive_strengths,
+      texture_transforms,
+      read_from_json: true,
+    }
+  }
+
+  /// Extensions a load with these can apply, the default only has the document ones
+  pub fn get_supported(&self) -> Vec<&'static str> {
+    let mut supported = SUPPORTED_EXTENSIONS.to_vec();
+
+    if self.read_from_json {
+      supported.extend_from_slice(JSON_EXTENSIONS);
+    }
+
+    supported
+  }
+}
+
 impl Renderer {
-  pub fn create_gltf_accessors(&mut self, gltf: &Gltf) -> IndexMap {
+  pub fn create_gltf_accessors(&mut self, gltf: &Gltf, buffers: &[&[u8]]) -> Result<IndexMap> {
     let mut buffer_index = IndexMap::new();
     let mut accessor_index = IndexMap::new();
+    let index_accessors = get_index_accessors(gltf);
+    let normalized_accessors = get_normalized_accessors(gltf);
 
     for accessor_def in gltf.accessors() {
-      if accessor_def.sparse().is_some() {
-        todo!("sparse");
-      }
+      let narrow_index_buffer = accessor_def.view().is_some()
+        && accessor_def.data_type() == DataType::U32
+        && index_accessors.contains(&accessor_def.index())
+        && !self.ctx.has_element_index_uint();
+
+      let accessor_handle = if let Some(sparse_def) = accessor_def.sparse() {
+        let data = read_sparse_accessor(buffers, &accessor_def, &sparse_def)?;
+        let buffer_handle =
+          self.insert_buffer(BufferTarget::ArrayBuffer, BufferUsage::StaticDraw, &data);
 
-      let accessor_handle = if let Some(view_def) = accessor_def.view() {
+        self.accessors.insert(Accessor {
+          buffer: buffer_handle,
+          count: accessor_def.count() as i32,
+          options: AttributeOptions {
+            component_type: get_component_type(accessor_def.data_type()),
+            item_size: accessor_def.dimensions().multiplicity() as i32,
+            normalized: accessor_def.normalized()
+              || normalized_accessors.contains(&accessor_def.index()),
+            stride: 0,
+            offset: 0,
+          },
+          data: if self.retain_vertex_data {
+            Some(data)
+          } else {
+            None
+          },
+        })
+      } else if narrow_index_buffer {
+        let data = narrow_indices(&read_accessor_data(buffers, &accessor_def)?)?;
+        let buffer_handle = self.insert_buffer(
+          BufferTarget::ElementArrayBuffer,
+          BufferUsage::StaticDraw,
+          &data,
+        );
+
+        self.accessors.insert(Accessor {
+          buffer: buffer_handle,
+          count: accessor_def.count() as i32,
+          options: AttributeOptions {
+            component_type: TypedArrayKind::Uint16,
+            item_size: 1,
+            normalized: false,
+            stride: 0,
+            offset: 0,
+          },
+          data: Some(data.iter().flat_map(|i| i.to_le_bytes()).collect()),
+        })
+      } else if let Some(view_def) = accessor_def.view() {
         let view_index = view_def.index();
-        let blob = gltf.blob.as_ref().unwrap();
+        let view_data = get_view_data(buffers, &view_def)?;
+        let is_index_buffer = index_accessors.contains(&accessor_def.index());
+
+        check_accessor_bounds(&accessor_def, &view_def)?;
 
+        // views are uploaded whole, so interleaved accessors share one buffer and keep their
+        // view-relative offset and the view stride as vertex attribute parameters
         let buffer_handle = if let Some(handle) = buffer_index.get(&view_index) {
           *handle
         } else {
-          let offset = view_def.offset();
-          let length = view_def.length();
-
-          let data = &blob[offset..(offset + length)];
-          let acc_idx = accessor_def.index();
-          let is_index_buffer = gltf
-            .meshes()
-            .find(|m| {
-              m.primitives()
-                .find(|p| match p.indices() {
-                  Some(acc) => acc.index() == acc_idx,
-                  None => false,
-                })
-                .is_some()
-            })
-            .is_some();
-          let buffer_target = if is_index_buffer {
-            BufferTarget::ElementArrayBuffer
+          let is_element_buffer = match view_def.target() {
+            Some(Target::ElementArrayBuffer) => true,
+            Some(Target::ArrayBuffer) => false,
+            None => is_index_buffer,
+          };
+          let handle = if is_element_buffer {
+            self.insert_buffer(
+              BufferTarget::ElementArrayBuffer,
+              BufferUsage::StaticDraw,
+              view_data,
+            )
           } else {
-            BufferTarget::ArrayBuffer
+            self.insert_interleaved_buffer(BufferUsage::StaticDraw, view_data)
           };
-          let handle = self.insert_buffer(buffer_target, BufferUsage::StaticDraw, data);
           buffer_index.insert(view_index, handle);
 
           handle
@@ -62,81 +318,336 @@ impl Renderer {
         self.accessors.insert(Accessor {
           buffer: buffer_handle,
           count: accessor_def.count() as i32,
-          options: AttributeOptions {
-            component_type: match accessor_def.data_type() {
-              DataType::U8 => TypedArrayKind::Uint8,
-              DataType::I8 => TypedArrayKind::Int8,
-              DataType::I16 => TypedArrayKind::Int16,
-              DataType::U16 => TypedArrayKind::Uint16,
-              DataType::U32 => TypedArrayKind::Uint32,
-              DataType::F32 => TypedArrayKind::Float32,
-            },
-            item_size: accessor_def.dimensions().multiplicity() as i32,
-            normalized: accessor_def.normalized(),
-            stride: view_def.stride().unwrap_or(0) as i32,
-            offset: accessor_def.offset() as i32,
+          options: get_accessor_options(
+            &accessor_def,
+            normalized_accessors.contains(&accessor_def.index()),
+          ),
+          data: if is_index_buffer {
+            let offset = accessor_def.offset();
+            let length = accessor_def.count() * accessor_def.size();
+
+            Some(view_data[offset..(offset + length)].to_vec())
+          } else if self.retain_vertex_data {
+            Some(read_dense_accessor(buffers, &accessor_def)?)
+          } else {
+            None
           },
         })
       } else {
+        // without a view and sparse values every element is zero, so upload exactly that
+        let data = vec![0u8; accessor_def.count() * accessor_def.size()];
+        let is_index_buffer = index_accessors.contains(&accessor_def.index());
+        let target = if is_index_buffer {
+          BufferTarget::ElementArrayBuffer
+        } else {
+          BufferTarget::ArrayBuffer
+        };
+        let buffer_handle = self.insert_buffer(target, BufferUsage::StaticDraw, &data);
+
         self.accessors.insert(Accessor {
-          buffer: Index::from_raw_parts(0, 0),
-          count: 0,
+          buffer: buffer_handle,
+          count: accessor_def.count() as i32,
           options: AttributeOptions {
-            component_type: TypedArrayKind::Float32,
-            item_size: 3,
-            normalized: false,
+            component_type: get_component_type(accessor_def.data_type()),
+            item_size: accessor_def.dimensions().multiplicity() as i32,
+            normalized: accessor_def.normalized()
+              || normalized_accessors.contains(&accessor_def.index()),
             stride: 0,
             offset: 0,
           },
+          data: if is_index_buffer || self.retain_vertex_data {
+            Some(data)
+          } else {
+            None
+          },
         })
       };
 
       accessor_index.insert(accessor_def.index(), accessor_handle);
     }
 
-    accessor_index
+    Ok(accessor_index)
   }
 
-  pub fn create_gltf_materials(&mut self, gltf: &Gltf) -> IndexMap {
-    let mut material_index = IndexMap::new();
+  pub fn create_gltf_image(
+    &mut self,
+    buffers: &[&[u8]],
+    image_def: &ImageDef,
+  ) -> Result<(Index, u32, u32)> {
+    let (view_def, mime_type) = match image_def.source() {
+      Source::View { view, mime_type } => (view, mime_type),
+      Source::Uri { uri, .. } => bail!("external image {} is not supported", uri),
+    };
+
+    let view_data = get_view_data(buffers, &view_def)?;
+
+    let format = match mime_type {
+      "image/png" => image::ImageFormat::Png,
+      "image/jpeg" => image::ImageFormat::Jpeg,
+      _ => bail!("unsupported image mime type {}", mime_type),
+    };
+
+    let pixels = image::load_from_memory_with_format(view_data, format)?.to_rgba8();
+
+    let (width, height) = pixels.dimensions();
+    let handle = self.bake_2d_image_data(TextureFormat::RGBA, width, height, pixels.as_raw(), true);
+
+    Ok((handle, width, height))
+  }
+
+  pub fn create_gltf_textures(&mut self, gltf: &Gltf, buffers: &[&[u8]]) -> Result<IndexMap> {
+    let mut image_index: HashMap<usize, (Index, u32, u32)> = HashMap::new();
+    let mut texture_index = IndexMap::new();
+
+    for texture_def in gltf.textures() {
+      let image_def = texture_def.source();
+
+      let (image_handle, width, height) = match image_index.get(&image_def.index()) {
+        Some(image) => *image,
+        None => {
+          let image = self.create_gltf_image(buffers, &image_def)?;
+          image_index.insert(image_def.index(), image);
+
+          image
+        }
+      };
+
+      let sampler = get_gltf_sampler(&texture_def.sampler()).fit_size(width, height);
+      let sampler_handle = self.insert_sampler(sampler);
+
+      let texture_handle = self.insert_texture(Texture {
+        source: image_handle,
+        sampler: sampler_handle,
+      });
+
+      texture_index.insert(texture_def.index(), texture_handle);
+    }
+
+    Ok(texture_index)
+  }
+
+  pub fn create_gltf_materials(
+    &mut self,
+    gltf: &Gltf,
+    texture_index: &IndexMap,
+    extensions: &GltfExtensions,
+  ) -> Result<MaterialIndex> {
+    let mut material_index = MaterialIndex::new();
+    let material_variants = get_material_variants(gltf);
 
     for material_def in gltf.materials() {
-      let material_handle = self.bake_material(
-        PbrMaterial::new()
-          .set_color(Vector3::new(1.0, 0.0, 0.0))
-          .boxed(),
-      );
+      let pbr_def = material_def.pbr_metallic_roughness();
+      let [r, g, b, a] = pbr_def.base_color_factor();
+      let index = material_def.index().unwrap();
+      // materials no primitive draws with aren't baked
+      let variants = match material_variants.get(&index) {
+        Some(variants) => variants,
+        None => continue,
+      };
+      let emissive_strength = extensions
+        .emissive_strengths
+        .get(&index)
+        .copied()
+        .unwrap_or(1.0);
+
+      let mut transforms: HashMap<TextureSlot, TextureTransform> = TextureSlot::JSON_ONLY
+        .iter()
+        .filter_map(|slot| {
+          let transform = extensions.texture_transforms.get(&(index, *slot));
+
+          transform.map(|transform| (*slot, *transform))
+        })
+        .collect();
+      let info_defs = vec![
+        (TextureSlot::BaseColor, pbr_def.base_color_texture()),
+        (
+          TextureSlot::MetallicRoughness,
+          pbr_def.metallic_roughness_texture(),
+        ),
+        (TextureSlot::Emissive, material_def.emissive_texture()),
+      ];
+
+      for (slot, info_def) in info_defs {
+        if let Some(transform_def) = info_def.and_then(|info_def| info_def.texture_transform()) {
+          transforms.insert(slot, TextureTransform::from_def(&transform_def));
+        }
+      }
+
+      let get_transform = |slot| transforms.get(&slot);
+      let get_tex_coord = |slot, tex_coord| {
+        get_transform(slot)
+          .and_then(|t: &TextureTransform| t.tex_coord)
+          .unwrap_or(tex_coord)
+      };
+      let get_uv_matrix =
+        |slot| get_transform(slot).map_or_else(Matrix3::identity, TextureTransform::get_matrix);
+
+      let alpha_mode = match material_def.alpha_mode() {
+        GltfAlphaMode::Opaque => AlphaMode::Opaque,
+        GltfAlphaMode::Mask => AlphaMode::Mask(material_def.alpha_cutoff().unwrap_or(0.5)),
+        GltfAlphaMode::Blend => AlphaMode::Blend,
+      };
+
+      let (color_map, color_map_tex_coord) = get_texture_ref(
+        texture_index,
+        "base color",
+        pbr_def.base_color_texture().map(|info_def| {
+          let tex_coord = get_tex_coord(TextureSlot::BaseColor, info_def.tex_coord());
+
+          (info_def.texture().index(), tex_coord)
+        }),
+      )?;
+
+      // KHR_materials_unlit keeps only the base color
+      if material_def.unlit() {
+        for variant in variants {
+          let material: Box<dyn Material> = UnlitMaterial::new()
+            .set_color(Vector3::new(r, g, b))
+            .set_color_map(color_map)
+            .set_color_map_tex_coord(color_map_tex_coord)
+            .set_color_map_transform(get_uv_matrix(TextureSlot::BaseColor))
+            .set_vertex_color(variant.vertex_color)
+            .set_skinning(variant.skinning)
+            .set_double_sided(material_def.double_sided())
+            .set_opacity(a)
+            .set_alpha_mode(alpha_mode)
+            .boxed();
+
+          material_index.insert((index, *variant), self.bake_material(material));
+        }
+
+        continue;
+      }
+
+      let (metallic_roughness_map, metallic_roughness_map_tex_coord) = get_texture_ref(
+        texture_index,
+        "metallic roughness",
+        pbr_def.metallic_roughness_texture().map(|info_def| {
+          let tex_coord = get_tex_coord(TextureSlot::MetallicRoughness, info_def.tex_coord());
 
-      material_index.insert(material_def.index().unwrap(), material_handle);
+          (info_def.texture().index(), tex_coord)
+        }),
+      )?;
+
+      let normal_def = material_def.normal_texture();
+      let normal_scale = normal_def.as_ref().map(|n| n.scale()).unwrap_or(1.0);
+      let (normal_map, normal_map_tex_coord) = get_texture_ref(
+        texture_index,
+        "normal",
+        normal_def.map(|n| {
+          let tex_coord = get_tex_coord(TextureSlot::Normal, n.tex_coord());
+
+          (n.texture().index(), tex_coord)
+        }),
+      )?;
+
+      let (emissive_map, emissive_map_tex_coord) = get_texture_ref(
+        texture_index,
+        "emissive",
+        material_def.emissive_texture().map(|info_def| {
+          let tex_coord = get_tex_coord(TextureSlot::Emissive, info_def.tex_coord());
+
+          (info_def.texture().index(), tex_coord)
+        }),
+      )?;
+
+      let occlusion_def = material_def.occlusion_texture();
+      let occlusion_strength = occlusion_def.as_ref().map(|o| o.strength()).unwrap_or(1.0);
+      let (occlusion_map, occlusion_map_tex_coord) = get_texture_ref(
+        texture_index,
+        "occlusion",
+        occlusion_def.map(|o| {
+          let tex_coord = get_tex_coord(TextureSlot::Occlusion, o.tex_coord());
+
+          (o.texture().index(), tex_coord)
+        }),
+      )?;
+
+      for variant in variants {
+        let material_handle = self.bake_material(
+          PbrMaterial::new()
+            .set_color(Vector3::new(r, g, b))
+            .set_metallic(pbr_def.metallic_factor())
+            .set_roughness(pbr_def.roughness_factor())
+            .set_color_map(color_map)
+            .set_color_map_tex_coord(color_map_tex_coord)
+            .set_color_map_transform(get_uv_matrix(TextureSlot::BaseColor))
+            .set_normal_map(normal_map)
+            .set_normal_map_tex_coord(normal_map_tex_coord)
+            .set_normal_map_transform(get_uv_matrix(TextureSlot::Normal))
+            .set_normal_scale(normal_scale)
+            .set_emissive(Vector3::from(material_def.emissive_factor()))
+            .set_emissive_strength(emissive_strength)
+            .set_emissive_map(emissive_map)
+            .set_emissive_map_tex_coord(emissive_map_tex_coord)
+            .set_emissive_map_transform(get_uv_matrix(TextureSlot::Emissive))
+            .set_metallic_roughness_map(metallic_roughness_map)
+            .set_metallic_roughness_map_tex_coord(metallic_roughness_map_tex_coord)
+            .set_metallic_roughness_map_transform(get_uv_matrix(TextureSlot::MetallicRoughness))
+            .set_occlusion_map(occlusion_map)
+            .set_occlusion_map_tex_coord(occlusion_map_tex_coord)
+            .set_occlusion_map_transform(get_uv_matrix(TextureSlot::Occlusion))
+            .set_occlusion_strength(occlusion_strength)
+            .set_vertex_color(variant.vertex_color)
+            .set_skinning(variant.skinning)
+            .set_double_sided(material_def.double_sided())
+            .set_opacity(a)
+            .set_alpha_mode(alpha_mode)
+            .boxed(),
+        );
+
+        material_index.insert((index, *variant), material_handle);
+      }
     }
 
-    material_index
+    Ok(material_index)
+  }
+
+  pub fn create_gltf_default_material(&mut self, vertex_color: bool, skinning: bool) -> Index {
+    self.bake_material(
+      PbrMaterial::new()
+        .set_color(Vector3::new(1.0, 1.0, 1.0))
+        .set_vertex_color(vertex_color)
+        .set_skinning(skinning)
+        .boxed(),
+    )
   }
 
   pub fn create_gltf_meshes(
     &mut self,
     gltf: &Gltf,
     accessor_index: &IndexMap,
-    materials_index: &IndexMap,
+    materials_index: &MaterialIndex,
   ) -> IndexMap {
     let mut mesh_index = IndexMap::new();
+    let mut default_materials: HashMap<MaterialVariant, Index> = HashMap::new();
 
     for mesh_def in gltf.meshes() {
       let mut primitives: Vec<Primitive> = vec![];
 
       for primitive_def in mesh_def.primitives() {
         let mut attributes: HashMap<AttributeName, Index> = HashMap::new();
+        let mut bounds = None;
 
         for (semantic_def, accessor_def) in primitive_def.attributes() {
           let attr_name = match semantic_def {
             Semantic::Positions => AttributeName::Position,
             Semantic::Normals => AttributeName::Normal,
+            Semantic::Tangents => AttributeName::Tangent,
+            Semantic::Colors(0) => AttributeName::Color,
+            Semantic::Joints(0) => AttributeName::Joints,
+            Semantic::Weights(0) => AttributeName::Weights,
             Semantic::TexCoords(value) => match value {
               0 => AttributeName::Uv,
+              1 => AttributeName::Uv2,
               _ => AttributeName::Custom(semantic_def.to_string()),
             },
             _ => AttributeName::Custom(semantic_def.to_string()),
           };
+          if attr_name == AttributeName::Position {
+            bounds = get_accessor_bounds(&accessor_def);
+          }
+
           attributes.insert(
             attr_name,
             *accessor_index.get(&accessor_def.index()).unwrap(),
@@ -151,17 +662,22 @@ impl Renderer {
           indices = None;
         }
 
-        let material;
-
-        if let Some(index) = primitive_def.material().index() {
-          material = materials_index.get(&index).cloned();
-        } else {
-          material = None;
-        }
+        let variant = MaterialVariant::from_primitive(&primitive_def);
+        let material = match primitive_def.material().index() {
+          Some(index) => materials_index.get(&(index, variant)).cloned(),
+          None => Some(*default_materials.entry(variant).or_insert_with(|| {
+            self.create_gltf_default_material(variant.vertex_color, variant.skinning)
+          })),
+        };
 
-        let geometry = self.insert_geometry(Geometry {
+        let geometry = self.intern_geometry(Geometry {
           attributes,
           indices,
+          bounding_sphere: bounds.as_ref().map(BoundingSphere::from_aabb),
+          bounds,
+          instances: None,
+          wireframe: None,
+          draw_mode: Some(get_draw_mode(primitive_def.mode())),
         });
 
         primitives.push(Primitive { geometry, material });
@@ -192,21 +708,20 @@ impl Renderer {
             rotation,
             scale,
           } => {
-            node.matrix_local = compose_matrix(
-              Some(Vector3::from_vec(translation.to_vec())),
-              Some(UnitQuaternion::from_quaternion(Quaternion::from(
+            node
+              .set_translation(Vector3::from_vec(translation.to_vec()))
+              .set_rotation(UnitQuaternion::from_quaternion(Quaternion::from(
                 Vector4::from_vec(rotation.to_vec()),
-              ))),
-              Some(Vector3::from_vec(scale.to_vec())),
-            )
+              )))
+              .set_scale(Vector3::from_vec(scale.to_vec()));
           }
           Transform::Matrix { matrix: m } => {
-            node.matrix_local = Matrix4::new(
-              m[0][0], m[0][1], m[0][2], m[0][3], //
-              m[1][0], m[1][1], m[1][2], m[1][3], //
-              m[2][0], m[2][1], m[2][2], m[2][3], //
-              m[3][0], m[3][1], m[3][2], m[3][3], //
-            );
+            node.set_matrix_local(Matrix4::new(
+              m[0][0], m[1][0], m[2][0], m[3][0], //
+              m[0][1], m[1][1], m[2][1], m[3][1], //
+              m[0][2], m[1][2], m[2][2], m[3][2], //
+              m[0][3], m[1][3], m[2][3], m[3][3], //
+            ));
           }
         };
 
@@ -231,13 +746,161 @@ impl Renderer {
         let child_handle = node_index.get(&child_def.index()).unwrap();
         let parent_handle = node_index.get(&node_def.index()).unwrap();
 
-        self.scene.set_parent(*child_handle, *parent_handle);
+        self.scene.set_parent(*child_handle, Some(*parent_handle));
       }
     }
 
     node_index
   }
 
+  pub fn create_gltf_skins(
+    &mut self,
+    gltf: &Gltf,
+    buffers: &[&[u8]],
+    node_index: &IndexMap,
+  ) -> Result<()> {
+    for skin_def in gltf.skins() {
+      let joints: Vec<Index> = skin_def
+        .joints()
+        .filter_map(|joint_def| node_index.get(&joint_def.index()).cloned())
+        .collect();
+
+      let inverse_bind_matrices = match skin_def.inverse_bind_matrices() {
+        Some(accessor_def) => read_matrices4(buffers, &accessor_def)?,
+        None => vec![Matrix4::identity(); joints.len()],
+      };
+
+      let skin_handle = self.insert_skin(Skin {
+        joints,
+        inverse_bind_matrices,
+      });
+
+      for node_def in gltf.nodes() {
+        match node_def.skin() {
+          Some(node_skin_def) if node_skin_def.index() == skin_def.index() => {
+            let node_handle = *node_index.get(&node_def.index()).unwrap();
+
+            if let Some(node) = self.scene.get_node_mut(node_handle) {
+              node.skin = Some(skin_handle);
+            }
+          }
+          _ => {}
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  pub fn create_gltf_animations(
+    &mut self,
+    gltf: &Gltf,
+    buffers: &[&[u8]],
+    node_index: &IndexMap,
+  ) -> Result<Vec<Index>> {
+    let mut animations = vec![];
+
+    for animation_def in gltf.animations() {
+      let mut channels = vec![];
+
+      for channel_def in animation_def.channels() {
+        let target_def = channel_def.target();
+        let sampler_def = channel_def.sampler();
+
+        let interpolation = match sampler_def.interpolation() {
+          GltfInterpolation::Linear => Interpolation::Linear,
+          GltfInterpolation::Step => Interpolation::Step,
+          GltfInterpolation::CubicSpline => bail!(
+            "animation {} uses unsupported cubic spline interpolation",
+            animation_def.index()
+          ),
+        };
+
+        let node = match node_index.get(&target_def.node().index()) {
+          Some(node) => *node,
+          None => continue,
+        };
+
+        let times = read_floats(buffers, &sampler_def.input())?;
+        let outputs = read_floats(buffers, &sampler_def.output())?;
+
+        let values = match target_def.property() {
+          Property::Translation => ChannelValues::Translation(
+            outputs
+              .chunks_exact(3)
+              .map(|v| Vector3::new(v[0], v[1], v[2]))
+              .collect(),
+          ),
+          Property::Rotation => ChannelValues::Rotation(
+            outputs
+              .chunks_exact(4)
+              .map(|v| UnitQuaternion::from_quaternion(Quaternion::new(v[3], v[0], v[1], v[2])))
+              .collect(),
+          ),
+          Property::Scale => ChannelValues::Scale(
+            outputs
+              .chunks_exact(3)
+              .map(|v| Vector3::new(v[0], v[1], v[2]))
+              .collect(),
+          ),
+          // morph targets are not supported yet
+          Property::MorphTargetWeights => continue,
+        };
+
+        channels.push(Channel {
+          node,
+          interpolation,
+          times,
+          values,
+        });
+      }
+
+      animations.push(self.animations.insert(Animation::new(
+        animation_def.name().map(|n| n.to_string()),
+        channels,
+      )));
+    }
+
+    Ok(animations)
+  }
+
+  pub fn create_gltf_cameras(&mut self, gltf: &Gltf, node_index: &IndexMap) -> Vec<Index> {
+    let mut cameras = vec![];
+
+    for node_def in gltf.nodes() {
+      if let Some(camera_def) = node_def.camera() {
+        let node_handle = *node_index.get(&node_def.index()).unwrap();
+        let view = self
+          .scene
+          .compute_matrix_world(node_handle)
+          .and_then(|m| m.try_inverse())
+          .unwrap_or_else(Matrix4::identity);
+
+        let projection = match camera_def.projection() {
+          Projection::Perspective(p) => get_perspective_projection(
+            p.aspect_ratio().unwrap_or(1.0),
+            p.yfov(),
+            p.znear(),
+            p.zfar(),
+          ),
+          Projection::Orthographic(o) => Orthographic3::new(
+            -o.xmag(),
+            o.xmag(),
+            -o.ymag(),
+            o.ymag(),
+            o.znear(),
+            o.zfar(),
+          )
+          .to_homogeneous(),
+        };
+
+        cameras.push(self.cameras.insert(Camera::new(view, projection)));
+      }
+    }
+
+    cameras
+  }
+
   pub fn create_gltf_scenes(&mut self, gltf: &Gltf, node_index: &IndexMap) -> Vec<Index> {
     gltf
       .scenes()
@@ -246,7 +909,7 @@ impl Renderer {
 
         for node_def in scene_def.nodes() {
           let node_handle = *node_index.get(&node_def.index()).unwrap();
-          self.scene.set_parent(node_handle, scene_handle);
+          self.scene.set_parent(node_handle, Some(scene_handle));
         }
 
         scene_handle
@@ -254,12 +917,813 @@ impl Renderer {
       .collect()
   }
 
-  pub fn bake_gltf(&mut self, gltf: &Gltf) -> Vec<Index> {
-    let accessor_index = self.create_gltf_accessors(gltf);
-    let material_index = self.create_gltf_materials(gltf);
+  /// Loads .gltf or .glb bytes, with the extensions the gltf crate drops read from the JSON
+  pub fn load_gltf_from_slice(
+    &mut self,
+    bytes: &[u8],
+    external: &HashMap<usize, Vec<u8>>,
+  ) -> Result<GltfImportResult> {
+    let json = read_gltf_json(bytes)?;
+
+    check_gltf_version(&json)?;
+
+    let gltf = Gltf::from_slice(bytes)?;
+
+    self.load_gltf_with_extensions(&gltf, external, &GltfExtensions::from_json(&json))
+  }
+
+  /// Only applies what the parsed document carries, see load_gltf_from_slice
+  pub fn load_gltf(&mut self, gltf: &Gltf) -> Result<GltfImportResult> {
+    self.load_gltf_with_buffers(gltf, &HashMap::new())
+  }
+
+  /// External buffers are fetched by the host and passed in by buffer index
+  pub fn load_gltf_with_buffers(
+    &mut self,
+    gltf: &Gltf,
+    external: &HashMap<usize, Vec<u8>>,
+  ) -> Result<GltfImportResult> {
+    self.load_gltf_with_extensions(gltf, external, &GltfExtensions::default())
+  }
+
+  pub fn load_gltf_with_extensions(
+    &mut self,
+    gltf: &Gltf,
+    external: &HashMap<usize, Vec<u8>>,
+    extensions: &GltfExtensions,
+  ) -> Result<GltfImportResult> {
+    check_gltf_extensions(gltf, &extensions.get_supported())?;
+
+    let buffers = get_gltf_buffers(gltf, external)?;
+    let accessor_index = self.create_gltf_accessors(gltf, &buffers)?;
+    let texture_index = self.create_gltf_textures(gltf, &buffers)?;
+    let material_index = self.create_gltf_materials(gltf, &texture_index, extensions)?;
     let mesh_index = self.create_gltf_meshes(gltf, &accessor_index, &material_index);
     let node_index = self.create_gltf_nodes(gltf, &mesh_index);
 
-    self.create_gltf_scenes(gltf, &node_index)
+    self.create_gltf_skins(gltf, &buffers, &node_index)?;
+
+    let animations = self.create_gltf_animations(gltf, &buffers, &node_index)?;
+    let scenes = self.create_gltf_scenes(gltf, &node_index);
+    let cameras = self.create_gltf_cameras(gltf, &node_index);
+    let scene_index = gltf.default_scene().map(|s| s.index()).unwrap_or(0);
+
+    Ok(GltfImportResult {
+      meshes: get_ordered_handles(&mesh_index, gltf.meshes().len()),
+      nodes: get_ordered_handles(&node_index, gltf.nodes().len()),
+      cameras,
+      animations,
+      root: scenes.get(scene_index).copied(),
+      scenes,
+    })
+  }
+
+  pub fn bake_gltf_from_slice(&mut self, bytes: &[u8]) -> Result<Vec<Index>> {
+    Ok(self.load_gltf_from_slice(bytes, &HashMap::new())?.scenes)
+  }
+
+  pub fn bake_gltf(&mut self, gltf: &Gltf) -> Result<Vec<Index>> {
+    self.bake_gltf_with_buffers(gltf, &HashMap::new())
+  }
+
+  pub fn bake_gltf_with_buffers(
+    &mut self,
+    gltf: &Gltf,
+    external: &HashMap<usize, Vec<u8>>,
+  ) -> Result<Vec<Index>> {
+    Ok(self.load_gltf_with_buffers(gltf, external)?.scenes)
+  }
+
+  pub fn bake_gltf_scene(&mut self, gltf: &Gltf) -> Result<Index> {
+    self
+      .load_gltf(gltf)?
+      .root
+      .ok_or_else(|| anyhow!("gltf has no scenes"))
+  }
+}
+
+/// Splits a GLB into its JSON and BIN chunks, the BIN is empty when the file has none
+pub fn parse_glb(bytes: &[u8]) -> Result<(Value, Vec<u8>)> {
+  if !bytes.starts_with(b"glTF") {
+    bail!("not a GLB file");
+  }
+
+  let glb = Glb::from_slice(bytes)?;
+  let json = serde_json::from_slice(&glb.json)?;
+  let bin = glb.bin.map(|bin| bin.into_owned()).unwrap_or_default();
+
+  Ok((json, bin))
+}
+
+/// JSON of either a .gltf or a .glb, for inspecting a file before loading it
+pub fn read_gltf_json(bytes: &[u8]) -> Result<Value> {
+  if bytes.starts_with(b"glTF") {
+    Ok(serde_json::from_slice(&Glb::from_slice(bytes)?.json)?)
+  } else {
+    Ok(serde_json::from_slice(bytes)?)
+  }
+}
+
+/// Only 2.x assets load, and only when they don't ask for a newer minor version than 2.0
+pub fn check_gltf_version(root: &Value) -> Result<()> {
+  let asset = &root["asset"];
+  let version = asset["version"]
+    .as_str()
+    .ok_or_else(|| anyhow!("gltf asset has no version"))?;
+
+  if !version.starts_with("2.") {
+    bail!("unsupported gltf version {}", version);
+  }
+
+  if let Some(min_version) = asset["minVersion"].as_str() {
+    if min_version != "2.0" {
+      bail!(
+        "gltf requires version {}, only 2.0 is supported",
+        min_version
+      );
+    }
+  }
+
+  Ok(())
+}
+
+/// Fails on required extensions outside supported and warns about optional ones it ignores
+pub fn check_gltf_extensions(gltf: &Gltf, supported: &[&str]) -> Result<()> {
+  let is_supported = |name: &&str| supported.contains(name);
+  let missing: Vec<&str> = gltf
+    .extensions_required()
+    .filter(|name| !is_supported(name))
+    .collect();
+
+  if !missing.is_empty() {
+    bail!(
+      "gltf requires unsupported extensions: {}",
+      missing.join(", ")
+    );
+  }
+
+  for name in gltf.extensions_used().filter(|name| !is_supported(name)) {
+    warn!(
+      "gltf extension {} is not supported and will be ignored",
+      name
+    );
+  }
+
+  Ok(())
+}
+
+pub fn get_ordered_handles(index: &IndexMap, len: usize) -> Vec<Index> {
+  (0..len).filter_map(|i| index.get(&i).copied()).collect()
+}
+
+/// Resolves every buffer to bytes, the GLB chunk comes from the blob and the rest from external
+pub fn get_gltf_buffers<'a>(
+  gltf: &'a Gltf,
+  external: &'a HashMap<usize, Vec<u8>>,
+) -> Result<Vec<&'a [u8]>> {
+  gltf
+    .buffers()
+    .map(|buffer_def| {
+      let data: &[u8] = match buffer_def.source() {
+        BufferSource::Bin => gltf
+          .blob
+          .as_deref()
+          .ok_or_else(|| anyhow!("buffer {} has no binary blob", buffer_def.index()))?,
+        BufferSource::Uri(uri) => external
+          .get(&buffer_def.index())
+          .map(|data| data.as_slice())
+          .ok_or_else(|| anyhow!("buffer {} ({}) was not provided", buffer_def.index(), uri))?,
+      };
+
+      if data.len() < buffer_def.length() {
+        bail!(
+          "buffer {} has {} bytes, expected {}",
+          buffer_def.index(),
+          data.len(),
+          buffer_def.length()
+        );
+      }
+
+      Ok(data)
+    })
+    .collect()
+}
+
+pub fn get_view_data<'a>(buffers: &[&'a [u8]], view_def: &View) -> Result<&'a [u8]> {
+  let buffer = buffers
+    .get(view_def.buffer().index())
+    .ok_or_else(|| anyhow!("buffer view {} has no buffer", view_def.index()))?;
+  let offset = view_def.offset();
+  let length = view_def.length();
+
+  buffer
+    .get(offset..(offset + length))
+    .ok_or_else(|| anyhow!("buffer view {} is out of bounds", view_def.index()))
+}
+
+pub fn get_index_accessors(gltf: &Gltf) -> HashSet<usize> {
+  gltf
+    .meshes()
+    .flat_map(|mesh_def| mesh_def.primitives())
+    .filter_map(|primitive_def| {
+      primitive_def
+        .indices()
+        .map(|accessor_def| accessor_def.index())
+    })
+    .collect()
+}
+
+/// Integer colors and weights are only valid normalized, some exporters leave the flag out
+/// and without it the shader sees 0..255 instead of 0..1
+pub fn get_normalized_accessors(gltf: &Gltf) -> HashSet<usize> {
+  gltf
+    .meshes()
+    .flat_map(|mesh_def| mesh_def.primitives())
+    .flat_map(|primitive_def| primitive_def.attributes().collect::<Vec<_>>())
+    .filter(|(semantic_def, accessor_def)| {
+      matches!(semantic_def, Semantic::Colors(_) | Semantic::Weights(_))
+        && matches!(accessor_def.data_type(), DataType::U8 | DataType::U16)
+    })
+    .map(|(_, accessor_def)| accessor_def.index())
+    .collect()
+}
+
+pub fn get_texture_ref(
+  texture_index: &IndexMap,
+  name: &str,
+  texture: Option<(usize, u32)>,
+) -> Result<(Option<Index>, u32)> {
+  match texture {
+    Some((_, tex_coord)) if tex_coord > 1 => {
+      bail!(
+        "{} texture uses unsupported texcoord set {}",
+        name,
+        tex_coord
+      )
+    }
+    Some((index, tex_coord)) => Ok((texture_index.get(&index).cloned(), tex_coord)),
+    None => Ok((None, 0)),
+  }
+}
+
+/// Filters missing from the definition keep the `Sampler` defaults
+pub fn get_gltf_sampler(sampler_def: &SamplerDef) -> Sampler {
+  let mut sampler = Sampler::default()
+    .set_wrap_s(get_wrapping_mode(sampler_def.wrap_s()))
+    .set_wrap_t(get_wrapping_mode(sampler_def.wrap_t()));
+
+  if let Some(filter) = sampler_def.min_filter() {
+    sampler = sampler.set_min_filter(match filter {
+      MinFilter::Nearest => TexParam::Nearest,
+      MinFilter::Linear => TexParam::Linear,
+      MinFilter::NearestMipmapNearest => TexParam::NearestMipMapNearest,
+      MinFilter::LinearMipmapNearest => TexParam::LinearMipMapNearest,
+      MinFilter::NearestMipmapLinear => TexParam::NearestMimMapLinear,
+      MinFilter::LinearMipmapLinear => TexParam::LinearMipMapLinear,
+    });
+  }
+
+  if let Some(filter) = sampler_def.mag_filter() {
+    sampler = sampler.set_mag_filter(match filter {
+      MagFilter::Nearest => TexParam::Nearest,
+      MagFilter::Linear => TexParam::Linear,
+    });
+  }
+
+  sampler
+}
+
+pub fn get_wrapping_mode(mode: WrappingMode) -> TexParam {
+  match mode {
+    WrappingMode::ClampToEdge => TexParam::ClampToEdge,
+    WrappingMode::MirroredRepeat => TexParam::MirroredRepeat,
+    WrappingMode::Repeat => TexParam::Repeat,
+  }
+}
+
+/// Without OES_element_index_uint 32-bit indices only fit when every vertex is below 65536
+pub fn narrow_indices(data: &[u8]) -> Result<Vec<u16>> {
+  data
+    .chunks_exact(4)
+    .map(|c| {
+      let index = u32::from_le_bytes([c[0], c[1], c[2], c[3]]);
+
+      u16::try_from(index).map_err(|_| {
+        anyhow!(
+          "index {} needs 32-bit indices but OES_element_index_uint is unavailable",
+          index
+        )
+      })
+    })
+    .collect()
+}
+
+pub fn get_draw_mode(mode: Mode) -> DrawMode {
+  match mode {
+    Mode::Points => DrawMode::Points,
+    Mode::Lines => DrawMode::Lines,
+    Mode::LineLoop => DrawMode::LineLoop,
+    Mode::LineStrip => DrawMode::LineStrip,
+    Mode::Triangles => DrawMode::Triangles,
+    Mode::TriangleStrip => DrawMode::TriangleStrip,
+    Mode::TriangleFan => DrawMode::TriangleFan,
+  }
+}
+
+/// Layout inside the whole uploaded view, stride and offset stay relative to the view so
+/// interleaved accessors and packed index ranges can share its buffer
+pub fn get_accessor_options(accessor_def: &AccessorDef, normalized: bool) -> AttributeOptions {
+  AttributeOptions {
+    component_type: get_component_type(accessor_def.data_type()),
+    item_size: accessor_def.dimensions().multiplicity() as i32,
+    normalized: accessor_def.normalized() || normalized,
+    stride: accessor_def
+      .view()
+      .and_then(|view_def| view_def.stride())
+      .unwrap_or(0) as i32,
+    offset: accessor_def.offset() as i32,
+  }
+}
+
+pub fn get_component_type(data_type: DataType) -> TypedArrayKind {
+  match data_type {
+    DataType::U8 => TypedArrayKind::Uint8,
+    DataType::I8 => TypedArrayKind::Int8,
+    DataType::I16 => TypedArrayKind::Int16,
+    DataType::U16 => TypedArrayKind::Uint16,
+    DataType::U32 => TypedArrayKind::Uint32,
+    DataType::F32 => TypedArrayKind::Float32,
+  }
+}
+
+/// Copies accessor items into a tightly packed vec, sparse substitution included
+pub fn read_accessor_data(buffers: &[&[u8]], accessor_def: &AccessorDef) -> Result<Vec<u8>> {
+  if let Some(sparse_def) = accessor_def.sparse() {
+    return read_sparse_accessor(buffers, accessor_def, &sparse_def);
+  }
+
+  read_dense_accessor(buffers, accessor_def)
+}
+
+/// Zeros when the accessor has no buffer view
+pub fn read_dense_accessor(buffers: &[&[u8]], accessor_def: &AccessorDef) -> Result<Vec<u8>> {
+  let element_size = accessor_def.size();
+  let count = accessor_def.count();
+
+  let view_def = match accessor_def.view() {
+    Some(view_def) => view_def,
+    None => return Ok(vec![0; element_size * count]),
+  };
+
+  check_accessor_bounds(accessor_def, &view_def)?;
+
+  let view_data = get_view_data(buffers, &view_def)?;
+  let stride = view_def.stride().unwrap_or(element_size);
+  let start = accessor_def.offset();
+
+  let mut data = Vec::with_capacity(element_size * count);
+
+  for i in 0..count {
+    let src = start + i * stride;
+
+    data.extend_from_slice(&view_data[src..(src + element_size)]);
+  }
+
+  Ok(data)
+}
+
+pub fn check_accessor_bounds(accessor_def: &AccessorDef, view_def: &View) -> Result<()> {
+  let element_size = accessor_def.size();
+  let count = accessor_def.count();
+  let stride = view_def.stride().unwrap_or(element_size);
+
+  if stride < element_size {
+    bail!(
+      "accessor {} elements of {} bytes overlap with view stride {}",
+      accessor_def.index(),
+      element_size,
+      stride
+    );
+  }
+
+  if count > 0 && accessor_def.offset() + (count - 1) * stride + element_size > view_def.length() {
+    bail!(
+      "accessor {} is out of bounds of view {}",
+      accessor_def.index(),
+      view_def.index()
+    );
+  }
+
+  Ok(())
+}
+
+pub fn read_sparse_accessor(
+  buffers: &[&[u8]],
+  accessor_def: &AccessorDef,
+  sparse_def: &Sparse,
+) -> Result<Vec<u8>> {
+  let element_size = accessor_def.size();
+  let count = accessor_def.count();
+
+  let mut data = read_dense_accessor(buffers, accessor_def)?;
+
+  let indices_def = sparse_def.indices();
+  let index_type = indices_def.index_type();
+  let index_size = index_type.size();
+  let indices_data = &get_view_data(buffers, &indices_def.view())?[indices_def.offset() as usize..];
+
+  let values_def = sparse_def.values();
+  let values_data = &get_view_data(buffers, &values_def.view())?[values_def.offset() as usize..];
+
+  for i in 0..sparse_def.count() as usize {
+    let src = &indices_data[(i * index_size)..];
+    let index = match index_type {
+      IndexType::U8 => src[0] as usize,
+      IndexType::U16 => u16::from_le_bytes([src[0], src[1]]) as usize,
+      IndexType::U32 => u32::from_le_bytes([src[0], src[1], src[2], src[3]]) as usize,
+    };
+
+    if index >= count {
+      bail!(
+        "sparse accessor {} index {} is out of range",
+        accessor_def.index(),
+        index
+      );
+    }
+
+    let value_start = i * element_size;
+
+    data[index * element_size..(index + 1) * element_size]
+      .copy_from_slice(&values_data[value_start..(value_start + element_size)]);
+  }
+
+  Ok(data)
+}
+
+/// Variants each material is drawn with, by material index
+pub fn get_material_variants(gltf: &Gltf) -> HashMap<usize, HashSet<MaterialVariant>> {
+  let mut variants: HashMap<usize, HashSet<MaterialVariant>> = HashMap::new();
+
+  for primitive_def in gltf.meshes().flat_map(|mesh_def| mesh_def.primitives()) {
+    if let Some(index) = primitive_def.material().index() {
+      variants
+        .entry(index)
+        .or_default()
+        .insert(MaterialVariant::from_primitive(&primitive_def));
+    }
+  }
+
+  variants
+}
+
+pub fn is_skinned_primitive(primitive_def: &gltf::Primitive) -> bool {
+  primitive_def.get(&Semantic::Joints(0)).is_some()
+    && primitive_def.get(&Semantic::Weights(0)).is_some()
+}
+
+/// Column-major float mat4 values
+pub fn read_matrices4(buffers: &[&[u8]], accessor_def: &AccessorDef) -> Result<Vec<Matrix4<f32>>> {
+  let values = read_floats(buffers, accessor_def)?;
+
+  Ok(
+    values
+      .chunks_exact(16)
+      .map(Matrix4::from_column_slice)
+      .collect(),
+  )
+}
+
+/// Reads accessor components as floats, normalized integers are mapped to their float range
+pub fn read_floats(buffers: &[&[u8]], accessor_def: &AccessorDef) -> Result<Vec<f32>> {
+  let data = read_accessor_data(buffers, accessor_def)?;
+
+  let normalized = accessor_def.normalized();
+  let signed = |v: f32, max: f32| if normalized { (v / max).max(-1.0) } else { v };
+  let unsigned = |v: f32, max: f32| if normalized { v / max } else { v };
+
+  Ok(match accessor_def.data_type() {
+    DataType::F32 => data
+      .chunks_exact(4)
+      .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+      .collect(),
+    DataType::I8 => data
+      .iter()
+      .map(|b| signed(*b as i8 as f32, 127.0))
+      .collect(),
+    DataType::U8 => data.iter().map(|b| unsigned(*b as f32, 255.0)).collect(),
+    DataType::I16 => data
+      .chunks_exact(2)
+      .map(|b| signed(i16::from_le_bytes([b[0], b[1]]) as f32, 32767.0))
+      .collect(),
+    DataType::U16 => data
+      .chunks_exact(2)
+      .map(|b| unsigned(u16::from_le_bytes([b[0], b[1]]) as f32, 65535.0))
+      .collect(),
+    DataType::U32 => data
+      .chunks_exact(4)
+      .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32)
+      .collect(),
+  })
+}
+
+pub fn get_accessor_bounds(accessor_def: &AccessorDef) -> Option<Aabb> {
+  let read = |value: Option<gltf::json::Value>| -> Option<Vector3<f32>> {
+    let values = value?;
+    let values = values.as_array()?;
+
+    if values.len() < 3 {
+      return None;
+    }
+
+    Some(Vector3::new(
+      values[0].as_f64()? as f32,
+      values[1].as_f64()? as f32,
+      values[2].as_f64()? as f32,
+    ))
+  };
+
+  Some(Aabb::new(
+    read(accessor_def.min())?,
+    read(accessor_def.max())?,
+  ))
+}
+
+pub fn get_perspective_projection(
+  aspect: f32,
+  yfov: f32,
+  znear: f32,
+  zfar: Option<f32>,
+) -> Matrix4<f32> {
+  match zfar {
+    Some(zfar) => Perspective3::new(aspect, yfov, znear, zfar).to_homogeneous(),
+    None => {
+      let f = 1.0 / (yfov / 2.0).tan();
+
+      Matrix4::new(
+        f / aspect,
+        0.0,
+        0.0,
+        0.0, //
+        0.0,
+        f,
+        0.0,
+        0.0, //
+        0.0,
+        0.0,
+        -1.0,
+        -2.0 * znear, //
+        0.0,
+        0.0,
+        -1.0,
+        0.0, //
+      )
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Positions, normals and uvs interleaved with a 32 byte stride, then two index ranges
+  /// packed into one view
+  fn interleaved_gltf() -> (Gltf, Vec<u8>) {
+    let json = r#"{
+      "asset": { "version": "2.0" },
+      "buffers": [{ "byteLength": 108 }],
+      "bufferViews": [
+        { "buffer": 0, "byteOffset": 0, "byteLength": 96, "byteStride": 32, "target": 34962 },
+        { "buffer": 0, "byteOffset": 96, "byteLength": 12, "target": 34963 }
+      ],
+      "accessors": [
+        { "bufferView": 0, "byteOffset": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+          "min": [0, 0, 0], "max": [2, 2, 2] },
+        { "bufferView": 0, "byteOffset": 12, "componentType": 5126, "count": 3, "type": "VEC3" },
+        { "bufferView": 0, "byteOffset": 24, "componentType": 5126, "count": 3, "type": "VEC2" },
+        { "bufferView": 1, "byteOffset": 0, "componentType": 5123, "count": 3, "type": "SCALAR" },
+        { "bufferView": 1, "byteOffset": 6, "componentType": 5123, "count": 3, "type": "SCALAR" }
+      ],
+      "meshes": [{
+        "primitives": [
+          { "attributes": { "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2 }, "indices": 3 },
+          { "attributes": { "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2 }, "indices": 4 }
+        ]
+      }]
+    }"#;
+
+    let mut buffer = vec![];
+
+    for i in 0..3 {
+      let v = i as f32;
+
+      for value in &[v, v, v, 0.0, 1.0, 0.0, v / 2.0, 1.0 - v / 2.0] {
+        buffer.extend_from_slice(&f32::to_le_bytes(*value));
+      }
+    }
+
+    for index in &[0u16, 1, 2, 2, 1, 0] {
+      buffer.extend_from_slice(&index.to_le_bytes());
+    }
+
+    (Gltf::from_slice(json.as_bytes()).unwrap(), buffer)
+  }
+
+  fn to_floats(data: &[u8]) -> Vec<f32> {
+    data
+      .chunks_exact(4)
+      .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+      .collect()
+  }
+
+  #[test]
+  fn interleaved_accessors_keep_view_relative_layout() {
+    let (gltf, _) = interleaved_gltf();
+    let options: Vec<AttributeOptions> = gltf
+      .accessors()
+      .map(|accessor_def| get_accessor_options(&accessor_def, false))
+      .collect();
+
+    assert_eq!(
+      options
+        .iter()
+        .map(|o| (o.stride, o.offset, o.item_size))
+        .collect::<Vec<_>>(),
+      vec![(32, 0, 3), (32, 12, 3), (32, 24, 2), (0, 0, 1), (0, 6, 1)]
+    );
+  }
+
+  #[test]
+  fn interleaved_accessors_read_their_own_components() {
+    let (gltf, buffer) = interleaved_gltf();
+    let buffers = [buffer.as_slice()];
+    let accessors: Vec<AccessorDef> = gltf.accessors().collect();
+
+    let positions = to_floats(&read_dense_accessor(&buffers, &accessors[0]).unwrap());
+    let normals = to_floats(&read_dense_accessor(&buffers, &accessors[1]).unwrap());
+    let uvs = to_floats(&read_dense_accessor(&buffers, &accessors[2]).unwrap());
+
+    assert_eq!(positions, vec![0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 2.0, 2.0, 2.0]);
+    assert_eq!(normals, [0.0, 1.0, 0.0].repeat(3));
+    assert_eq!(uvs, vec![0.0, 1.0, 0.5, 0.5, 1.0, 0.0]);
+
+    let second_indices = read_dense_accessor(&buffers, &accessors[4]).unwrap();
+
+    assert_eq!(second_indices, vec![2, 0, 1, 0, 0, 0]);
+  }
+
+  #[test]
+  fn extension_check_follows_the_loader_capabilities() {
+    let json = r#"{
+      "asset": { "version": "2.0" },
+      "extensionsUsed": ["KHR_texture_transform"],
+      "extensionsRequired": ["KHR_texture_transform"]
+    }"#;
+    let gltf = Gltf::from_slice(json.as_bytes()).unwrap();
+    let parsed = GltfExtensions::from_slice(json.as_bytes()).unwrap();
+
+    assert!(check_gltf_extensions(&gltf, &GltfExtensions::default().get_supported()).is_err());
+    assert!(check_gltf_extensions(&gltf, &parsed.get_supported()).is_ok());
+  }
+
+  #[test]
+  fn emissive_strength_needs_the_json() {
+    let json = r#"{
+      "asset": { "version": "2.0" },
+      "extensionsUsed": ["KHR_materials_emissive_strength"],
+      "extensionsRequired": ["KHR_materials_emissive_strength"],
+      "materials": [
+        {},
+        { "extensions": { "KHR_materials_emissive_strength": { "emissiveStrength": 5.0 } } }
+      ]
+    }"#;
+    let gltf = Gltf::from_slice(json.as_bytes()).unwrap();
+    let parsed = GltfExtensions::from_slice(json.as_bytes()).unwrap();
+
+    assert_eq!(parsed.emissive_strengths.get(&0), None);
+    assert_eq!(parsed.emissive_strengths.get(&1), Some(&5.0));
+    assert!(check_gltf_extensions(&gltf, &GltfExtensions::default().get_supported()).is_err());
+    assert!(check_gltf_extensions(&gltf, &parsed.get_supported()).is_ok());
+  }
+
+  #[test]
+  fn texture_transforms_come_from_the_document_and_the_json() {
+    let json = r#"{
+      "asset": { "version": "2.0" },
+      "extensionsUsed": ["KHR_texture_transform"],
+      "images": [{ "uri": "texture.png" }],
+      "textures": [{ "source": 0 }],
+      "materials": [{
+        "pbrMetallicRoughness": {
+          "baseColorTexture": {
+            "index": 0,
+            "extensions": {
+              "KHR_texture_transform": { "offset": [0.5, 0.25], "scale": [2, 2], "texCoord": 1 }
+            }
+          }
+        },
+        "normalTexture": {
+          "index": 0,
+          "extensions": { "KHR_texture_transform": { "rotation": 1.5 } }
+        }
+      }]
+    }"#;
+    let gltf = Gltf::from_slice(json.as_bytes()).unwrap();
+    let material_def = gltf.materials().next().unwrap();
+    let transform_def = material_def
+      .pbr_metallic_roughness()
+      .base_color_texture()
+      .and_then(|info_def| info_def.texture_transform())
+      .unwrap();
+    let base_color = TextureTransform::from_def(&transform_def);
+
+    assert_eq!(base_color.offset, Vector2::new(0.5, 0.25));
+    assert_eq!(base_color.scale, Vector2::new(2.0, 2.0));
+    assert_eq!(base_color.rotation, 0.0);
+    assert_eq!(base_color.tex_coord, Some(1));
+
+    let extensions = GltfExtensions::from_slice(json.as_bytes()).unwrap();
+    let normal = extensions.texture_transforms[&(0, TextureSlot::Normal)];
+
+    assert_eq!(normal.rotation, 1.5);
+    assert_eq!(normal.scale, Vector2::new(1.0, 1.0));
+    assert!(!extensions
+      .texture_transforms
+      .contains_key(&(0, TextureSlot::BaseColor)));
+  }
+
+  #[test]
+  fn integer_colors_read_as_normalized() {
+    let json = r#"{
+      "asset": { "version": "2.0" },
+      "buffers": [{ "byteLength": 20 }],
+      "bufferViews": [
+        { "buffer": 0, "byteOffset": 0, "byteLength": 12 },
+        { "buffer": 0, "byteOffset": 12, "byteLength": 8 }
+      ],
+      "accessors": [
+        { "bufferView": 0, "componentType": 5126, "count": 1, "type": "VEC3",
+          "min": [0, 0, 0], "max": [0, 0, 0] },
+        { "bufferView": 1, "byteOffset": 0, "componentType": 5121, "normalized": true,
+          "count": 1, "type": "VEC4" },
+        { "bufferView": 1, "byteOffset": 4, "componentType": 5121, "count": 1, "type": "VEC4" }
+      ],
+      "meshes": [{
+        "primitives": [
+          { "attributes": { "POSITION": 0, "COLOR_0": 1 } },
+          { "attributes": { "POSITION": 0, "COLOR_0": 2 } }
+        ]
+      }]
+    }"#;
+    let gltf = Gltf::from_slice(json.as_bytes()).unwrap();
+    let mut buffer = vec![0; 12];
+
+    buffer.extend_from_slice(&[0, 51, 255, 255, 255, 0, 0, 255]);
+
+    let buffers = [buffer.as_slice()];
+    let accessors: Vec<AccessorDef> = gltf.accessors().collect();
+    let normalized = get_normalized_accessors(&gltf);
+
+    // the flag is missing on the second color, integers there are still fractions
+    assert_eq!(normalized, vec![1, 2].into_iter().collect());
+    assert!(!normalized.contains(&0));
+
+    let colors = read_floats(&buffers, &accessors[1]).unwrap();
+
+    assert_eq!(colors, vec![0.0, 0.2, 1.0, 1.0]);
+    assert!(colors.iter().all(|c| (0.0..=1.0).contains(c)));
+  }
+
+  #[test]
+  fn shared_materials_get_a_variant_per_attribute_set() {
+    let json = r#"{
+      "asset": { "version": "2.0" },
+      "buffers": [{ "byteLength": 16 }],
+      "bufferViews": [{ "buffer": 0, "byteLength": 16 }],
+      "accessors": [
+        { "bufferView": 0, "componentType": 5126, "count": 1, "type": "VEC3",
+          "min": [0, 0, 0], "max": [0, 0, 0] },
+        { "bufferView": 0, "componentType": 5126, "count": 1, "type": "VEC4" },
+        { "bufferView": 0, "componentType": 5121, "count": 1, "type": "VEC4" }
+      ],
+      "materials": [{}, {}],
+      "meshes": [{
+        "primitives": [
+          { "attributes": { "POSITION": 0, "COLOR_0": 1 }, "material": 0 },
+          { "attributes": { "POSITION": 0 }, "material": 0 },
+          { "attributes": { "POSITION": 0 }, "material": 1 },
+          { "attributes": { "POSITION": 0, "JOINTS_0": 2, "WEIGHTS_0": 1 }, "material": 1 }
+        ]
+      }]
+    }"#;
+    let gltf = Gltf::from_slice(json.as_bytes()).unwrap();
+    let variants = get_material_variants(&gltf);
+    let primitives: Vec<gltf::Primitive> = gltf.meshes().next().unwrap().primitives().collect();
+
+    assert_eq!(variants[&0].len(), 2);
+    assert_eq!(variants[&1].len(), 2);
+    assert!(MaterialVariant::from_primitive(&primitives[0]).vertex_color);
+    assert!(!MaterialVariant::from_primitive(&primitives[1]).vertex_color);
+    assert!(!MaterialVariant::from_primitive(&primitives[2]).skinning);
+    assert!(MaterialVariant::from_primitive(&primitives[3]).skinning);
   }
 }
@@ -0,0 +1,99 @@
+use generational_arena::Index;
+use na::Vector3;
+
+use super::context::TextureKind;
+use super::material::material::bind_texture;
+use super::renderer::{DirectionalLight, PointLight, Renderer};
+use super::shader::Shader;
+
+pub const MAX_POINT_LIGHTS: usize = 4;
+
+// kept clear of the units materials bind their own maps to
+pub const ENVIRONMENT_MAP_UNIT: u32 = 6;
+
+impl Renderer {
+  pub fn insert_light(&mut self, light: DirectionalLight) -> Index {
+    let handle = self.lights.insert(light);
+
+    self.active_lights.push(handle);
+
+    handle
+  }
+
+  pub fn set_light_active(&mut self, handle: Index, active: bool) {
+    self.active_lights.retain(|h| *h != handle);
+
+    if active && self.lights.contains(handle) {
+      self.active_lights.push(handle);
+    }
+  }
+
+  pub fn get_active_light(&self) -> Option<&DirectionalLight> {
+    self
+      .active_lights
+      .iter()
+      .find_map(|handle| self.lights.get(*handle))
+  }
+
+  pub fn insert_point_light(&mut self, light: PointLight) -> Index {
+    self.point_lights.insert(light)
+  }
+
+  pub fn remove_point_light(&mut self, handle: Index) -> Option<()> {
+    self.point_lights.remove(handle)?;
+
+    Some(())
+  }
+
+  pub fn setup_lights(&self, shader: &Shader) {
+    match self.get_active_light() {
+      Some(light) => {
+        shader.set_vector3("lightDirection", &light.direction.normalize());
+        shader.set_vector3("lightColor", &light.color);
+        shader.set_float("lightIntensity", light.intensity);
+      }
+      None => {
+        shader.set_vector3("lightDirection", &Vector3::new(0.0, -1.0, 0.0));
+        shader.set_vector3("lightColor", &Vector3::new(0.0, 0.0, 0.0));
+        shader.set_float("lightIntensity", 0.0);
+      }
+    }
+
+    // lights past the shader limit are dropped
+    let mut count = 0;
+
+    for (_, light) in self.point_lights.iter().take(MAX_POINT_LIGHTS) {
+      shader.set_vector3(&format!("pointLightPositions[{}]", count), &light.position);
+      shader.set_vector3(
+        &format!("pointLightColors[{}]", count),
+        &(light.color * light.intensity),
+      );
+      shader.set_float(&format!("pointLightRanges[{}]", count), light.range);
+
+      count += 1;
+    }
+
+    shader.set_integer("pointLightCount", count);
+  }
+
+  /// Sets the prefiltered cube map shared by every material using IBL.
+  pub fn set_environment_map(&mut self, environment_map: Option<Index>) {
+    self.environment_map = environment_map;
+  }
+
+  pub fn setup_environment(&self, shader: &Shader) {
+    if let Some(environment_map) = self.environment_map {
+      bind_texture(
+        &self.ctx,
+        &self.images,
+        &self.textures,
+        &self.samplers,
+        shader,
+        environment_map,
+        TextureKind::CubeMap,
+        "environmentMap",
+        ENVIRONMENT_MAP_UNIT,
+      );
+    }
+  }
+}
@@ -0,0 +1,158 @@
+use na::Matrix4;
+
+use super::material::{Material, MaterialParams};
+use crate::renderer::webgl::context::{Context, DepthFunc, DrawMode};
+use crate::renderer::webgl::define::Define;
+use crate::renderer::webgl::renderer::{Camera, Images, RenderError, Samplers, Textures};
+use crate::renderer::webgl::shader::Shader;
+use crate::renderer::webgl::skin::MAX_JOINTS;
+use crate::scene::node::Node;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugMode {
+  /// World space normals
+  Normals,
+  /// First UV set, wrapped into [0, 1]
+  Uvs,
+  /// World space tangents, black where the geometry has none
+  Tangents,
+  VertexColors,
+}
+
+impl DebugMode {
+  fn get_name(&self) -> &'static str {
+    match self {
+      DebugMode::Normals => "normals",
+      DebugMode::Uvs => "uvs",
+      DebugMode::Tangents => "tangents",
+      DebugMode::VertexColors => "vertex_colors",
+    }
+  }
+}
+
+/// Shows one vertex attribute as color, shares the PBR vertex stage
+#[derive(Debug)]
+pub struct DebugMaterial {
+  mode: DebugMode,
+  skinning: bool,
+  instanced: bool,
+  double_sided: bool,
+}
+
+impl DebugMaterial {
+  pub fn new(mode: DebugMode) -> Self {
+    DebugMaterial {
+      mode,
+      skinning: false,
+      instanced: false,
+      double_sided: false,
+    }
+  }
+
+  pub fn set_mode(mut self, mode: DebugMode) -> Self {
+    self.mode = mode;
+    self
+  }
+
+  pub fn set_skinning(mut self, skinning: bool) -> Self {
+    self.skinning = skinning;
+    self
+  }
+
+  pub fn set_instanced(mut self, instanced: bool) -> Self {
+    self.instanced = instanced;
+    self
+  }
+
+  pub fn set_double_sided(mut self, double_sided: bool) -> Self {
+    self.double_sided = double_sided;
+    self
+  }
+
+  pub fn boxed(self) -> Box<Self> {
+    Box::new(self)
+  }
+}
+
+impl Material for DebugMaterial {
+  fn get_tag(&self) -> String {
+    let mut tag = format!("debug:{}", self.mode.get_name());
+
+    if self.skinning {
+      tag.push_str(":skinning");
+    }
+
+    if self.instanced {
+      tag.push_str(":instanced");
+    }
+
+    tag
+  }
+
+  fn create_shader(&self, ctx: &Context, defines: &[Define]) -> Result<Shader, RenderError> {
+    let vert_src = include_str!("./shaders/pbr_vert.glsl");
+    let frag_src = include_str!("./shaders/debug_frag.glsl");
+
+    let mut defines = defines.to_vec();
+
+    match self.mode {
+      DebugMode::Normals => defines.push(Define::def("DEBUG_NORMALS")),
+      DebugMode::Uvs => defines.push(Define::def("DEBUG_UVS")),
+      DebugMode::Tangents => {
+        // the vertex stage only forwards tangents for normal mapping
+        defines.push(Define::def("DEBUG_TANGENTS"));
+        defines.push(Define::def("USE_NORMAL_MAP"));
+      }
+      DebugMode::VertexColors => {
+        defines.push(Define::def("DEBUG_VERTEX_COLORS"));
+        defines.push(Define::def("USE_VERTEX_COLOR"));
+      }
+    }
+
+    if self.skinning {
+      defines.push(Define::def("USE_SKINNING"));
+      defines.push(Define::int("MAX_JOINTS", MAX_JOINTS as i32));
+    }
+
+    if self.instanced {
+      defines.push(Define::def("INSTANCED"));
+    }
+
+    ctx.create_shader(vert_src, frag_src, &defines)
+  }
+
+  fn setup_shader(
+    &self,
+    _ctx: &Context,
+    _images: &Images,
+    _textures: &Textures,
+    _samplers: &Samplers,
+    shader: &Shader,
+    node: &Node,
+    _camera: &Camera,
+  ) {
+    shader.set_matrix4("modelMatrix", &node.matrix_world);
+    shader.set_matrix3(
+      "normalMatrix",
+      &node
+        .matrix_world
+        .try_inverse()
+        .unwrap_or_else(Matrix4::identity)
+        .transpose()
+        .fixed_slice::<3, 3>(0, 0)
+        .into(),
+    );
+  }
+
+  fn params(&self) -> MaterialParams {
+    MaterialParams {
+      cull_face: !self.double_sided,
+      depth_test: true,
+      depth_func: DepthFunc::Less,
+      depth_write: true,
+      polygon_offset: None,
+      draw_mode: DrawMode::Triangles,
+      line_width: 1.0,
+    }
+  }
+}
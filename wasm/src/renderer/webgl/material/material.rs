@@ -1,22 +1,35 @@
 use generational_arena::Index;
 
 use crate::renderer::webgl::context::{Context, DepthFunc, DrawMode, TextureKind};
-use crate::renderer::webgl::renderer::{Camera, Images, Samplers, Textures};
+use crate::renderer::webgl::define::Define;
+use crate::renderer::webgl::renderer::{Camera, Images, RenderError, Samplers, Textures};
 use crate::renderer::webgl::shader::Shader;
 use crate::scene::node::Node;
-use anyhow::Result;
 use std::fmt::Debug;
 
 pub struct MaterialParams {
   pub cull_face: bool,
   pub depth_test: bool,
   pub depth_func: DepthFunc,
+  pub depth_write: bool,
+  /// (factor, units) pushing the depth away from coplanar geometry, negative values pull it closer
+  pub polygon_offset: Option<(f32, f32)>,
   pub draw_mode: DrawMode,
+  /// Width of line primitives in pixels, most browsers only support 1
+  pub line_width: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlphaMode {
+  Opaque,
+  Mask(f32),
+  Blend,
 }
 
 pub trait Material: Debug {
   fn get_tag(&self) -> String;
-  fn create_shader(&self, ctx: &Context) -> Result<Shader>;
+  /// `defines` are renderer-wide and come before the material's own
+  fn create_shader(&self, ctx: &Context, defines: &[Define]) -> Result<Shader, RenderError>;
   fn setup_shader(
     &self,
     ctx: &Context,
@@ -28,6 +41,13 @@ pub trait Material: Debug {
     camera: &Camera,
   );
   fn params(&self) -> MaterialParams;
+  fn is_transparent(&self) -> bool {
+    false
+  }
+  /// Masked materials discard fragments, so depth-only shaders can't stand in for them
+  fn is_alpha_masked(&self) -> bool {
+    false
+  }
 }
 
 pub fn bind_several_maps(
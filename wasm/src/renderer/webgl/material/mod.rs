@@ -1,7 +1,17 @@
+pub mod debug_material;
+pub mod depth_material;
 pub mod material;
 pub mod pbr_material;
+pub mod pick_material;
+pub mod shadow_material;
 pub mod skybox_material;
+pub mod unlit_material;
 
-pub use material::Material;
+pub use debug_material::{DebugMaterial, DebugMode};
+pub use depth_material::DepthMaterial;
+pub use material::{AlphaMode, Material};
 pub use pbr_material::PbrMaterial;
+pub use pick_material::PickMaterial;
+pub use shadow_material::ShadowMaterial;
 pub use skybox_material::SkyboxMaterial;
+pub use unlit_material::UnlitMaterial;
@@ -1,23 +1,55 @@
 use generational_arena::Index;
-use na::{Matrix4, Vector2, Vector3, U3};
+use na::{Matrix3, Matrix4, Vector2, Vector3, U3};
 
-use anyhow::Result;
-
-use super::material::{bind_several_maps, Material, MaterialParams};
+use super::material::{bind_several_maps, AlphaMode, Material, MaterialParams};
 use crate::renderer::webgl::context::{Context, DepthFunc, DrawMode, TextureKind};
 use crate::renderer::webgl::define::Define;
-use crate::renderer::webgl::renderer::{Camera, Images, Samplers, Textures};
+use crate::renderer::webgl::light::MAX_POINT_LIGHTS;
+use crate::renderer::webgl::renderer::{Camera, Images, RenderError, Samplers, Textures};
 use crate::renderer::webgl::shader::Shader;
+use crate::renderer::webgl::skin::MAX_JOINTS;
 use crate::scene::node::Node;
 
 #[derive(Debug)]
 pub struct PbrMaterial {
   color: Vector3<f32>,
+  metallic: f32,
+  roughness: f32,
+  opacity: f32,
+  alpha_mode: AlphaMode,
+  depth_write: bool,
   color_map: Option<Index>,
+  color_map_tex_coord: u32,
+  color_map_transform: Matrix3<f32>,
+  normal_map: Option<Index>,
+  normal_map_tex_coord: u32,
+  normal_map_transform: Matrix3<f32>,
+  normal_scale: f32,
+  emissive: Vector3<f32>,
+  emissive_strength: f32,
+  emissive_map: Option<Index>,
+  emissive_map_tex_coord: u32,
+  emissive_map_transform: Matrix3<f32>,
+  metallic_roughness_map: Option<Index>,
+  metallic_roughness_map_tex_coord: u32,
+  metallic_roughness_map_transform: Matrix3<f32>,
+  occlusion_map: Option<Index>,
+  occlusion_map_tex_coord: u32,
+  occlusion_map_transform: Matrix3<f32>,
+  occlusion_strength: f32,
   debug_cube_map: Option<Index>,
+  vertex_color: bool,
+  skinning: bool,
+  instanced: bool,
+  point_size: Option<f32>,
+  line_width: f32,
+  ibl: bool,
   uv_repeating: Vector2<f32>,
   cull_face: bool,
+  double_sided: bool,
   depth_test: bool,
+  depth_func: DepthFunc,
+  polygon_offset: Option<(f32, f32)>,
   draw_mode: DrawMode,
 }
 
@@ -25,11 +57,43 @@ impl PbrMaterial {
   pub fn new() -> Self {
     PbrMaterial {
       color: Vector3::new(0.0, 0.0, 0.0),
+      metallic: 1.0,
+      roughness: 1.0,
+      opacity: 1.0,
+      alpha_mode: AlphaMode::Opaque,
+      depth_write: true,
       cull_face: true,
+      double_sided: false,
       depth_test: true,
+      depth_func: DepthFunc::Less,
+      polygon_offset: None,
       draw_mode: DrawMode::Triangles,
       color_map: None,
+      color_map_tex_coord: 0,
+      color_map_transform: Matrix3::identity(),
+      normal_map: None,
+      normal_map_tex_coord: 0,
+      normal_map_transform: Matrix3::identity(),
+      normal_scale: 1.0,
+      emissive: Vector3::zeros(),
+      emissive_strength: 1.0,
+      emissive_map: None,
+      emissive_map_tex_coord: 0,
+      emissive_map_transform: Matrix3::identity(),
+      metallic_roughness_map: None,
+      metallic_roughness_map_tex_coord: 0,
+      metallic_roughness_map_transform: Matrix3::identity(),
+      occlusion_map: None,
+      occlusion_map_tex_coord: 0,
+      occlusion_map_transform: Matrix3::identity(),
+      occlusion_strength: 1.0,
       debug_cube_map: None,
+      vertex_color: false,
+      skinning: false,
+      instanced: false,
+      point_size: None,
+      line_width: 1.0,
+      ibl: false,
       uv_repeating: Vector2::new(1.0, 1.0),
     }
   }
@@ -39,16 +103,57 @@ impl PbrMaterial {
     self
   }
 
+  pub fn set_metallic(mut self, metallic: f32) -> Self {
+    self.metallic = metallic;
+    self
+  }
+
+  pub fn set_roughness(mut self, roughness: f32) -> Self {
+    self.roughness = roughness;
+    self
+  }
+
+  pub fn set_opacity(mut self, opacity: f32) -> Self {
+    self.opacity = opacity;
+    self
+  }
+
+  pub fn set_alpha_mode(mut self, alpha_mode: AlphaMode) -> Self {
+    self.alpha_mode = alpha_mode;
+    self
+  }
+
+  pub fn set_depth_write(mut self, depth_write: bool) -> Self {
+    self.depth_write = depth_write;
+    self
+  }
+
   pub fn set_cull_face(mut self, cull_face: bool) -> Self {
     self.cull_face = cull_face;
     self
   }
 
+  pub fn set_double_sided(mut self, double_sided: bool) -> Self {
+    self.double_sided = double_sided;
+    self
+  }
+
   pub fn set_depth_test(mut self, depth_test: bool) -> Self {
     self.depth_test = depth_test;
     self
   }
 
+  pub fn set_depth_func(mut self, depth_func: DepthFunc) -> Self {
+    self.depth_func = depth_func;
+    self
+  }
+
+  /// Decals use a negative offset to win over the surface they lie on
+  pub fn set_polygon_offset(mut self, polygon_offset: Option<(f32, f32)>) -> Self {
+    self.polygon_offset = polygon_offset;
+    self
+  }
+
   pub fn set_draw_mode(mut self, draw_mode: DrawMode) -> Self {
     self.draw_mode = draw_mode;
     self
@@ -59,11 +164,140 @@ impl PbrMaterial {
     self
   }
 
+  pub fn set_color_map_tex_coord(mut self, tex_coord: u32) -> Self {
+    self.color_map_tex_coord = tex_coord;
+    self
+  }
+
+  /// Applied to the texture coordinates before uv_repeating, identity by default
+  pub fn set_color_map_transform(mut self, transform: Matrix3<f32>) -> Self {
+    self.color_map_transform = transform;
+    self
+  }
+
+  pub fn set_normal_map(mut self, normal_map: Option<Index>) -> Self {
+    self.normal_map = normal_map;
+    self
+  }
+
+  pub fn set_normal_map_tex_coord(mut self, tex_coord: u32) -> Self {
+    self.normal_map_tex_coord = tex_coord;
+    self
+  }
+
+  /// Applied to the texture coordinates before uv_repeating, identity by default
+  pub fn set_normal_map_transform(mut self, transform: Matrix3<f32>) -> Self {
+    self.normal_map_transform = transform;
+    self
+  }
+
+  pub fn set_normal_scale(mut self, normal_scale: f32) -> Self {
+    self.normal_scale = normal_scale;
+    self
+  }
+
+  pub fn set_emissive(mut self, emissive: Vector3<f32>) -> Self {
+    self.emissive = emissive;
+    self
+  }
+
+  /// Multiplies the emissive term, values above 1 only survive when tone mapping is on
+  pub fn set_emissive_strength(mut self, emissive_strength: f32) -> Self {
+    self.emissive_strength = emissive_strength;
+    self
+  }
+
+  pub fn set_emissive_map(mut self, emissive_map: Option<Index>) -> Self {
+    self.emissive_map = emissive_map;
+    self
+  }
+
+  pub fn set_emissive_map_tex_coord(mut self, tex_coord: u32) -> Self {
+    self.emissive_map_tex_coord = tex_coord;
+    self
+  }
+
+  /// Applied to the texture coordinates before uv_repeating, identity by default
+  pub fn set_emissive_map_transform(mut self, transform: Matrix3<f32>) -> Self {
+    self.emissive_map_transform = transform;
+    self
+  }
+
+  pub fn set_metallic_roughness_map(mut self, metallic_roughness_map: Option<Index>) -> Self {
+    self.metallic_roughness_map = metallic_roughness_map;
+    self
+  }
+
+  pub fn set_metallic_roughness_map_tex_coord(mut self, tex_coord: u32) -> Self {
+    self.metallic_roughness_map_tex_coord = tex_coord;
+    self
+  }
+
+  /// Applied to the texture coordinates before uv_repeating, identity by default
+  pub fn set_metallic_roughness_map_transform(mut self, transform: Matrix3<f32>) -> Self {
+    self.metallic_roughness_map_transform = transform;
+    self
+  }
+
+  pub fn set_occlusion_map(mut self, occlusion_map: Option<Index>) -> Self {
+    self.occlusion_map = occlusion_map;
+    self
+  }
+
+  pub fn set_occlusion_map_tex_coord(mut self, tex_coord: u32) -> Self {
+    self.occlusion_map_tex_coord = tex_coord;
+    self
+  }
+
+  /// Applied to the texture coordinates before uv_repeating, identity by default
+  pub fn set_occlusion_map_transform(mut self, transform: Matrix3<f32>) -> Self {
+    self.occlusion_map_transform = transform;
+    self
+  }
+
+  pub fn set_occlusion_strength(mut self, occlusion_strength: f32) -> Self {
+    self.occlusion_strength = occlusion_strength;
+    self
+  }
+
   pub fn set_debug_cube_map(mut self, debug_cube_map: Option<Index>) -> Self {
     self.debug_cube_map = debug_cube_map;
     self
   }
 
+  pub fn set_vertex_color(mut self, vertex_color: bool) -> Self {
+    self.vertex_color = vertex_color;
+    self
+  }
+
+  pub fn set_skinning(mut self, skinning: bool) -> Self {
+    self.skinning = skinning;
+    self
+  }
+
+  pub fn set_instanced(mut self, instanced: bool) -> Self {
+    self.instanced = instanced;
+    self
+  }
+
+  /// Pixel size of point primitives, None keeps 1px. Drivers clamp it to their own range
+  pub fn set_point_size(mut self, point_size: Option<f32>) -> Self {
+    self.point_size = point_size;
+    self
+  }
+
+  /// Pixel width of line primitives, see `Context::set_line_width` for the browser limits
+  pub fn set_line_width(mut self, line_width: f32) -> Self {
+    self.line_width = line_width;
+    self
+  }
+
+  /// Samples the renderer environment map for ambient lighting.
+  pub fn set_ibl(mut self, ibl: bool) -> Self {
+    self.ibl = ibl;
+    self
+  }
+
   pub fn set_uv_repeating(mut self, uv_repeating: Vector2<f32>) -> Self {
     self.uv_repeating = uv_repeating;
     self
@@ -72,6 +306,32 @@ impl PbrMaterial {
   pub fn boxed(self) -> Box<Self> {
     Box::new(self)
   }
+
+  fn get_uv2_maps(&self) -> Vec<&'static str> {
+    let mut maps = vec![];
+
+    if self.color_map.is_some() && self.color_map_tex_coord == 1 {
+      maps.push("COLOR_MAP");
+    }
+
+    if self.normal_map.is_some() && self.normal_map_tex_coord == 1 {
+      maps.push("NORMAL_MAP");
+    }
+
+    if self.emissive_map.is_some() && self.emissive_map_tex_coord == 1 {
+      maps.push("EMISSIVE_MAP");
+    }
+
+    if self.metallic_roughness_map.is_some() && self.metallic_roughness_map_tex_coord == 1 {
+      maps.push("METALLIC_ROUGHNESS_MAP");
+    }
+
+    if self.occlusion_map.is_some() && self.occlusion_map_tex_coord == 1 {
+      maps.push("OCCLUSION_MAP");
+    }
+
+    maps
+  }
 }
 
 impl Material for PbrMaterial {
@@ -82,27 +342,136 @@ impl Material for PbrMaterial {
       tag.push_str(":color_map");
     }
 
+    if self.normal_map.is_some() {
+      tag.push_str(":normal_map");
+    }
+
+    if self.emissive_map.is_some() {
+      tag.push_str(":emissive_map");
+    }
+
+    if self.metallic_roughness_map.is_some() {
+      tag.push_str(":metallic_roughness_map");
+    }
+
+    if self.occlusion_map.is_some() {
+      tag.push_str(":occlusion_map");
+    }
+
     if self.debug_cube_map.is_some() {
       tag.push_str(":debug_cube_map");
     }
 
+    if self.vertex_color {
+      tag.push_str(":vertex_color");
+    }
+
+    if self.skinning {
+      tag.push_str(":skinning");
+    }
+
+    if self.double_sided {
+      tag.push_str(":double_sided");
+    }
+
+    if self.instanced {
+      tag.push_str(":instanced");
+    }
+
+    if self.point_size.is_some() {
+      tag.push_str(":point_size");
+    }
+
+    if self.ibl {
+      tag.push_str(":ibl");
+    }
+
+    match self.alpha_mode {
+      AlphaMode::Opaque => {}
+      AlphaMode::Mask(_) => tag.push_str(":alpha_mask"),
+      AlphaMode::Blend => tag.push_str(":alpha_blend"),
+    }
+
+    for map in self.get_uv2_maps() {
+      tag.push_str(&format!(":{}_uv2", map.to_lowercase()));
+    }
+
     tag
   }
 
-  fn create_shader(&self, ctx: &Context) -> Result<Shader> {
+  fn create_shader(&self, ctx: &Context, defines: &[Define]) -> Result<Shader, RenderError> {
     let vert_src = include_str!("./shaders/pbr_vert.glsl");
     let frag_src = include_str!("./shaders/pbr_frag.glsl");
 
-    let mut defines = vec![];
+    let mut defines = defines.to_vec();
+
+    defines.push(Define::int("MAX_POINT_LIGHTS", MAX_POINT_LIGHTS as i32));
 
     if self.color_map.is_some() {
       defines.push(Define::def("USE_COLOR_MAP"));
     }
 
+    if self.normal_map.is_some() {
+      defines.push(Define::def("USE_NORMAL_MAP"));
+    }
+
+    if self.emissive_map.is_some() {
+      defines.push(Define::def("USE_EMISSIVE_MAP"));
+    }
+
+    if self.metallic_roughness_map.is_some() {
+      defines.push(Define::def("USE_METALLIC_ROUGHNESS_MAP"));
+    }
+
+    if self.occlusion_map.is_some() {
+      defines.push(Define::def("USE_OCCLUSION_MAP"));
+    }
+
     if self.debug_cube_map.is_some() {
       defines.push(Define::def("USE_DEBUG_CUBE_MAP"));
     }
 
+    if self.vertex_color {
+      defines.push(Define::def("USE_VERTEX_COLOR"));
+    }
+
+    if self.skinning {
+      defines.push(Define::def("USE_SKINNING"));
+      defines.push(Define::int("MAX_JOINTS", MAX_JOINTS as i32));
+    }
+
+    if self.double_sided {
+      defines.push(Define::def("DOUBLE_SIDED"));
+    }
+
+    if self.instanced {
+      defines.push(Define::def("INSTANCED"));
+    }
+
+    if self.point_size.is_some() {
+      defines.push(Define::def("POINT_SIZE"));
+    }
+
+    if self.ibl {
+      defines.push(Define::def("USE_IBL"));
+    }
+
+    match self.alpha_mode {
+      AlphaMode::Opaque => {}
+      AlphaMode::Mask(_) => defines.push(Define::def("USE_ALPHA_MASK")),
+      AlphaMode::Blend => defines.push(Define::def("USE_ALPHA_BLEND")),
+    }
+
+    let uv2_maps = self.get_uv2_maps();
+
+    if !uv2_maps.is_empty() {
+      defines.push(Define::def("USE_UV2"));
+    }
+
+    for map in uv2_maps {
+      defines.push(Define::new(&format!("{}_UV", map), Some("v_uv2")));
+    }
+
     ctx.create_shader(vert_src, frag_src, &defines)
   }
 
@@ -117,9 +486,33 @@ impl Material for PbrMaterial {
     camera: &Camera,
   ) {
     shader.set_vector3("color", &self.color);
+    shader.set_float("metallic", self.metallic);
+    shader.set_float("roughness", self.roughness);
+    shader.set_float("opacity", self.opacity);
+
+    if let AlphaMode::Mask(cutoff) = self.alpha_mode {
+      shader.set_float("alphaCutoff", cutoff);
+    }
+
+    shader.set_float("normalScale", self.normal_scale);
+    shader.set_vector3("emissive", &self.emissive);
+    shader.set_float("emissiveStrength", self.emissive_strength);
+    shader.set_float("occlusionStrength", self.occlusion_strength);
+    shader.set_vector3("cameraPosition", &camera.get_position());
     shader.set_vector2("uvRepeating", &self.uv_repeating);
-    shader.set_matrix4("projectionMatrix", &camera.projection);
-    shader.set_matrix4("viewMatrix", &camera.view);
+
+    if let Some(point_size) = self.point_size {
+      shader.set_float("pointSize", point_size);
+    }
+
+    shader.set_matrix3("colorMapTransform", &self.color_map_transform);
+    shader.set_matrix3("normalMapTransform", &self.normal_map_transform);
+    shader.set_matrix3("emissiveMapTransform", &self.emissive_map_transform);
+    shader.set_matrix3(
+      "metallicRoughnessMapTransform",
+      &self.metallic_roughness_map_transform,
+    );
+    shader.set_matrix3("occlusionMapTransform", &self.occlusion_map_transform);
     shader.set_matrix4("modelMatrix", &node.matrix_world);
     shader.set_matrix3(
       "normalMatrix",
@@ -141,16 +534,35 @@ impl Material for PbrMaterial {
       &[
         (self.color_map, TextureKind::Texture2d, "colorMap"),
         (self.debug_cube_map, TextureKind::CubeMap, "debugCubeMap"),
+        (self.normal_map, TextureKind::Texture2d, "normalMap"),
+        (self.emissive_map, TextureKind::Texture2d, "emissiveMap"),
+        (self.occlusion_map, TextureKind::Texture2d, "occlusionMap"),
+        (
+          self.metallic_roughness_map,
+          TextureKind::Texture2d,
+          "metallicRoughnessMap",
+        ),
       ],
     );
   }
 
   fn params(&self) -> MaterialParams {
     MaterialParams {
-      cull_face: self.cull_face,
+      cull_face: self.cull_face && !self.double_sided,
       depth_test: self.depth_test,
-      depth_func: DepthFunc::Less,
-      draw_mode: DrawMode::Triangles,
+      depth_func: self.depth_func,
+      depth_write: self.depth_write,
+      polygon_offset: self.polygon_offset,
+      draw_mode: self.draw_mode,
+      line_width: self.line_width,
     }
   }
+
+  fn is_transparent(&self) -> bool {
+    self.alpha_mode == AlphaMode::Blend
+  }
+
+  fn is_alpha_masked(&self) -> bool {
+    matches!(self.alpha_mode, AlphaMode::Mask(_))
+  }
 }
@@ -0,0 +1,91 @@
+use na::Vector3;
+
+use super::material::{Material, MaterialParams};
+use crate::renderer::webgl::context::{Context, DepthFunc, DrawMode};
+use crate::renderer::webgl::define::Define;
+use crate::renderer::webgl::renderer::{Camera, Images, RenderError, Samplers, Textures};
+use crate::renderer::webgl::shader::Shader;
+use crate::renderer::webgl::skin::MAX_JOINTS;
+use crate::scene::node::Node;
+
+/// Flat color output used by the picking pass to encode draw ids
+#[derive(Debug)]
+pub struct PickMaterial {
+  color: Vector3<f32>,
+  instanced: bool,
+  skinning: bool,
+}
+
+impl PickMaterial {
+  pub fn new(color: Vector3<f32>, instanced: bool, skinning: bool) -> Self {
+    PickMaterial {
+      color,
+      instanced,
+      skinning,
+    }
+  }
+
+  pub fn boxed(self) -> Box<Self> {
+    Box::new(self)
+  }
+}
+
+impl Material for PickMaterial {
+  fn get_tag(&self) -> String {
+    let mut tag = String::from("pick");
+
+    if self.instanced {
+      tag.push_str(":instanced");
+    }
+
+    if self.skinning {
+      tag.push_str(":skinning");
+    }
+
+    tag
+  }
+
+  fn create_shader(&self, ctx: &Context, defines: &[Define]) -> Result<Shader, RenderError> {
+    let vert_src = include_str!("./shaders/pick_vert.glsl");
+    let frag_src = include_str!("./shaders/pick_frag.glsl");
+
+    let mut defines = defines.to_vec();
+
+    if self.instanced {
+      defines.push(Define::def("INSTANCED"));
+    }
+
+    if self.skinning {
+      defines.push(Define::def("USE_SKINNING"));
+      defines.push(Define::int("MAX_JOINTS", MAX_JOINTS as i32));
+    }
+
+    ctx.create_shader(vert_src, frag_src, &defines)
+  }
+
+  fn setup_shader(
+    &self,
+    _ctx: &Context,
+    _images: &Images,
+    _textures: &Textures,
+    _samplers: &Samplers,
+    shader: &Shader,
+    node: &Node,
+    _camera: &Camera,
+  ) {
+    shader.set_vector3("pickColor", &self.color);
+    shader.set_matrix4("modelMatrix", &node.matrix_world);
+  }
+
+  fn params(&self) -> MaterialParams {
+    MaterialParams {
+      cull_face: false,
+      depth_test: true,
+      depth_func: DepthFunc::Less,
+      depth_write: true,
+      polygon_offset: None,
+      draw_mode: DrawMode::Triangles,
+      line_width: 1.0,
+    }
+  }
+}
@@ -0,0 +1,32 @@
+varying vec3 v_normal;
+varying vec2 v_uv;
+
+#ifdef DEBUG_TANGENTS
+varying vec4 v_tangent;
+#endif
+
+#ifdef DEBUG_VERTEX_COLORS
+varying vec4 v_color;
+#endif
+
+// maps a unit vector from [-1, 1] to a displayable color
+vec3 encodeDirection(vec3 value) {
+  return value * 0.5 + 0.5;
+}
+
+void main() {
+#if defined(DEBUG_NORMALS)
+  vec3 color = encodeDirection(normalize(v_normal));
+#elif defined(DEBUG_UVS)
+  vec3 color = vec3(fract(v_uv), 0.0);
+#elif defined(DEBUG_TANGENTS)
+  // missing tangents come through zeroed and show up black
+  vec3 color = dot(v_tangent.xyz, v_tangent.xyz) > 0.0
+    ? encodeDirection(normalize(v_tangent.xyz))
+    : vec3(0.0);
+#else
+  vec3 color = v_color.rgb;
+#endif
+
+  gl_FragColor = vec4(color, 1.0);
+}
@@ -1,32 +1,347 @@
+#define PI 3.14159265359
+
 uniform vec3 color;
+uniform float metallic;
+uniform float roughness;
+uniform float opacity;
+uniform vec3 emissive;
+uniform float emissiveStrength;
+
+#ifdef USE_ALPHA_MASK
+uniform float alphaCutoff;
+#endif
 uniform vec2 uvRepeating;
+uniform vec3 cameraPosition;
+
+uniform vec3 lightDirection;
+uniform vec3 lightColor;
+uniform float lightIntensity;
+
+uniform vec3 pointLightPositions[MAX_POINT_LIGHTS];
+uniform vec3 pointLightColors[MAX_POINT_LIGHTS];
+uniform float pointLightRanges[MAX_POINT_LIGHTS];
+uniform int pointLightCount;
 
 varying vec3 v_position;
+varying vec3 v_world_position;
 varying vec3 v_normal;
 varying vec2 v_uv;
 
+#ifdef USE_UV2
+varying vec2 v_uv2;
+#endif
+
+#ifndef COLOR_MAP_UV
+#define COLOR_MAP_UV v_uv
+#endif
+
+#ifndef NORMAL_MAP_UV
+#define NORMAL_MAP_UV v_uv
+#endif
+
+#ifndef EMISSIVE_MAP_UV
+#define EMISSIVE_MAP_UV v_uv
+#endif
+
+#ifndef METALLIC_ROUGHNESS_MAP_UV
+#define METALLIC_ROUGHNESS_MAP_UV v_uv
+#endif
+
+#ifndef OCCLUSION_MAP_UV
+#define OCCLUSION_MAP_UV v_uv
+#endif
+
 #ifdef USE_COLOR_MAP
 uniform sampler2D colorMap;
+uniform mat3 colorMapTransform;
+#endif
+
+#ifdef USE_NORMAL_MAP
+uniform sampler2D normalMap;
+uniform mat3 normalMapTransform;
+uniform float normalScale;
+varying vec4 v_tangent;
+#endif
+
+#ifdef USE_EMISSIVE_MAP
+uniform sampler2D emissiveMap;
+uniform mat3 emissiveMapTransform;
+#endif
+
+#ifdef USE_METALLIC_ROUGHNESS_MAP
+uniform sampler2D metallicRoughnessMap;
+uniform mat3 metallicRoughnessMapTransform;
+#endif
+
+#ifdef USE_OCCLUSION_MAP
+uniform sampler2D occlusionMap;
+uniform mat3 occlusionMapTransform;
+uniform float occlusionStrength;
+#endif
+
+#ifdef USE_VERTEX_COLOR
+varying vec4 v_color;
 #endif
 
 #ifdef USE_DEBUG_CUBE_MAP
 uniform samplerCube debugCubeMap;
 #endif
 
+#ifdef USE_SHADOW_MAP
+uniform sampler2D shadowMap;
+uniform float shadowBias;
+uniform float shadowMapSize;
+varying vec4 v_shadow_position;
+#endif
+
+#ifdef USE_IBL
+#define ENVIRONMENT_MAX_LOD 8.0
+
+uniform samplerCube environmentMap;
+#endif
+
+// per map transform from KHR_texture_transform, then the material wide tiling
+vec2 getMapUv(mat3 transform, vec2 uv) {
+  return (transform * vec3(uv, 1.0)).xy * uvRepeating;
+}
+
+// color textures are authored in sRGB, lighting happens in linear space
+vec3 decodeColor(vec3 value) {
+#ifdef OUTPUT_GAMMA
+  return pow(value, vec3(2.2));
+#else
+  return value;
+#endif
+}
+
+vec3 encodeColor(vec3 value) {
+#ifdef OUTPUT_GAMMA
+  return pow(value, vec3(1.0 / 2.2));
+#else
+  return value;
+#endif
+}
+
+#ifdef TONEMAP
+vec3 toneMap(vec3 value) {
+#ifdef TONEMAP_ACES
+  // Narkowicz fit of the ACES filmic curve
+  return clamp((value * (2.51 * value + 0.03)) / (value * (2.43 * value + 0.59) + 0.14), 0.0, 1.0);
+#else
+  return value / (value + vec3(1.0));
+#endif
+}
+#endif
+
+float distributionGGX(float NdotH, float alpha) {
+  float alpha2 = alpha * alpha;
+  float d = NdotH * NdotH * (alpha2 - 1.0) + 1.0;
+
+  return alpha2 / (PI * d * d);
+}
+
+float geometrySmith(float NdotV, float NdotL, float rough) {
+  float k = (rough + 1.0) * (rough + 1.0) / 8.0;
+
+  return NdotV / (NdotV * (1.0 - k) + k) * NdotL / (NdotL * (1.0 - k) + k);
+}
+
+vec3 fresnelSchlick(float VdotH, vec3 f0) {
+  return f0 + (1.0 - f0) * pow(1.0 - VdotH, 5.0);
+}
+
+vec3 brdf(vec3 N, vec3 V, vec3 L, vec3 albedo, float metal, float rough) {
+  vec3 H = normalize(V + L);
+
+  float NdotL = max(dot(N, L), 0.0);
+  float NdotV = max(dot(N, V), 0.0001);
+  float NdotH = max(dot(N, H), 0.0);
+  float VdotH = max(dot(V, H), 0.0);
+
+  vec3 f0 = mix(vec3(0.04), albedo, metal);
+  vec3 F = fresnelSchlick(VdotH, f0);
+  float D = distributionGGX(NdotH, rough * rough);
+  float G = geometrySmith(NdotV, NdotL, rough);
+
+  vec3 specular = D * G * F / max(4.0 * NdotV * NdotL, 0.0001);
+  vec3 kd = (vec3(1.0) - F) * (1.0 - metal);
+
+  return kd * albedo / PI + specular;
+}
+
+#ifdef USE_IBL
+// analytic fit of the split-sum environment BRDF
+vec3 environmentBrdf(vec3 f0, float rough, float NdotV) {
+  vec4 r = rough * vec4(-1.0, -0.0275, -0.572, 0.022) + vec4(1.0, 0.0425, 1.04, -0.04);
+  float a004 = min(r.x * r.x, exp2(-9.28 * NdotV)) * r.x + r.y;
+  vec2 AB = vec2(-1.04, 1.04) * a004 + r.zw;
+
+  return f0 * AB.x + AB.y;
+}
+
+vec3 environmentLight(vec3 N, vec3 V, vec3 albedo, float metal, float rough) {
+  float NdotV = max(dot(N, V), 0.0001);
+  vec3 R = reflect(-V, N);
+
+  vec3 f0 = mix(vec3(0.04), albedo, metal);
+  vec3 F = environmentBrdf(f0, rough, NdotV);
+  vec3 kd = (vec3(1.0) - F) * (1.0 - metal);
+
+  // the blurriest mip stands in for an irradiance map
+  vec3 irradiance = decodeColor(textureCube(environmentMap, N, ENVIRONMENT_MAX_LOD).rgb);
+  vec3 prefiltered = decodeColor(textureCube(environmentMap, R, rough * ENVIRONMENT_MAX_LOD).rgb);
+
+  return kd * albedo * irradiance + prefiltered * F;
+}
+#endif
+
+#ifdef USE_SHADOW_MAP
+// 2x2 PCF, fragments outside the light frustum stay lit
+float getShadow() {
+  vec3 coords = v_shadow_position.xyz / v_shadow_position.w * 0.5 + 0.5;
+
+  if (coords.x < 0.0 || coords.x > 1.0 || coords.y < 0.0 || coords.y > 1.0 || coords.z > 1.0) {
+    return 1.0;
+  }
+
+  float texel = 1.0 / shadowMapSize;
+  float lit = 0.0;
+
+  for (int x = 0; x < 2; x++) {
+    for (int y = 0; y < 2; y++) {
+      vec2 offset = (vec2(float(x), float(y)) - 0.5) * texel;
+      float depth = texture2D(shadowMap, coords.xy + offset).r;
+
+      lit += coords.z - shadowBias > depth ? 0.0 : 1.0;
+    }
+  }
+
+  return lit * 0.25;
+}
+#endif
+
+float getDistanceAttenuation(float distance, float range) {
+  float attenuation = 1.0 / max(distance * distance, 0.0001);
+
+  if (range > 0.0) {
+    float ratio = distance / range;
+    attenuation *= clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+  }
+
+  return attenuation;
+}
+
 void main() {
   vec3 normal = normalize(v_normal);
 
+#ifdef DOUBLE_SIDED
+  normal = gl_FrontFacing ? normal : -normal;
+#endif
+
+#ifdef USE_NORMAL_MAP
+  // geometry without tangents leaves the attribute zeroed, keep the geometric normal then
+  if (dot(v_tangent.xyz, v_tangent.xyz) > 0.0) {
+    vec3 T = normalize(v_tangent.xyz - dot(v_tangent.xyz, normal) * normal);
+    vec3 B = cross(normal, T) * (v_tangent.w < 0.0 ? -1.0 : 1.0);
+    vec3 mapNormal = texture2D(normalMap, getMapUv(normalMapTransform, NORMAL_MAP_UV)).xyz * 2.0 - 1.0;
+
+    mapNormal.xy *= normalScale;
+    normal = normalize(mat3(T, B, normal) * mapNormal);
+  }
+#endif
+  vec3 viewDirection = normalize(cameraPosition - v_world_position);
+  vec3 L = -lightDirection;
+  vec3 radiance = lightColor * lightIntensity;
+
   vec3 albedo = color;
+  float alpha = opacity;
 
 #ifdef USE_COLOR_MAP
-  albedo = texture2D(colorMap, v_uv * uvRepeating).rgb;
+  vec4 texel = texture2D(colorMap, getMapUv(colorMapTransform, COLOR_MAP_UV));
+  albedo = decodeColor(texel.rgb);
+  alpha *= texel.a;
+#endif
+
+#ifdef USE_VERTEX_COLOR
+  albedo *= v_color.rgb;
+  alpha *= v_color.a;
+#endif
+
+#ifdef USE_ALPHA_MASK
+  if (alpha < alphaCutoff) {
+    discard;
+  }
+#endif
+
+#ifndef USE_ALPHA_BLEND
+  alpha = 1.0;
+#endif
+
+  float metal = metallic;
+  float rough = roughness;
+
+#ifdef USE_METALLIC_ROUGHNESS_MAP
+  vec4 metallicRoughness = texture2D(metallicRoughnessMap, getMapUv(metallicRoughnessMapTransform, METALLIC_ROUGHNESS_MAP_UV));
+  metal *= metallicRoughness.b;
+  rough *= metallicRoughness.g;
+#endif
+
+  float NdotL = max(dot(normal, L), 0.0);
+
+#ifdef USE_SHADOW_MAP
+  radiance *= getShadow();
+#endif
+
+  vec3 diffuse = brdf(normal, viewDirection, L, albedo, metal, rough) * radiance * NdotL * PI;
+
+  for (int i = 0; i < MAX_POINT_LIGHTS; i++) {
+    if (i >= pointLightCount) {
+      break;
+    }
+
+    vec3 toLight = pointLightPositions[i] - v_world_position;
+    float distance = length(toLight);
+    vec3 pointL = toLight / max(distance, 0.0001);
+    float pointNdotL = max(dot(normal, pointL), 0.0);
+    vec3 pointRadiance = pointLightColors[i] * getDistanceAttenuation(distance, pointLightRanges[i]);
+
+    diffuse += brdf(normal, viewDirection, pointL, albedo, metal, rough) * pointRadiance * pointNdotL * PI;
+  }
+
+#ifdef USE_IBL
+  vec3 ambient = environmentLight(normal, viewDirection, albedo, metal, rough);
+
+#ifdef USE_OCCLUSION_MAP
+  // occlusion is stored in the red channel and only darkens indirect light
+  float occlusion = texture2D(occlusionMap, getMapUv(occlusionMapTransform, OCCLUSION_MAP_UV)).r;
+  ambient *= mix(1.0, occlusion, occlusionStrength);
+#endif
+
+  diffuse += ambient;
+#endif
+
+  vec3 emission = emissive;
+
+#ifdef USE_EMISSIVE_MAP
+  emission *= decodeColor(texture2D(emissiveMap, getMapUv(emissiveMapTransform, EMISSIVE_MAP_UV)).rgb);
 #endif
 
-  vec3 diffuse = dot(vec3(0.0, 1.0, 0.0), normal) * albedo;
+  emission *= emissiveStrength;
+
+#ifndef TONEMAP
+  // without tone mapping anything above 1 would only saturate the other channels
+  emission = min(emission, vec3(1.0));
+#endif
+
+  diffuse += emission;
 
 #ifdef USE_DEBUG_CUBE_MAP
-  diffuse = textureCube(debugCubeMap, normalize(v_position)).rgb;
+  diffuse = decodeColor(textureCube(debugCubeMap, normalize(v_position)).rgb);
+#endif
+
+#ifdef TONEMAP
+  diffuse = toneMap(diffuse);
 #endif
 
-  gl_FragColor = vec4(diffuse, 1.0);
+  gl_FragColor = vec4(encodeColor(diffuse), alpha);
 }
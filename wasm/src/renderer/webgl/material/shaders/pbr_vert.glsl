@@ -2,18 +2,111 @@ attribute vec3 position;
 attribute vec3 normal;
 attribute vec2 uv;
 
+#ifdef INSTANCED
+attribute mat4 instanceMatrix;
+#endif
+
+#ifdef USE_UV2
+attribute vec2 uv2;
+varying vec2 v_uv2;
+#endif
+
+#ifdef USE_NORMAL_MAP
+attribute vec4 tangent;
+#endif
+
+#ifdef USE_VERTEX_COLOR
+attribute vec4 color;
+varying vec4 v_color;
+#endif
+
+#ifdef USE_SKINNING
+attribute vec4 joints;
+attribute vec4 weights;
+
+uniform mat4 jointMatrices[MAX_JOINTS];
+#endif
+
+#ifdef USE_CAMERA_BLOCK
+layout(std140) uniform Camera {
+  mat4 projectionMatrix;
+  mat4 viewMatrix;
+};
+#else
 uniform mat4 projectionMatrix;
 uniform mat4 viewMatrix;
+#endif
 uniform mat4 modelMatrix;
 uniform mat3 normalMatrix;
 
+#ifdef POINT_SIZE
+uniform float pointSize;
+#endif
+
+// the depth pre-pass relies on every program computing bit-identical positions
+invariant gl_Position;
+
 varying vec3 v_position;
+varying vec3 v_world_position;
 varying vec3 v_normal;
 varying vec2 v_uv;
 
+#ifdef USE_NORMAL_MAP
+varying vec4 v_tangent;
+#endif
+
+#ifdef USE_SHADOW_MAP
+uniform mat4 shadowMatrix;
+varying vec4 v_shadow_position;
+#endif
+
 void main() {
-  gl_Position = projectionMatrix * viewMatrix * modelMatrix * vec4(position, 1.0);
-  v_normal = normalMatrix * normal;
+#ifdef INSTANCED
+  mat4 model = modelMatrix * instanceMatrix;
+  mat3 normalModel = normalMatrix * mat3(instanceMatrix);
+#else
+  mat4 model = modelMatrix;
+  mat3 normalModel = normalMatrix;
+#endif
+
+#ifdef USE_SKINNING
+  // joint matrices are relative to the skinned node, so they stack on the model matrix
+  mat4 skinMatrix = weights.x * jointMatrices[int(joints.x)] +
+    weights.y * jointMatrices[int(joints.y)] +
+    weights.z * jointMatrices[int(joints.z)] +
+    weights.w * jointMatrices[int(joints.w)];
+
+  model = model * skinMatrix;
+  normalModel = normalModel * mat3(skinMatrix);
+#endif
+
+  vec4 worldPosition = model * vec4(position, 1.0);
+
+  gl_Position = projectionMatrix * viewMatrix * worldPosition;
+  // point size is undefined unless written, only point primitives read it
+#ifdef POINT_SIZE
+  gl_PointSize = pointSize;
+#else
+  gl_PointSize = 1.0;
+#endif
+  v_world_position = worldPosition.xyz;
+  v_normal = normalModel * normal;
   v_position = position;
   v_uv = uv;
+
+#ifdef USE_UV2
+  v_uv2 = uv2;
+#endif
+
+#ifdef USE_VERTEX_COLOR
+  v_color = color;
+#endif
+
+#ifdef USE_NORMAL_MAP
+  v_tangent = vec4(mat3(model) * tangent.xyz, tangent.w);
+#endif
+
+#ifdef USE_SHADOW_MAP
+  v_shadow_position = shadowMatrix * worldPosition;
+#endif
 }
@@ -0,0 +1,5 @@
+uniform vec3 pickColor;
+
+void main() {
+  gl_FragColor = vec4(pickColor, 1.0);
+}
@@ -0,0 +1,40 @@
+attribute vec3 position;
+
+#ifdef INSTANCED
+attribute mat4 instanceMatrix;
+#endif
+
+#ifdef USE_SKINNING
+attribute vec4 joints;
+attribute vec4 weights;
+
+uniform mat4 jointMatrices[MAX_JOINTS];
+#endif
+
+#ifdef USE_CAMERA_BLOCK
+layout(std140) uniform Camera {
+  mat4 projectionMatrix;
+  mat4 viewMatrix;
+};
+#else
+uniform mat4 projectionMatrix;
+uniform mat4 viewMatrix;
+#endif
+uniform mat4 modelMatrix;
+
+void main() {
+#ifdef INSTANCED
+  mat4 model = modelMatrix * instanceMatrix;
+#else
+  mat4 model = modelMatrix;
+#endif
+
+#ifdef USE_SKINNING
+  model = model * (weights.x * jointMatrices[int(joints.x)] +
+    weights.y * jointMatrices[int(joints.y)] +
+    weights.z * jointMatrices[int(joints.z)] +
+    weights.w * jointMatrices[int(joints.w)]);
+#endif
+
+  gl_Position = projectionMatrix * viewMatrix * model * vec4(position, 1.0);
+}
@@ -0,0 +1,75 @@
+uniform vec3 color;
+uniform float opacity;
+
+#ifdef USE_ALPHA_MASK
+uniform float alphaCutoff;
+#endif
+uniform vec2 uvRepeating;
+
+varying vec2 v_uv;
+
+#ifdef USE_UV2
+varying vec2 v_uv2;
+#endif
+
+#ifndef COLOR_MAP_UV
+#define COLOR_MAP_UV v_uv
+#endif
+
+#ifdef USE_COLOR_MAP
+uniform sampler2D colorMap;
+uniform mat3 colorMapTransform;
+#endif
+
+#ifdef USE_VERTEX_COLOR
+varying vec4 v_color;
+#endif
+
+// per map transform from KHR_texture_transform, then the material wide tiling
+vec2 getMapUv(mat3 transform, vec2 uv) {
+  return (transform * vec3(uv, 1.0)).xy * uvRepeating;
+}
+
+vec3 decodeColor(vec3 value) {
+#ifdef OUTPUT_GAMMA
+  return pow(value, vec3(2.2));
+#else
+  return value;
+#endif
+}
+
+vec3 encodeColor(vec3 value) {
+#ifdef OUTPUT_GAMMA
+  return pow(value, vec3(1.0 / 2.2));
+#else
+  return value;
+#endif
+}
+
+void main() {
+  vec3 albedo = color;
+  float alpha = opacity;
+
+#ifdef USE_COLOR_MAP
+  vec4 texel = texture2D(colorMap, getMapUv(colorMapTransform, COLOR_MAP_UV));
+  albedo *= decodeColor(texel.rgb);
+  alpha *= texel.a;
+#endif
+
+#ifdef USE_VERTEX_COLOR
+  albedo *= v_color.rgb;
+  alpha *= v_color.a;
+#endif
+
+#ifdef USE_ALPHA_MASK
+  if (alpha < alphaCutoff) {
+    discard;
+  }
+#endif
+
+#ifndef USE_ALPHA_BLEND
+  alpha = 1.0;
+#endif
+
+  gl_FragColor = vec4(encodeColor(albedo), alpha);
+}
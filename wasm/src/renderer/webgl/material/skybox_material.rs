@@ -1,11 +1,10 @@
 use generational_arena::Index;
 use na::Matrix4;
 
-use anyhow::Result;
-
 use super::material::{bind_several_maps, Material, MaterialParams};
 use crate::renderer::webgl::context::{Context, DepthFunc, DrawMode, TextureKind};
-use crate::renderer::webgl::renderer::{Camera, Images, Samplers, Textures};
+use crate::renderer::webgl::define::Define;
+use crate::renderer::webgl::renderer::{Camera, Images, RenderError, Samplers, Textures};
 use crate::renderer::webgl::shader::Shader;
 use crate::scene::node::Node;
 
@@ -29,11 +28,11 @@ impl Material for SkyboxMaterial {
     String::from("skybox")
   }
 
-  fn create_shader(&self, ctx: &Context) -> Result<Shader> {
+  fn create_shader(&self, ctx: &Context, defines: &[Define]) -> Result<Shader, RenderError> {
     let vert_src = include_str!("./shaders/skybox_vert.glsl");
     let frag_src = include_str!("./shaders/skybox_frag.glsl");
 
-    ctx.create_shader(vert_src, frag_src, &[])
+    ctx.create_shader(vert_src, frag_src, defines)
   }
 
   fn setup_shader(
@@ -76,7 +75,10 @@ impl Material for SkyboxMaterial {
       cull_face: true,
       depth_test: true,
       depth_func: DepthFunc::Lequal,
+      depth_write: true,
+      polygon_offset: None,
       draw_mode: DrawMode::Triangles,
+      line_width: 1.0,
     }
   }
 }
@@ -0,0 +1,289 @@
+use generational_arena::Index;
+use na::{Matrix3, Vector2, Vector3};
+
+use super::material::{bind_several_maps, AlphaMode, Material, MaterialParams};
+use crate::renderer::webgl::context::{Context, DepthFunc, DrawMode, TextureKind};
+use crate::renderer::webgl::define::Define;
+use crate::renderer::webgl::renderer::{Camera, Images, RenderError, Samplers, Textures};
+use crate::renderer::webgl::shader::Shader;
+use crate::renderer::webgl::skin::MAX_JOINTS;
+use crate::scene::node::Node;
+
+/// Flat color unaffected by lights, shares the PBR vertex stage
+#[derive(Debug)]
+pub struct UnlitMaterial {
+  color: Vector3<f32>,
+  opacity: f32,
+  alpha_mode: AlphaMode,
+  color_map: Option<Index>,
+  color_map_tex_coord: u32,
+  color_map_transform: Matrix3<f32>,
+  vertex_color: bool,
+  skinning: bool,
+  instanced: bool,
+  point_size: Option<f32>,
+  line_width: f32,
+  uv_repeating: Vector2<f32>,
+  double_sided: bool,
+  depth_test: bool,
+  depth_func: DepthFunc,
+  depth_write: bool,
+  polygon_offset: Option<(f32, f32)>,
+}
+
+impl Default for UnlitMaterial {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl UnlitMaterial {
+  pub fn new() -> Self {
+    UnlitMaterial {
+      color: Vector3::new(1.0, 1.0, 1.0),
+      opacity: 1.0,
+      alpha_mode: AlphaMode::Opaque,
+      color_map: None,
+      color_map_tex_coord: 0,
+      color_map_transform: Matrix3::identity(),
+      vertex_color: false,
+      skinning: false,
+      instanced: false,
+      point_size: None,
+      line_width: 1.0,
+      uv_repeating: Vector2::new(1.0, 1.0),
+      double_sided: false,
+      depth_test: true,
+      depth_func: DepthFunc::Less,
+      depth_write: true,
+      polygon_offset: None,
+    }
+  }
+
+  pub fn set_color(mut self, color: Vector3<f32>) -> Self {
+    self.color = color;
+    self
+  }
+
+  pub fn set_opacity(mut self, opacity: f32) -> Self {
+    self.opacity = opacity;
+    self
+  }
+
+  pub fn set_alpha_mode(mut self, alpha_mode: AlphaMode) -> Self {
+    self.alpha_mode = alpha_mode;
+    self
+  }
+
+  pub fn set_color_map(mut self, color_map: Option<Index>) -> Self {
+    self.color_map = color_map;
+    self
+  }
+
+  pub fn set_color_map_tex_coord(mut self, tex_coord: u32) -> Self {
+    self.color_map_tex_coord = tex_coord;
+    self
+  }
+
+  /// Applied to the texture coordinates before uv_repeating, identity by default
+  pub fn set_color_map_transform(mut self, transform: Matrix3<f32>) -> Self {
+    self.color_map_transform = transform;
+    self
+  }
+
+  pub fn set_vertex_color(mut self, vertex_color: bool) -> Self {
+    self.vertex_color = vertex_color;
+    self
+  }
+
+  pub fn set_skinning(mut self, skinning: bool) -> Self {
+    self.skinning = skinning;
+    self
+  }
+
+  pub fn set_instanced(mut self, instanced: bool) -> Self {
+    self.instanced = instanced;
+    self
+  }
+
+  /// Pixel size of point primitives, None keeps 1px. Drivers clamp it to their own range
+  pub fn set_point_size(mut self, point_size: Option<f32>) -> Self {
+    self.point_size = point_size;
+    self
+  }
+
+  /// Pixel width of line primitives, see `Context::set_line_width` for the browser limits
+  pub fn set_line_width(mut self, line_width: f32) -> Self {
+    self.line_width = line_width;
+    self
+  }
+
+  pub fn set_uv_repeating(mut self, uv_repeating: Vector2<f32>) -> Self {
+    self.uv_repeating = uv_repeating;
+    self
+  }
+
+  pub fn set_double_sided(mut self, double_sided: bool) -> Self {
+    self.double_sided = double_sided;
+    self
+  }
+
+  pub fn set_depth_test(mut self, depth_test: bool) -> Self {
+    self.depth_test = depth_test;
+    self
+  }
+
+  pub fn set_depth_write(mut self, depth_write: bool) -> Self {
+    self.depth_write = depth_write;
+    self
+  }
+
+  pub fn set_depth_func(mut self, depth_func: DepthFunc) -> Self {
+    self.depth_func = depth_func;
+    self
+  }
+
+  pub fn set_polygon_offset(mut self, polygon_offset: Option<(f32, f32)>) -> Self {
+    self.polygon_offset = polygon_offset;
+    self
+  }
+
+  pub fn boxed(self) -> Box<Self> {
+    Box::new(self)
+  }
+}
+
+impl Material for UnlitMaterial {
+  fn get_tag(&self) -> String {
+    let mut tag = String::from("unlit");
+
+    if self.color_map.is_some() {
+      tag.push_str(":color_map");
+
+      if self.color_map_tex_coord == 1 {
+        tag.push_str(":color_map_uv2");
+      }
+    }
+
+    if self.vertex_color {
+      tag.push_str(":vertex_color");
+    }
+
+    if self.skinning {
+      tag.push_str(":skinning");
+    }
+
+    if self.instanced {
+      tag.push_str(":instanced");
+    }
+
+    if self.point_size.is_some() {
+      tag.push_str(":point_size");
+    }
+
+    match self.alpha_mode {
+      AlphaMode::Opaque => {}
+      AlphaMode::Mask(_) => tag.push_str(":alpha_mask"),
+      AlphaMode::Blend => tag.push_str(":alpha_blend"),
+    }
+
+    tag
+  }
+
+  fn create_shader(&self, ctx: &Context, defines: &[Define]) -> Result<Shader, RenderError> {
+    let vert_src = include_str!("./shaders/pbr_vert.glsl");
+    let frag_src = include_str!("./shaders/unlit_frag.glsl");
+
+    let mut defines = defines.to_vec();
+
+    if self.color_map.is_some() {
+      defines.push(Define::def("USE_COLOR_MAP"));
+
+      if self.color_map_tex_coord == 1 {
+        defines.push(Define::def("USE_UV2"));
+        defines.push(Define::new("COLOR_MAP_UV", Some("v_uv2")));
+      }
+    }
+
+    if self.vertex_color {
+      defines.push(Define::def("USE_VERTEX_COLOR"));
+    }
+
+    if self.skinning {
+      defines.push(Define::def("USE_SKINNING"));
+      defines.push(Define::int("MAX_JOINTS", MAX_JOINTS as i32));
+    }
+
+    if self.instanced {
+      defines.push(Define::def("INSTANCED"));
+    }
+
+    if self.point_size.is_some() {
+      defines.push(Define::def("POINT_SIZE"));
+    }
+
+    match self.alpha_mode {
+      AlphaMode::Opaque => {}
+      AlphaMode::Mask(_) => defines.push(Define::def("USE_ALPHA_MASK")),
+      AlphaMode::Blend => defines.push(Define::def("USE_ALPHA_BLEND")),
+    }
+
+    ctx.create_shader(vert_src, frag_src, &defines)
+  }
+
+  fn setup_shader(
+    &self,
+    ctx: &Context,
+    images: &Images,
+    textures: &Textures,
+    samplers: &Samplers,
+    shader: &Shader,
+    node: &Node,
+    _camera: &Camera,
+  ) {
+    shader.set_vector3("color", &self.color);
+    shader.set_float("opacity", self.opacity);
+
+    if let AlphaMode::Mask(cutoff) = self.alpha_mode {
+      shader.set_float("alphaCutoff", cutoff);
+    }
+
+    shader.set_vector2("uvRepeating", &self.uv_repeating);
+
+    if let Some(point_size) = self.point_size {
+      shader.set_float("pointSize", point_size);
+    }
+
+    shader.set_matrix3("colorMapTransform", &self.color_map_transform);
+    shader.set_matrix4("modelMatrix", &node.matrix_world);
+
+    bind_several_maps(
+      ctx,
+      images,
+      textures,
+      samplers,
+      shader,
+      &[(self.color_map, TextureKind::Texture2d, "colorMap")],
+    );
+  }
+
+  fn params(&self) -> MaterialParams {
+    MaterialParams {
+      cull_face: !self.double_sided,
+      depth_test: self.depth_test,
+      depth_func: self.depth_func,
+      depth_write: self.depth_write,
+      polygon_offset: self.polygon_offset,
+      draw_mode: DrawMode::Triangles,
+      line_width: self.line_width,
+    }
+  }
+
+  fn is_transparent(&self) -> bool {
+    self.alpha_mode == AlphaMode::Blend
+  }
+
+  fn is_alpha_masked(&self) -> bool {
+    matches!(self.alpha_mode, AlphaMode::Mask(_))
+  }
+}
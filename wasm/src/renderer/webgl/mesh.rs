@@ -1,13 +1,15 @@
 use generational_arena::Index;
 use log::info;
-use na::{Point2, Point3, Vector3};
+use na::{Matrix4, Point2, Point3, Vector3};
 use ncollide3d::procedural::{IndexBuffer, TriMesh};
 use ncollide3d::shape::{Ball, Cuboid};
 use ncollide3d::transformation::ToTriMesh;
 use std::slice;
 
-use super::context::{BufferItem, BufferTarget, BufferUsage};
-use super::renderer::{Accessor, Attributes, Geometry, Mesh, Primitive, Renderer};
+use super::context::{BufferItem, BufferTarget, BufferUsage, TypedArrayKind};
+use super::renderer::{
+  Aabb, Accessor, Attributes, BoundingSphere, Geometry, Instances, Mesh, Primitive, Renderer,
+};
 use super::shader::{AttributeName, AttributeOptions};
 
 impl Renderer {
@@ -20,10 +22,16 @@ impl Renderer {
   ) -> Index {
     let buffer = self.insert_buffer(target, BufferUsage::StaticDraw, data);
     let attribute_options = AttributeOptions::new(T::array_kind(), item_size);
+    let data = match target {
+      BufferTarget::ElementArrayBuffer => Some(as_bytes(data).to_vec()),
+      BufferTarget::ArrayBuffer if self.retain_vertex_data => Some(as_bytes(data).to_vec()),
+      BufferTarget::ArrayBuffer => None,
+    };
     let accessor = Accessor {
       buffer,
       count,
       options: attribute_options,
+      data,
     };
 
     self.insert_accessor(accessor)
@@ -33,6 +41,8 @@ impl Renderer {
     tri_mesh.unify_index_buffer();
 
     let mut attributes = Attributes::new();
+    let bounds = Aabb::from_points(tri_mesh.coords.iter().map(|p| &p.coords));
+    let bounding_sphere = BoundingSphere::from_points(tri_mesh.coords.iter().map(|p| &p.coords));
 
     attributes.insert(
       AttributeName::Position,
@@ -81,9 +91,83 @@ impl Renderer {
     self.insert_geometry(Geometry {
       attributes,
       indices: Some(indices),
+      bounds,
+      bounding_sphere,
+      instances: None,
+      wireframe: None,
+      draw_mode: None,
     })
   }
 
+  /// Returns the cached bounds, scanning CPU-side position data the first time
+  pub fn compute_geometry_bounds(&mut self, handle: Index) -> Option<Aabb> {
+    let geometry = self.geometries.get(handle)?;
+
+    if let Some(bounds) = &geometry.bounds {
+      return Some(*bounds);
+    }
+
+    let points = self.read_positions(handle)?;
+    let bounds = Aabb::from_points(&points)?;
+    let geometry = self.geometries.get_mut(handle)?;
+
+    geometry.bounds = Some(bounds);
+    geometry.bounding_sphere = BoundingSphere::from_points(&points);
+
+    Some(bounds)
+  }
+
+  /// None unless the positions were uploaded with retain_vertex_data on
+  pub fn read_positions(&self, handle: Index) -> Option<Vec<Vector3<f32>>> {
+    let geometry = self.geometries.get(handle)?;
+    let accessor = self
+      .accessors
+      .get(*geometry.attributes.get(&AttributeName::Position)?)?;
+
+    if !matches!(accessor.options.component_type, TypedArrayKind::Float32)
+      || accessor.options.item_size < 3
+    {
+      return None;
+    }
+
+    let item_size = accessor.options.item_size as usize * 4;
+    let points = accessor
+      .data
+      .as_ref()?
+      .chunks_exact(item_size)
+      .map(|item| {
+        let read = |i: usize| f32::from_le_bytes([item[i], item[i + 1], item[i + 2], item[i + 3]]);
+
+        Vector3::new(read(0), read(4), read(8))
+      })
+      .collect();
+
+    Some(points)
+  }
+
+  pub fn bake_instances(&mut self, geometry: Index, matrices: &[Matrix4<f32>]) -> Option<()> {
+    let buffer = self.insert_buffer(
+      BufferTarget::ArrayBuffer,
+      BufferUsage::StaticDraw,
+      flatten_matrices4_f32(matrices),
+    );
+
+    let previous = self
+      .geometries
+      .get_mut(geometry)?
+      .instances
+      .replace(Instances {
+        buffer,
+        count: matrices.len() as i32,
+      });
+
+    if let Some(instances) = previous {
+      self.remove_buffer(instances.buffer);
+    }
+
+    Some(())
+  }
+
   pub fn compose_mesh(&mut self, geometry: Index, material: Index, name: Option<String>) -> Index {
     let primitive = Primitive {
       geometry,
@@ -136,3 +220,17 @@ fn flatten_vectors3_f32(vectors: &[Vector3<f32>]) -> &[f32] {
 
   unsafe { slice::from_raw_parts(ptr as *const f32, len) }
 }
+
+fn flatten_matrices4_f32(matrices: &[Matrix4<f32>]) -> &[f32] {
+  let len = matrices.len() * 16;
+  let ptr = matrices.as_ptr();
+
+  unsafe { slice::from_raw_parts(ptr as *const f32, len) }
+}
+
+pub fn as_bytes<T>(data: &[T]) -> &[u8] {
+  let len = std::mem::size_of_val(data);
+  let ptr = data.as_ptr();
+
+  unsafe { slice::from_raw_parts(ptr as *const u8, len) }
+}
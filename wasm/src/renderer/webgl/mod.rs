@@ -2,11 +2,20 @@ pub mod camera;
 pub mod context;
 pub mod define;
 pub mod framebuffer;
+pub mod frustum;
 pub mod gltf;
+pub mod light;
 pub mod material;
 pub mod mesh;
+pub mod orbit_controls;
 pub mod pass;
+pub mod picking;
+pub mod prepass;
+pub mod primitives;
 pub mod renderer;
 pub mod shader;
+pub mod shadow;
+pub mod skin;
 pub mod texture;
 pub mod turntable;
+pub mod wireframe;
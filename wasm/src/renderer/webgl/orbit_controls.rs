@@ -0,0 +1,63 @@
+use na::{Point3, Vector3};
+use std::f32::consts::PI;
+
+use super::renderer::{Camera, LookAt};
+
+// keeps the view direction away from the up axis so look_at doesn't flip
+const PITCH_LIMIT: f32 = PI / 2.0 - 0.001;
+const MIN_RADIUS: f32 = 0.001;
+
+pub struct OrbitControls {
+  pub target: Point3<f32>,
+  pub radius: f32,
+  pub yaw: f32,
+  pub pitch: f32,
+}
+
+impl OrbitControls {
+  pub fn new(target: Point3<f32>, radius: f32) -> Self {
+    OrbitControls {
+      target,
+      radius: radius.max(MIN_RADIUS),
+      yaw: 0.0,
+      pitch: 0.0,
+    }
+  }
+
+  pub fn rotate(&mut self, delta_yaw: f32, delta_pitch: f32) {
+    self.yaw = (self.yaw + delta_yaw) % (PI * 2.0);
+    self.pitch = (self.pitch + delta_pitch).clamp(-PITCH_LIMIT, PITCH_LIMIT);
+  }
+
+  pub fn zoom(&mut self, delta: f32) {
+    self.radius = (self.radius + delta).max(MIN_RADIUS);
+  }
+
+  /// Moves the target in the view plane, scaled by the distance to it.
+  pub fn pan(&mut self, dx: f32, dy: f32) {
+    let forward = (self.target - self.get_position()).normalize();
+    let right = forward.cross(&Vector3::y()).normalize();
+    let up = right.cross(&forward);
+
+    self.target += (right * dx + up * dy) * self.radius;
+  }
+
+  pub fn get_position(&self) -> Point3<f32> {
+    let r = self.radius;
+
+    self.target
+      + Vector3::new(
+        r * self.pitch.cos() * self.yaw.sin(),
+        r * self.pitch.sin(),
+        r * self.pitch.cos() * self.yaw.cos(),
+      )
+  }
+
+  pub fn update_camera(&self, camera: &mut Camera) {
+    camera.set_look_at(LookAt {
+      eye: self.get_position(),
+      target: self.target,
+      up: Vector3::y(),
+    });
+  }
+}
@@ -57,7 +57,10 @@ impl Pass {
 
     if let Some(render_target_handle) = self.render_target_handle {
       let target = renderer.targets.get(render_target_handle).unwrap();
-      let fb = renderer.framebuffers.get(target.fb).unwrap();
+      let fb = renderer
+        .framebuffers
+        .get(target.get_draw_framebuffer())
+        .unwrap();
 
       renderer.ctx.bind_framebuffer(Some(&fb));
     }
@@ -68,6 +71,7 @@ impl Pass {
       background_color.z,
       background_color.w,
     );
+    renderer.ctx.clear_depth(renderer.get_clear_depth());
     renderer.ctx.clear(*clean_color, *clean_depth);
 
     handler(renderer);
@@ -0,0 +1,160 @@
+use generational_arena::Index;
+use log::error;
+use na::Vector3;
+
+use super::context::Feature;
+use super::frustum::Frustum;
+use super::material::{Material, PickMaterial};
+use super::renderer::{DepthAttachment, DrawItem, DrawState, RenderError, Renderer, Sampler};
+
+impl Renderer {
+  /// Returns the node drawn at viewport pixel x, y counted from the top-left corner
+  pub fn pick(&mut self, x: i32, y: i32, camera_handle: Index) -> Option<Index> {
+    match self.try_pick(x, y, camera_handle) {
+      Ok(handle) => handle,
+      Err(e) => {
+        error!("pick error: {}", e);
+        None
+      }
+    }
+  }
+
+  pub fn try_pick(
+    &mut self,
+    x: i32,
+    y: i32,
+    camera_handle: Index,
+  ) -> Result<Option<Index>, RenderError> {
+    let [viewport_x, viewport_y, width, height] = self.ctx.get_viewport();
+
+    if x < 0 || y < 0 || x >= width || y >= height {
+      return Ok(None);
+    }
+
+    let target_handle = self.checkup_pick_target(width as u32, height as u32)?;
+
+    for (instanced, skinning) in [(false, false), (true, false), (false, true), (true, true)] {
+      let material: Box<dyn Material> =
+        PickMaterial::new(Vector3::zeros(), instanced, skinning).boxed();
+
+      self.checkup_shader(&material)?;
+    }
+
+    let camera = self
+      .cameras
+      .get(camera_handle)
+      .ok_or(RenderError::MissingCamera(camera_handle))?;
+    let target = self
+      .targets
+      .get(target_handle)
+      .ok_or(RenderError::MissingTarget(target_handle))?;
+    let fb = self
+      .framebuffers
+      .get(target.fb)
+      .ok_or(RenderError::MissingTarget(target_handle))?;
+
+    let frustum = Frustum::from_matrix(&(camera.projection * camera.view));
+    let mut state = DrawState {
+      reverse_z: self.reverse_z,
+      ..Default::default()
+    };
+    let items =
+      self.collect_draw_items(self.scene.get_root_handle(), &frustum, &mut state.stats)?;
+
+    let [r, g, b, a] = self.ctx.get_clear_color();
+    // picking can run while a render target or a scissored viewport is active
+    let framebuffer = self.ctx.get_framebuffer();
+    let scissor = self.ctx.is_enabled(Feature::ScissorTest);
+    let [scissor_x, scissor_y, scissor_width, scissor_height] = self.ctx.get_scissor();
+
+    self.ctx.bind_framebuffer(Some(fb));
+    self.ctx.viewport(0, 0, width, height);
+    self.ctx.set(Feature::ScissorTest, false);
+    self.ctx.clear_color(0.0, 0.0, 0.0, 0.0);
+    self.ctx.clear_depth(self.get_clear_depth());
+    self.ctx.clear(true, true);
+
+    let mut result = Ok(());
+
+    // id 0 is left for the cleared background
+    for (i, item) in items.iter().enumerate() {
+      let material: Box<dyn Material> = PickMaterial::new(
+        encode_pick_id(i as u32 + 1),
+        item.geometry.instances.is_some(),
+        item.node.skin.is_some(),
+      )
+      .boxed();
+      let pick_item = DrawItem {
+        material: &material,
+        tag: material.get_tag(),
+        ..*item
+      };
+
+      result = self.draw_call(&pick_item, camera, &mut state);
+
+      if result.is_err() {
+        break;
+      }
+    }
+
+    self.restore_front_face(&state);
+
+    let mut pixel = [0; 4];
+
+    // GL rows start at the bottom
+    self.ctx.read_pixels(x, height - 1 - y, 1, 1, &mut pixel);
+
+    self.ctx.bind_framebuffer(framebuffer.as_ref());
+    self.ctx.viewport(viewport_x, viewport_y, width, height);
+    self.ctx.set(Feature::ScissorTest, scissor);
+    self
+      .ctx
+      .scissor(scissor_x, scissor_y, scissor_width, scissor_height);
+    self.ctx.clear_color(r, g, b, a);
+    self.ctx.set(Feature::Blend, false);
+    self.ctx.depth_mask(true);
+
+    result?;
+
+    let id = decode_pick_id(&pixel) as usize;
+
+    Ok(match id {
+      0 => None,
+      _ => items.get(id - 1).map(|item| item.node_handle),
+    })
+  }
+
+  fn checkup_pick_target(&mut self, width: u32, height: u32) -> Result<Index, RenderError> {
+    if let Some(handle) = self.pick_target {
+      match self.targets.get(handle) {
+        Some(target) if target.width == width && target.height == height => return Ok(handle),
+        _ => {
+          self.remove_render_target(handle);
+        }
+      }
+    }
+
+    let handle = self.bake_render_target(
+      width,
+      height,
+      Sampler::framebuffer(),
+      DepthAttachment::Renderbuffer,
+    )?;
+
+    self.pick_target = Some(handle);
+
+    Ok(handle)
+  }
+}
+
+pub fn encode_pick_id(id: u32) -> Vector3<f32> {
+  Vector3::new(
+    ((id >> 16) & 0xff) as f32 / 255.0,
+    ((id >> 8) & 0xff) as f32 / 255.0,
+    (id & 0xff) as f32 / 255.0,
+  )
+}
+
+pub fn decode_pick_id(pixel: &[u8; 4]) -> u32 {
+  (pixel[0] as u32) << 16 | (pixel[1] as u32) << 8 | pixel[2] as u32
+}
@@ -0,0 +1,83 @@
+use super::context::{DepthFunc, DrawMode};
+use super::material::DepthMaterial;
+use super::renderer::{Camera, DrawItem, DrawState, RenderResult, Renderer};
+
+impl Renderer {
+  /// Lays down opaque depth first so the color pass only shades visible fragments
+  pub fn enable_depth_prepass(&mut self) -> RenderResult {
+    self.disable_depth_prepass();
+
+    self.depth_prepass = Some([
+      self.try_bake_material(DepthMaterial::new(false, false).boxed())?,
+      self.try_bake_material(DepthMaterial::new(true, false).boxed())?,
+      self.try_bake_material(DepthMaterial::new(false, true).boxed())?,
+      self.try_bake_material(DepthMaterial::new(true, true).boxed())?,
+    ]);
+
+    Ok(())
+  }
+
+  pub fn disable_depth_prepass(&mut self) {
+    if let Some(materials) = self.depth_prepass.take() {
+      for handle in materials {
+        self.remove_material(handle);
+      }
+    }
+  }
+
+  /// Alpha masked items draw their own material so discarded texels stay open
+  pub fn render_depth_prepass(
+    &self,
+    items: &[DrawItem],
+    camera: &Camera,
+    state: &mut DrawState,
+  ) -> RenderResult {
+    let materials = match (&self.depth_prepass, self.wireframe) {
+      (Some(materials), false) => materials,
+      _ => return Ok(()),
+    };
+
+    self.ctx.color_mask(false);
+
+    let mut result = Ok(());
+
+    for item in items.iter().filter(|item| is_depth_prepass_item(item)) {
+      result = if item.material.is_alpha_masked() {
+        self.draw_call(item, camera, state)
+      } else {
+        // skinned nodes are expected to use skinning materials, as the glTF loader sets them up
+        let variant =
+          item.geometry.instances.is_some() as usize | (item.node.skin.is_some() as usize) << 1;
+        let material = self.materials.get(materials[variant]).unwrap();
+        let depth_item = DrawItem {
+          material,
+          tag: material.get_tag(),
+          ..*item
+        };
+
+        self.draw_call(&depth_item, camera, state)
+      };
+
+      if result.is_err() {
+        break;
+      }
+    }
+
+    self.ctx.color_mask(true);
+    state.depth_prepassed = result.is_ok();
+
+    result
+  }
+}
+
+/// Opaque items whose depth the pre-pass can reproduce exactly
+pub fn is_depth_prepass_item(item: &DrawItem) -> bool {
+  let params = item.material.params();
+
+  !item.material.is_transparent()
+    && params.depth_test
+    && params.depth_write
+    && params.polygon_offset.is_none()
+    && matches!(params.depth_func, DepthFunc::Less | DepthFunc::Lequal)
+    && (item.geometry.draw_mode.is_some() || params.draw_mode == DrawMode::Triangles)
+}
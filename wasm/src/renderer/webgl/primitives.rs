@@ -0,0 +1,156 @@
+use generational_arena::Index;
+use na::{Point2, Point3, Vector3};
+use ncollide3d::procedural::{IndexBuffer, TriMesh};
+use std::f32::consts::PI;
+
+use super::renderer::Renderer;
+
+impl Renderer {
+  /// Axis aligned box centered at the origin, every face has its own vertices and a full UV square
+  pub fn create_box(&mut self, width: f32, height: f32, depth: f32) -> Index {
+    self.bake_tri_mesh_geometry(get_box_tri_mesh(width, height, depth))
+  }
+
+  /// Plane in XZ facing +Y, split into subdivisions x subdivisions quads
+  pub fn create_plane(&mut self, width: f32, height: f32, subdivisions: u32) -> Index {
+    self.bake_tri_mesh_geometry(get_plane_tri_mesh(width, height, subdivisions))
+  }
+
+  /// UV sphere with segments around the equator and half as many rings
+  pub fn create_sphere(&mut self, radius: f32, segments: u32) -> Index {
+    self.bake_tri_mesh_geometry(get_sphere_tri_mesh(radius, segments))
+  }
+}
+
+#[derive(Default)]
+struct TriMeshBuilder {
+  coords: Vec<Point3<f32>>,
+  normals: Vec<Vector3<f32>>,
+  uvs: Vec<Point2<f32>>,
+  indices: Vec<Point3<u32>>,
+}
+
+impl TriMeshBuilder {
+  fn push_vertex(&mut self, position: Vector3<f32>, normal: Vector3<f32>, uv: Point2<f32>) {
+    self.coords.push(Point3::from(position));
+    self.normals.push(normal);
+    self.uvs.push(uv);
+  }
+
+  /// Grid spanning u and v from corner, u x v must point along the face normal for CCW winding
+  fn push_grid(&mut self, corner: Vector3<f32>, u: Vector3<f32>, v: Vector3<f32>, cells: u32) {
+    let cells = cells.max(1);
+    let normal = u.cross(&v).normalize();
+    let first = self.coords.len() as u32;
+    let row = cells + 1;
+
+    for j in 0..=cells {
+      for i in 0..=cells {
+        let s = i as f32 / cells as f32;
+        let t = j as f32 / cells as f32;
+
+        self.push_vertex(corner + u * s + v * t, normal, Point2::new(s, 1.0 - t));
+      }
+    }
+
+    for j in 0..cells {
+      for i in 0..cells {
+        let a = first + i + j * row;
+        let b = a + 1;
+        let c = a + row;
+        let d = c + 1;
+
+        self.indices.push(Point3::new(a, b, d));
+        self.indices.push(Point3::new(a, d, c));
+      }
+    }
+  }
+
+  fn build(self) -> TriMesh<f32> {
+    TriMesh::new(
+      self.coords,
+      Some(self.normals),
+      Some(self.uvs),
+      Some(IndexBuffer::Unified(self.indices)),
+    )
+  }
+}
+
+pub fn get_box_tri_mesh(width: f32, height: f32, depth: f32) -> TriMesh<f32> {
+  let x = Vector3::x() * width;
+  let y = Vector3::y() * height;
+  let z = Vector3::z() * depth;
+
+  // (normal, u, v) per face
+  let faces = [
+    (x, -z, y),
+    (-x, z, y),
+    (y, x, -z),
+    (-y, x, z),
+    (z, x, y),
+    (-z, -x, y),
+  ];
+
+  let mut builder = TriMeshBuilder::default();
+
+  for (normal, u, v) in faces.iter() {
+    builder.push_grid((normal - u - v) / 2.0, *u, *v, 1);
+  }
+
+  builder.build()
+}
+
+pub fn get_plane_tri_mesh(width: f32, height: f32, subdivisions: u32) -> TriMesh<f32> {
+  let u = Vector3::x() * width;
+  let v = -Vector3::z() * height;
+  let mut builder = TriMeshBuilder::default();
+
+  builder.push_grid(-(u + v) / 2.0, u, v, subdivisions);
+
+  builder.build()
+}
+
+pub fn get_sphere_tri_mesh(radius: f32, segments: u32) -> TriMesh<f32> {
+  let segments = segments.max(3);
+  let rings = (segments / 2).max(2);
+  let row = segments + 1;
+  let mut builder = TriMeshBuilder::default();
+
+  // the seam and the poles get duplicated vertices so UVs don't wrap
+  for ring in 0..=rings {
+    let v = ring as f32 / rings as f32;
+    let theta = v * PI;
+
+    for segment in 0..=segments {
+      let u = segment as f32 / segments as f32;
+      let phi = u * PI * 2.0;
+      let normal = Vector3::new(
+        -phi.cos() * theta.sin(),
+        theta.cos(),
+        phi.sin() * theta.sin(),
+      );
+
+      builder.push_vertex(normal * radius, normal, Point2::new(u, v));
+    }
+  }
+
+  for ring in 0..rings {
+    for segment in 0..segments {
+      let a = ring * row + segment + 1;
+      let b = ring * row + segment;
+      let c = (ring + 1) * row + segment;
+      let d = (ring + 1) * row + segment + 1;
+
+      // the triangles touching a pole are degenerate, skip them
+      if ring != 0 {
+        builder.indices.push(Point3::new(a, b, d));
+      }
+
+      if ring != rings - 1 {
+        builder.indices.push(Point3::new(b, c, d));
+      }
+    }
+  }
+
+  builder.build()
+}
@@ -1,17 +1,27 @@
 use generational_arena::{Arena, Index};
-use log::info;
-use na::Matrix4;
-use std::collections::HashMap;
+use log::{error, info, warn};
+use na::{Matrix4, Orthographic3, Perspective3, Point3, Vector3, Vector4};
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::default::Default;
-use web_sys::{WebGlBuffer, WebGlFramebuffer, WebGlTexture};
+use std::fmt;
+use web_sys::{
+  WebGlBuffer, WebGlFramebuffer, WebGlRenderbuffer, WebGlTexture, WebGlVertexArrayObject,
+};
 
 use super::context::{
-  BufferItem, BufferTarget, BufferUsage, Context, Feature, TexParam, TexParamName, TextureKind,
+  BlendFactor, BufferItem, BufferTarget, BufferUsage, Context, DepthFunc, DrawMode, Feature,
+  FrontFace, TexParam, TexParamName, TextureKind, TypedArrayKind,
 };
-use super::material::Material;
+use super::define::Define;
+use super::frustum::Frustum;
+use super::material::{Material, PbrMaterial};
+use super::prepass::is_depth_prepass_item;
 use super::shader::Shader;
 
-use super::shader::{AttributeName, AttributeOptions};
+use super::shader::{AttributeName, AttributeOptions, CAMERA_BLOCK_BINDING, CAMERA_BLOCK_DEFINE};
+use crate::scene::animation::Animation;
 use crate::scene::node::Node;
 use crate::scene::scene::Scene;
 
@@ -20,6 +30,9 @@ pub struct Accessor {
   pub buffer: Index,
   pub count: i32,
   pub options: AttributeOptions,
+  /// CPU-side copy of tightly packed items, kept for index accessors and, with
+  /// retain_vertex_data, for vertex ones
+  pub data: Option<Vec<u8>>,
 }
 
 pub type Attributes = HashMap<AttributeName, Index>;
@@ -29,6 +42,126 @@ pub type Indices = Option<Index>;
 pub struct Geometry {
   pub attributes: Attributes,
   pub indices: Indices,
+  pub bounds: Option<Aabb>,
+  /// Coarser than `bounds` but cheaper to cull, tested first
+  pub bounding_sphere: Option<BoundingSphere>,
+  pub instances: Option<Instances>,
+  pub wireframe: Option<Index>,
+  /// Overrides the material draw mode, set from the glTF primitive mode
+  pub draw_mode: Option<DrawMode>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Instances {
+  pub buffer: Index,
+  pub count: i32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+  pub min: Vector3<f32>,
+  pub max: Vector3<f32>,
+}
+
+impl Aabb {
+  pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Self {
+    Aabb { min, max }
+  }
+
+  pub fn from_points<'a, I: IntoIterator<Item = &'a Vector3<f32>>>(points: I) -> Option<Self> {
+    let mut iter = points.into_iter();
+    let first = iter.next()?;
+    let (mut min, mut max) = (*first, *first);
+
+    for point in iter {
+      min = min.inf(point);
+      max = max.sup(point);
+    }
+
+    Some(Aabb { min, max })
+  }
+
+  pub fn center(&self) -> Vector3<f32> {
+    (self.min + self.max) * 0.5
+  }
+
+  pub fn size(&self) -> Vector3<f32> {
+    self.max - self.min
+  }
+
+  pub fn union(&self, other: &Aabb) -> Self {
+    Aabb {
+      min: self.min.inf(&other.min),
+      max: self.max.sup(&other.max),
+    }
+  }
+
+  pub fn transform(&self, matrix: &Matrix4<f32>) -> Self {
+    let corners = [
+      Vector3::new(self.min.x, self.min.y, self.min.z),
+      Vector3::new(self.max.x, self.min.y, self.min.z),
+      Vector3::new(self.min.x, self.max.y, self.min.z),
+      Vector3::new(self.max.x, self.max.y, self.min.z),
+      Vector3::new(self.min.x, self.min.y, self.max.z),
+      Vector3::new(self.max.x, self.min.y, self.max.z),
+      Vector3::new(self.min.x, self.max.y, self.max.z),
+      Vector3::new(self.max.x, self.max.y, self.max.z),
+    ];
+
+    let transformed: Vec<Vector3<f32>> = corners
+      .iter()
+      .map(|c| matrix.transform_point(&Point3::from(*c)).coords)
+      .collect();
+
+    Aabb::from_points(&transformed).unwrap()
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BoundingSphere {
+  pub center: Vector3<f32>,
+  pub radius: f32,
+}
+
+impl BoundingSphere {
+  pub fn new(center: Vector3<f32>, radius: f32) -> Self {
+    BoundingSphere { center, radius }
+  }
+
+  /// Centered on the points' bounding box, tighter than the box's own sphere
+  pub fn from_points<'a, I>(points: I) -> Option<Self>
+  where
+    I: IntoIterator<Item = &'a Vector3<f32>>,
+    I::IntoIter: Clone,
+  {
+    let points = points.into_iter();
+    let center = Aabb::from_points(points.clone())?.center();
+    let radius = points
+      .map(|point| (point - center).norm_squared())
+      .fold(0.0, f32::max)
+      .sqrt();
+
+    Some(BoundingSphere { center, radius })
+  }
+
+  pub fn from_aabb(aabb: &Aabb) -> Self {
+    BoundingSphere {
+      center: aabb.center(),
+      radius: aabb.size().norm() * 0.5,
+    }
+  }
+
+  /// Radius grows by the largest axis scale, so non-uniform scaling stays conservative
+  pub fn transform(&self, matrix: &Matrix4<f32>) -> Self {
+    let scale = (0..3)
+      .map(|i| matrix.fixed_slice::<3, 1>(0, i).norm())
+      .fold(0.0, f32::max);
+
+    BoundingSphere {
+      center: matrix.transform_point(&Point3::from(self.center)).coords,
+      radius: self.radius * scale,
+    }
+  }
 }
 
 #[derive(Debug, Clone)]
@@ -49,6 +182,8 @@ pub struct Sampler {
   pub min_filter: TexParam,
   pub wrap_s: TexParam,
   pub wrap_t: TexParam,
+  pub mipmaps: bool,
+  pub anisotropy: f32,
 }
 
 impl Default for Sampler {
@@ -58,6 +193,8 @@ impl Default for Sampler {
       wrap_t: TexParam::ClampToEdge,
       min_filter: TexParam::LinearMipMapLinear,
       mag_filter: TexParam::Linear,
+      mipmaps: true,
+      anisotropy: 1.0,
     }
   }
 }
@@ -69,7 +206,57 @@ impl Sampler {
       wrap_t: TexParam::ClampToEdge,
       min_filter: TexParam::Linear,
       mag_filter: TexParam::Linear,
+      mipmaps: false,
+      anisotropy: 1.0,
+    }
+  }
+
+  pub fn set_wrap_s(mut self, wrap: TexParam) -> Self {
+    self.wrap_s = wrap;
+    self
+  }
+
+  pub fn set_wrap_t(mut self, wrap: TexParam) -> Self {
+    self.wrap_t = wrap;
+    self
+  }
+
+  /// Mipmaps are generated only when the min filter samples them
+  pub fn set_min_filter(mut self, filter: TexParam) -> Self {
+    self.min_filter = filter;
+    self.mipmaps = matches!(
+      filter,
+      TexParam::NearestMipMapNearest
+        | TexParam::LinearMipMapNearest
+        | TexParam::NearestMimMapLinear
+        | TexParam::LinearMipMapLinear
+    );
+    self
+  }
+
+  pub fn set_mag_filter(mut self, filter: TexParam) -> Self {
+    self.mag_filter = filter;
+    self
+  }
+
+  /// WebGL1 can't mip or repeat NPOT textures, so such sizes fall back to
+  /// linear/nearest filtering and clamped wrapping
+  pub fn fit_size(mut self, width: u32, height: u32) -> Self {
+    if !width.is_power_of_two() || !height.is_power_of_two() {
+      self.mipmaps = false;
+      self.wrap_s = TexParam::ClampToEdge;
+      self.wrap_t = TexParam::ClampToEdge;
+    }
+
+    if !self.mipmaps {
+      self.min_filter = match self.min_filter {
+        TexParam::NearestMipMapNearest | TexParam::NearestMimMapLinear => TexParam::Nearest,
+        TexParam::LinearMipMapNearest | TexParam::LinearMipMapLinear => TexParam::Linear,
+        filter => filter,
+      };
     }
+
+    self
   }
 
   pub fn set_params(&self, kind: TextureKind, ctx: &Context) {
@@ -77,6 +264,7 @@ impl Sampler {
     ctx.texture_parameter(kind, TexParamName::TextureMagFilter, self.mag_filter);
     ctx.texture_parameter(kind, TexParamName::TextureWrapS, self.wrap_s);
     ctx.texture_parameter(kind, TexParamName::TextureWrapT, self.wrap_t);
+    ctx.texture_anisotropy(kind, self.anisotropy);
   }
 }
 
@@ -89,35 +277,499 @@ pub struct Texture {
 #[derive(Debug, Clone)]
 pub struct RenderTarget {
   pub fb: Index,
+  pub width: u32,
+  pub height: u32,
   pub color_texture: Index,
   pub depth_texture: Option<Index>,
+  pub depth_renderbuffer: Option<Index>,
+  /// Rendering goes here instead of fb, resolve_render_target copies it into the color texture
+  pub multisample: Option<Multisample>,
+}
+
+impl RenderTarget {
+  pub fn get_draw_framebuffer(&self) -> Index {
+    match &self.multisample {
+      Some(multisample) => multisample.fb,
+      None => self.fb,
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct Multisample {
+  pub fb: Index,
+  pub samples: i32,
+  pub color_renderbuffer: Index,
+  pub depth_renderbuffer: Option<Index>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMapping {
+  None,
+  Reinhard,
+  Aces,
+}
+
+#[derive(Debug)]
+pub struct ShadowMap {
+  pub target: Index,
+  pub size: u32,
+  pub bias: f32,
+  /// Depth-only materials for plain, instanced, skinned and skinned instanced geometry
+  pub materials: [Index; 4],
+  /// Light projection * view of the last shadow pass
+  pub matrix: Cell<Matrix4<f32>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DepthAttachment {
+  None,
+  Texture,
+  Renderbuffer,
+  /// Combined depth and stencil renderbuffer
+  StencilRenderbuffer,
+}
+
+/// Parameters the projection matrix is rebuilt from
+#[derive(Debug, Clone, Copy)]
+pub enum Projection {
+  Perspective {
+    fov_y: f32,
+    aspect: f32,
+    near: f32,
+    far: f32,
+  },
+  /// Maps near to depth 1 and far to 0, needs the renderer's `reverse_z`. WebGL has no
+  /// clip control, so z still goes through the [-1, 1] to [0, 1] remap and precision is no
+  /// better than Perspective; this only keeps the API in shape for a backend that has it.
+  ReverseZPerspective {
+    fov_y: f32,
+    aspect: f32,
+    near: f32,
+    far: f32,
+  },
+  Orthographic {
+    left: f32,
+    right: f32,
+    bottom: f32,
+    top: f32,
+    near: f32,
+    far: f32,
+  },
+}
+
+impl Projection {
+  pub fn to_homogeneous(&self) -> Matrix4<f32> {
+    match *self {
+      Projection::Perspective {
+        fov_y,
+        aspect,
+        near,
+        far,
+      } => Perspective3::new(aspect, fov_y, near, far).to_homogeneous(),
+      Projection::ReverseZPerspective {
+        fov_y,
+        aspect,
+        near,
+        far,
+      } => {
+        Matrix4::new_nonuniform_scaling(&Vector3::new(1.0, 1.0, -1.0))
+          * Perspective3::new(aspect, fov_y, near, far).to_homogeneous()
+      }
+      Projection::Orthographic {
+        left,
+        right,
+        bottom,
+        top,
+        near,
+        far,
+      } => Orthographic3::new(left, right, bottom, top, near, far).to_homogeneous(),
+    }
+  }
+}
+
+/// Parameters the view matrix is rebuilt from
+#[derive(Debug, Clone, Copy)]
+pub struct LookAt {
+  pub eye: Point3<f32>,
+  pub target: Point3<f32>,
+  pub up: Vector3<f32>,
+}
+
+impl Default for LookAt {
+  fn default() -> Self {
+    LookAt {
+      eye: Point3::origin(),
+      target: -Point3::from(Vector3::z()),
+      up: Vector3::y(),
+    }
+  }
+}
+
+impl LookAt {
+  pub fn to_homogeneous(&self) -> Matrix4<f32> {
+    Matrix4::look_at_rh(&self.eye, &self.target, &self.up)
+  }
 }
 
+/// `view` and `projection` are what gets uploaded, the optional parameters are only kept so the
+/// setters can rebuild one matrix without touching the other. Writing a matrix directly leaves
+/// its parameters stale.
 #[derive(Debug, Clone)]
 pub struct Camera {
   pub view: Matrix4<f32>,
   pub projection: Matrix4<f32>,
+  pub projection_params: Option<Projection>,
+  pub view_params: Option<LookAt>,
 }
 
 impl Default for Camera {
   fn default() -> Self {
+    Camera::new(Matrix4::identity(), Matrix4::identity())
+  }
+}
+
+impl Camera {
+  pub fn new(view: Matrix4<f32>, projection: Matrix4<f32>) -> Self {
+    Camera {
+      view,
+      projection,
+      projection_params: None,
+      view_params: None,
+    }
+  }
+
+  pub fn from_projection(projection: Projection) -> Self {
     Camera {
       view: Matrix4::identity(),
-      projection: Matrix4::identity(),
+      projection: projection.to_homogeneous(),
+      projection_params: Some(projection),
+      view_params: None,
+    }
+  }
+
+  pub fn perspective(fov_y: f32, aspect: f32, near: f32, far: f32) -> Self {
+    Camera::from_projection(Projection::Perspective {
+      fov_y,
+      aspect,
+      near,
+      far,
+    })
+  }
+
+  /// No depth precision gain on WebGL, see `Projection::ReverseZPerspective`
+  pub fn perspective_reverse_z(fov_y: f32, aspect: f32, near: f32, far: f32) -> Self {
+    Camera::from_projection(Projection::ReverseZPerspective {
+      fov_y,
+      aspect,
+      near,
+      far,
+    })
+  }
+
+  pub fn orthographic(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Self {
+    Camera::from_projection(Projection::Orthographic {
+      left,
+      right,
+      bottom,
+      top,
+      near,
+      far,
+    })
+  }
+
+  pub fn look_at(mut self, eye: &Point3<f32>, target: &Point3<f32>, up: &Vector3<f32>) -> Self {
+    self.set_look_at(LookAt {
+      eye: *eye,
+      target: *target,
+      up: *up,
+    });
+    self
+  }
+
+  pub fn set_projection(&mut self, projection: Projection) {
+    self.projection = projection.to_homogeneous();
+    self.projection_params = Some(projection);
+  }
+
+  pub fn set_look_at(&mut self, look_at: LookAt) {
+    self.view = look_at.to_homogeneous();
+    self.view_params = Some(look_at);
+  }
+
+  /// Drops the projection parameters, so set_aspect leaves the matrix alone
+  pub fn set_projection_matrix(&mut self, projection: Matrix4<f32>) {
+    self.projection = projection;
+    self.projection_params = None;
+  }
+
+  /// Drops the look at parameters, set_position and set_target start over from the defaults
+  pub fn set_view_matrix(&mut self, view: Matrix4<f32>) {
+    self.view = view;
+    self.view_params = None;
+  }
+
+  /// Rebuilds only the projection, an orthographic box keeps its height and center.
+  /// Does nothing for cameras built from a raw projection matrix.
+  pub fn set_aspect(&mut self, aspect: f32) {
+    let projection = match self.projection_params {
+      Some(Projection::Perspective {
+        fov_y, near, far, ..
+      }) => Projection::Perspective {
+        fov_y,
+        aspect,
+        near,
+        far,
+      },
+      Some(Projection::ReverseZPerspective {
+        fov_y, near, far, ..
+      }) => Projection::ReverseZPerspective {
+        fov_y,
+        aspect,
+        near,
+        far,
+      },
+      Some(Projection::Orthographic {
+        left,
+        right,
+        bottom,
+        top,
+        near,
+        far,
+      }) => {
+        let center = (left + right) / 2.0;
+        let half_width = (top - bottom) * aspect / 2.0;
+
+        Projection::Orthographic {
+          left: center - half_width,
+          right: center + half_width,
+          bottom,
+          top,
+          near,
+          far,
+        }
+      }
+      None => return,
+    };
+
+    self.set_projection(projection);
+  }
+
+  /// Rebuilds only the view, cameras without look at parameters start from the default ones
+  pub fn set_position(&mut self, eye: &Point3<f32>) {
+    let look_at = LookAt {
+      eye: *eye,
+      ..self.view_params.unwrap_or_default()
+    };
+
+    self.set_look_at(look_at);
+  }
+
+  /// Rebuilds only the view, cameras without look at parameters start from the default ones
+  pub fn set_target(&mut self, target: &Point3<f32>) {
+    let look_at = LookAt {
+      target: *target,
+      ..self.view_params.unwrap_or_default()
+    };
+
+    self.set_look_at(look_at);
+  }
+
+  /// Moves the camera along +Z from the box center until the bounding sphere fits both fovs
+  pub fn frame_bounds(&mut self, bounds: &Aabb, fov_y: f32, aspect: f32) {
+    let margin = 1.1;
+    let radius = match bounds.size().norm() * 0.5 {
+      r if r > f32::EPSILON => r,
+      _ => 1.0,
+    };
+
+    let fov_x = 2.0 * ((fov_y * 0.5).tan() * aspect).atan();
+    let distance = radius * margin / (fov_y.min(fov_x) * 0.5).sin();
+
+    let target = Point3::from(bounds.center());
+    let eye = target + Vector3::z() * distance;
+
+    self.set_look_at(LookAt {
+      eye,
+      target,
+      up: Vector3::y(),
+    });
+  }
+
+  pub fn get_position(&self) -> Vector3<f32> {
+    let matrix_world = self.view.try_inverse().unwrap_or_else(Matrix4::identity);
+
+    Vector3::new(matrix_world[12], matrix_world[13], matrix_world[14])
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct Skin {
+  pub joints: Vec<Index>,
+  pub inverse_bind_matrices: Vec<Matrix4<f32>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DirectionalLight {
+  pub direction: Vector3<f32>,
+  pub color: Vector3<f32>,
+  pub intensity: f32,
+}
+
+impl DirectionalLight {
+  pub fn new(direction: Vector3<f32>, color: Vector3<f32>, intensity: f32) -> Self {
+    DirectionalLight {
+      direction,
+      color,
+      intensity,
     }
   }
 }
 
-impl Camera {
-  pub fn new(view: Matrix4<f32>, projection: Matrix4<f32>) -> Self {
-    Camera { view, projection }
+#[derive(Debug, Clone)]
+pub struct PointLight {
+  pub position: Vector3<f32>,
+  pub color: Vector3<f32>,
+  pub intensity: f32,
+  pub range: f32,
+}
+
+impl PointLight {
+  /// A range of zero means the light is not cut off.
+  pub fn new(position: Vector3<f32>, color: Vector3<f32>, intensity: f32, range: f32) -> Self {
+    PointLight {
+      position,
+      color,
+      intensity,
+      range,
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum RenderError {
+  MissingNode(Index),
+  MissingMesh(Index),
+  MissingCamera(Index),
+  MissingGeometry(Index),
+  MissingMaterial(Index),
+  MissingAccessor(Index),
+  MissingBuffer(Index),
+  MissingShader(String),
+  MissingExtension(String),
+  MissingTarget(Index),
+  IncompleteFramebuffer,
+  ShaderCompile(String, String),
+  ShaderLink(String, String),
+  BufferCreate,
+  /// (buffer, start, end, size) of an update past the allocation, in bytes
+  BufferOutOfRange(Index, usize, usize, usize),
+  MissingAttribute(String, AttributeName),
+  MismatchedCubeMapFaces,
+}
+
+impl fmt::Display for RenderError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Self::MissingNode(handle) => write!(f, "missing node {:?}", handle),
+      Self::MissingMesh(handle) => write!(f, "missing mesh {:?}", handle),
+      Self::MissingCamera(handle) => write!(f, "missing camera {:?}", handle),
+      Self::MissingGeometry(handle) => write!(f, "missing geometry {:?}", handle),
+      Self::MissingMaterial(handle) => write!(f, "missing material {:?}", handle),
+      Self::MissingAccessor(handle) => write!(f, "missing accessor {:?}", handle),
+      Self::MissingBuffer(handle) => write!(f, "missing buffer {:?}", handle),
+      Self::MissingShader(tag) => write!(f, "missing shader {}", tag),
+      Self::MissingExtension(name) => write!(f, "missing extension {}", name),
+      Self::MissingTarget(handle) => write!(f, "missing render target {:?}", handle),
+      Self::IncompleteFramebuffer => write!(f, "framebuffer is incomplete"),
+      Self::ShaderCompile(tag, message) => {
+        write!(f, "failed to create shader {}: {}", tag, message)
+      }
+      Self::ShaderLink(tag, message) => {
+        write!(f, "failed to link shader {}: {}", tag, message)
+      }
+      Self::BufferCreate => write!(f, "unable to create buffer"),
+      Self::BufferOutOfRange(handle, start, end, size) => write!(
+        f,
+        "update {}..{} is out of range of buffer {:?} with {} bytes",
+        start, end, handle, size
+      ),
+      Self::MissingAttribute(tag, name) => {
+        write!(f, "shader {} needs {:?}, the geometry has none", tag, name)
+      }
+      Self::MismatchedCubeMapFaces => {
+        write!(f, "cube map faces must be square and of the same size")
+      }
+    }
+  }
+}
+
+impl std::error::Error for RenderError {}
+
+pub type RenderResult = Result<(), RenderError>;
+
+#[derive(Debug)]
+pub struct DrawItem<'a> {
+  pub node_handle: Index,
+  pub geometry_handle: Index,
+  pub geometry: &'a Geometry,
+  pub material: &'a Box<dyn Material>,
+  pub node: &'a Node,
+  pub tag: String,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RenderStats {
+  pub draw_calls: u32,
+  pub triangles: u32,
+  pub shader_binds: u32,
+  pub culled: u32,
+}
+
+impl RenderStats {
+  pub fn count_draw(&mut self, draw_mode: DrawMode, count: i32, instances: i32) {
+    self.draw_calls += 1;
+
+    match draw_mode {
+      DrawMode::Triangles => self.triangles += (count / 3 * instances) as u32,
+      DrawMode::TriangleStrip | DrawMode::TriangleFan => {
+        self.triangles += ((count - 2).max(0) * instances) as u32
+      }
+      _ => {}
+    }
   }
 }
 
+#[derive(Debug, Default)]
+pub struct DrawState {
+  pub stats: RenderStats,
+  pub tag: Option<String>,
+  pub cull_face: Option<bool>,
+  pub depth_test: Option<bool>,
+  pub depth_func: Option<DepthFunc>,
+  pub front_face: Option<FrontFace>,
+  pub depth_write: Option<bool>,
+  pub polygon_offset: Option<Option<(f32, f32)>>,
+  pub line_width: Option<f32>,
+  pub blend: Option<bool>,
+  /// Whether this pass already uploaded its camera to the uniform buffer
+  pub camera_block: bool,
+  /// A depth pre-pass ran, so items it covered test against the depth they laid down
+  pub depth_prepassed: bool,
+  /// Flips material depth funcs, set by passes drawn through the main camera
+  pub reverse_z: bool,
+  /// Drawing into the shadow map, so it must not be bound for sampling
+  pub shadow_pass: bool,
+}
+
 pub type Buffers = Arena<WebGlBuffer>;
 pub type Images = Arena<WebGlTexture>;
 pub type Framebuffers = Arena<WebGlFramebuffer>;
+pub type Renderbuffers = Arena<WebGlRenderbuffer>;
 pub type Targets = Arena<RenderTarget>;
+pub type Skins = Arena<Skin>;
+pub type Animations = Arena<Animation>;
 pub type Accessors = Arena<Accessor>;
 pub type Geometries = Arena<Geometry>;
 pub type Materials = Arena<Box<dyn Material>>;
@@ -125,59 +777,229 @@ pub type Samplers = Arena<Sampler>;
 pub type Textures = Arena<Texture>;
 pub type Meshes = Arena<Mesh>;
 pub type Cameras = Arena<Camera>;
+pub type DirectionalLights = Arena<DirectionalLight>;
+pub type PointLights = Arena<PointLight>;
 pub type Shaders = HashMap<String, Shader>;
+/// Amount of arena materials per shader tag
+pub type ShaderRefs = HashMap<String, usize>;
+pub type VertexArrays = HashMap<(Index, String), WebGlVertexArrayObject>;
+pub type GeometryKey = (Vec<(AttributeName, Index)>, Indices, Option<DrawMode>);
+
+pub struct BoundAttributes {
+  /// Locations with data bound, shader attributes the geometry lacks stay disabled
+  pub locations: Vec<u32>,
+  pub count: i32,
+  pub instance_location: Option<u32>,
+}
 
 pub struct Renderer {
   pub ctx: Context,
   pub buffers: Buffers,
   pub images: Images,
   pub framebuffers: Framebuffers,
+  pub renderbuffers: Renderbuffers,
   pub targets: Targets,
   pub accessors: Accessors,
   pub geometries: Geometries,
   pub materials: Materials,
+  /// Used for primitives without a material
+  pub default_material: Index,
   pub samplers: Samplers,
   pub textures: Textures,
   pub meshes: Meshes,
+  pub skins: Skins,
+  pub animations: Animations,
   pub cameras: Cameras,
+  pub lights: DirectionalLights,
+  pub active_lights: Vec<Index>,
+  pub point_lights: PointLights,
+  pub environment_map: Option<Index>,
+  pub pick_target: Option<Index>,
   pub scene: Scene,
   pub shaders: Shaders,
+  pub shader_refs: ShaderRefs,
+  pub vertex_arrays: RefCell<VertexArrays>,
+  /// Geometries inserted through `intern_geometry`, keyed by their accessors
+  pub geometry_keys: HashMap<GeometryKey, Index>,
+  pub wireframe: bool,
+  /// Clears depth to 0 and flips depth funcs, pair it with `Camera::perspective_reverse_z`.
+  /// Same depth precision as the regular mapping on WebGL.
+  pub reverse_z: bool,
+  /// Fails draws whose geometry lacks a shader attribute instead of warning
+  pub strict_attributes: bool,
+  /// Missing attributes already warned about, so each pair is logged once
+  pub missing_attributes: RefCell<HashSet<(String, AttributeName)>>,
+  pub clear_color: Vector4<f32>,
+  /// Clears color and depth at the start of every scene render
+  pub auto_clear: bool,
+  /// Keeps uploaded vertex data on the CPU for read_positions, off to save memory
+  pub retain_vertex_data: bool,
+  /// Shades in linear space, decoding sRGB color textures and encoding the output
+  pub output_gamma: bool,
+  /// Compresses HDR lighting into the displayable range before gamma encoding
+  pub tone_mapping: ToneMapping,
+  /// Depth from the active directional light, rendered before every scene render
+  pub shadow_map: Option<ShadowMap>,
+  /// Camera matrices shared by every shader on WebGL2, uploaded once per pass
+  pub camera_block: Option<WebGlBuffer>,
+  /// Depth materials for plain, instanced, skinned and skinned instanced geometry
+  pub depth_prepass: Option<[Index; 4]>,
 }
 
 impl Renderer {
   pub fn new(ctx: Context) -> Self {
-    ctx.get_extension("OES_element_index_uint").unwrap();
-    ctx.get_extension("WEBGL_depth_texture").unwrap();
-    ctx.get_extension("OES_texture_float").unwrap();
+    // both are core in WebGL2, where they aren't exposed as extensions
+    if ctx.get_webgl2().is_none() {
+      ctx.get_extension("WEBGL_depth_texture").unwrap();
+      ctx.get_extension("OES_texture_float").unwrap();
+    }
+
+    let mut materials = Materials::default();
+
+    let default_material: Box<dyn Material> = PbrMaterial::new()
+      .set_color(Vector3::new(0.5, 0.5, 0.5))
+      .set_metallic(0.0)
+      .set_roughness(1.0)
+      .boxed();
+
+    let camera_block = ctx.create_uniform_buffer().ok();
+    let mut shader_refs = ShaderRefs::new();
+
+    shader_refs.insert(default_material.get_tag(), 1);
 
-    Renderer {
+    let default_material = materials.insert(default_material);
+
+    let mut renderer = Renderer {
       ctx,
       buffers: Buffers::default(),
       images: Images::default(),
       framebuffers: Framebuffers::default(),
+      renderbuffers: Renderbuffers::default(),
       targets: Targets::default(),
       accessors: Accessors::default(),
       geometries: Geometries::default(),
-      materials: Materials::default(),
+      materials,
+      default_material,
       samplers: Samplers::default(),
       textures: Textures::default(),
       meshes: Meshes::default(),
+      skins: Skins::default(),
+      animations: Animations::default(),
       cameras: Cameras::default(),
+      lights: DirectionalLights::default(),
+      active_lights: vec![],
+      point_lights: Arena::new(),
+      environment_map: None,
+      pick_target: None,
       scene: Scene::new(),
-      shaders: HashMap::new(),
-    }
+      shaders: Shaders::new(),
+      shader_refs,
+      vertex_arrays: RefCell::new(VertexArrays::new()),
+      geometry_keys: HashMap::new(),
+      wireframe: false,
+      reverse_z: false,
+      strict_attributes: false,
+      missing_attributes: RefCell::new(HashSet::new()),
+      clear_color: Vector4::new(0.0, 0.0, 0.0, 1.0),
+      auto_clear: false,
+      retain_vertex_data: false,
+      output_gamma: true,
+      tone_mapping: ToneMapping::None,
+      shadow_map: None,
+      camera_block,
+      depth_prepass: None,
+    };
+
+    renderer.recompile_shaders().unwrap();
+
+    renderer
   }
 
-  pub fn checkup_shader(&mut self, material: &Box<dyn Material>) {
+  pub fn checkup_shader(&mut self, material: &Box<dyn Material>) -> RenderResult {
     let tag = material.get_tag();
 
     if self.shaders.get(&tag).is_none() {
       info!("compile shader: {}", tag);
 
-      self
-        .shaders
-        .insert(tag.clone(), material.create_shader(&self.ctx).unwrap());
+      let shader = self.compile_shader(material.as_ref(), &tag)?;
+
+      self.shaders.insert(tag, shader);
     };
+
+    Ok(())
+  }
+
+  fn compile_shader(&self, material: &dyn Material, tag: &str) -> Result<Shader, RenderError> {
+    // shaders only know their defines, the tag tells which material failed
+    material
+      .create_shader(&self.ctx, &self.get_shader_defines())
+      .map_err(|e| match e {
+        RenderError::ShaderCompile(context, message) => {
+          RenderError::ShaderCompile(format!("{} ({})", tag, context), message)
+        }
+        RenderError::ShaderLink(context, message) => {
+          RenderError::ShaderLink(format!("{} ({})", tag, context), message)
+        }
+        e => e,
+      })
+  }
+
+  pub fn get_shader_defines(&self) -> Vec<Define> {
+    let mut defines = vec![];
+
+    if self.output_gamma {
+      defines.push(Define::def("OUTPUT_GAMMA"));
+    }
+
+    match self.tone_mapping {
+      ToneMapping::None => {}
+      ToneMapping::Reinhard => {
+        defines.push(Define::def("TONEMAP"));
+        defines.push(Define::def("TONEMAP_REINHARD"));
+      }
+      ToneMapping::Aces => {
+        defines.push(Define::def("TONEMAP"));
+        defines.push(Define::def("TONEMAP_ACES"));
+      }
+    }
+
+    if self.shadow_map.is_some() {
+      defines.push(Define::def("USE_SHADOW_MAP"));
+    }
+
+    if self.camera_block.is_some() {
+      defines.push(Define::def(CAMERA_BLOCK_DEFINE));
+    }
+
+    defines
+  }
+
+  /// Changing renderer-wide defines invalidates every compiled shader
+  pub fn recompile_shaders(&mut self) -> RenderResult {
+    let ctx = &self.ctx;
+
+    for (_, vao) in self.vertex_arrays.borrow_mut().drain() {
+      ctx.delete_vertex_array(Some(&vao));
+    }
+
+    for (_, shader) in self.shaders.drain() {
+      ctx.delete_program(Some(shader.get_program()));
+    }
+
+    let handles: Vec<Index> = self.materials.iter().map(|(handle, _)| handle).collect();
+
+    for handle in handles {
+      let material = self.materials.get(handle).unwrap();
+      let tag = material.get_tag();
+
+      if !self.shaders.contains_key(&tag) {
+        let shader = self.compile_shader(material.as_ref(), &tag)?;
+
+        self.shaders.insert(tag, shader);
+      }
+    }
+
+    Ok(())
   }
 
   pub fn insert_buffer<T: BufferItem>(
@@ -191,10 +1013,69 @@ impl Renderer {
       .insert(self.ctx.create_buffer(target, usage, data).unwrap())
   }
 
+  /// Rewrites part of a buffer in place, offset is in bytes. Meant for `DynamicDraw` data
+  /// changing every frame, the allocation never grows.
+  pub fn update_buffer<T: BufferItem>(
+    &self,
+    handle: Index,
+    target: BufferTarget,
+    offset: usize,
+    data: &[T],
+  ) -> RenderResult {
+    let buffer = self
+      .buffers
+      .get(handle)
+      .ok_or(RenderError::MissingBuffer(handle))?;
+    let size = self.ctx.get_buffer_size(target, buffer);
+    let end = offset + std::mem::size_of_val(data);
+
+    if end > size {
+      return Err(RenderError::BufferOutOfRange(handle, offset, end, size));
+    }
+
+    self.ctx.update_buffer(target, buffer, offset, data);
+
+    Ok(())
+  }
+
+  /// Uploads interleaved vertex data once, accessors into it come from
+  /// `insert_interleaved_accessors`
+  pub fn insert_interleaved_buffer(&mut self, usage: BufferUsage, data: &[u8]) -> Index {
+    self.insert_buffer(BufferTarget::ArrayBuffer, usage, data)
+  }
+
+  /// Every layout entry carries its own offset and the shared stride
+  pub fn insert_interleaved_accessors(
+    &mut self,
+    buffer: Index,
+    count: i32,
+    layout: &[AttributeOptions],
+  ) -> Vec<Index> {
+    layout
+      .iter()
+      .map(|options| {
+        self.accessors.insert(Accessor {
+          buffer,
+          count,
+          options: options.clone(),
+          data: None,
+        })
+      })
+      .collect()
+  }
+
   pub fn bake_material(&mut self, material: Box<dyn Material>) -> Index {
-    self.checkup_shader(&material);
+    self
+      .try_bake_material(material)
+      .unwrap_or_else(|e| panic!("{}", e))
+  }
+
+  pub fn try_bake_material(&mut self, material: Box<dyn Material>) -> Result<Index, RenderError> {
+    self.checkup_shader(&material)?;
 
-    self.materials.insert(material)
+    *self.shader_refs.entry(material.get_tag()).or_insert(0) += 1;
+
+    Ok(self.materials.insert(material))
   }
 
   pub fn insert_node(&mut self, node: Node) -> Index {
@@ -213,13 +1094,41 @@ impl Renderer {
     self.geometries.insert(geometry)
   }
 
-  pub fn insert_image(&mut self, image: WebGlTexture) -> Index {
-    self.images.insert(image)
-  }
+  /// Returns the already inserted geometry built from the same accessors, instanced
+  /// geometry is always inserted anew
+  pub fn intern_geometry(&mut self, geometry: Geometry) -> Index {
+    if geometry.instances.is_some() {
+      return self.insert_geometry(geometry);
+    }
 
-  pub fn insert_sampler(&mut self, sampler: Sampler) -> Index {
-    self.samplers.insert(sampler)
-  }
+    let mut attributes: Vec<(AttributeName, Index)> = geometry
+      .attributes
+      .iter()
+      .map(|(name, handle)| (name.clone(), *handle))
+      .collect();
+    attributes.sort();
+
+    let key = (attributes, geometry.indices, geometry.draw_mode);
+
+    if let Some(handle) = self.geometry_keys.get(&key) {
+      if self.geometries.contains(*handle) {
+        return *handle;
+      }
+    }
+
+    let handle = self.insert_geometry(geometry);
+    self.geometry_keys.insert(key, handle);
+
+    handle
+  }
+
+  pub fn insert_image(&mut self, image: WebGlTexture) -> Index {
+    self.images.insert(image)
+  }
+
+  pub fn insert_sampler(&mut self, sampler: Sampler) -> Index {
+    self.samplers.insert(sampler)
+  }
 
   pub fn insert_texture(&mut self, texture: Texture) -> Index {
     self.textures.insert(texture)
@@ -229,41 +1138,429 @@ impl Renderer {
     self.framebuffers.insert(fb)
   }
 
+  pub fn insert_renderbuffer(&mut self, renderbuffer: WebGlRenderbuffer) -> Index {
+    self.renderbuffers.insert(renderbuffer)
+  }
+
   pub fn insert_render_target(&mut self, target: RenderTarget) -> Index {
     self.targets.insert(target)
   }
 
+  /// Fails while an accessor still reads from the buffer
+  pub fn remove_buffer(&mut self, handle: Index) -> Option<()> {
+    if is_buffer_used(&self.accessors, handle) {
+      return None;
+    }
+
+    let buffer = self.buffers.remove(handle)?;
+
+    self.ctx.delete_buffer(Some(&buffer));
+
+    Some(())
+  }
+
+  /// Removes only the mesh record, its geometries and materials stay alive
+  pub fn remove_mesh(&mut self, handle: Index) -> Option<()> {
+    self.meshes.remove(handle)?;
+
+    Some(())
+  }
+
+  /// Fails while a mesh still draws the geometry, its accessors and buffers stay alive
+  pub fn remove_geometry(&mut self, handle: Index) -> Option<()> {
+    if is_geometry_used(&self.meshes, handle) {
+      return None;
+    }
+
+    self.geometries.remove(handle)?;
+    self.geometry_keys.retain(|_, geometry| *geometry != handle);
+
+    let ctx = &self.ctx;
+
+    self
+      .vertex_arrays
+      .borrow_mut()
+      .retain(|(geometry, _), vao| {
+        if *geometry == handle {
+          ctx.delete_vertex_array(Some(vao));
+        }
+
+        *geometry != handle
+      });
+
+    Some(())
+  }
+
+  /// Fails while a mesh or a node override still uses the material, the shader goes with
+  /// the last material of its tag
+  pub fn remove_material(&mut self, handle: Index) -> Option<()> {
+    if is_material_used(&self.meshes, &self.scene, handle) {
+      return None;
+    }
+
+    let tag = self.materials.remove(handle)?.get_tag();
+
+    if release_shader_ref(&mut self.shader_refs, &tag) {
+      self.remove_shader(&tag);
+    }
+
+    Some(())
+  }
+
+  /// Deletes the program along with the vertex arrays bound to its attribute locations
+  fn remove_shader(&mut self, tag: &str) -> Option<()> {
+    let shader = self.shaders.remove(tag)?;
+    let ctx = &self.ctx;
+
+    ctx.delete_program(Some(shader.get_program()));
+
+    self.vertex_arrays.borrow_mut().retain(|(_, vao_tag), vao| {
+      if vao_tag == tag {
+        ctx.delete_vertex_array(Some(vao));
+      }
+
+      vao_tag != tag
+    });
+
+    Some(())
+  }
+
+  pub fn set_clear_color(&mut self, r: f32, g: f32, b: f32, a: f32) {
+    self.clear_color = Vector4::new(r, g, b, a);
+  }
+
+  pub fn set_strict_attributes(&mut self, strict_attributes: bool) {
+    self.strict_attributes = strict_attributes;
+  }
+
+  pub fn set_auto_clear(&mut self, auto_clear: bool) {
+    self.auto_clear = auto_clear;
+  }
+
+  /// Only affects accessors created afterwards
+  pub fn set_retain_vertex_data(&mut self, retain_vertex_data: bool) {
+    self.retain_vertex_data = retain_vertex_data;
+  }
+
+  pub fn set_output_gamma(&mut self, output_gamma: bool) -> RenderResult {
+    if self.output_gamma == output_gamma {
+      return Ok(());
+    }
+
+    self.output_gamma = output_gamma;
+    self.recompile_shaders()
+  }
+
+  pub fn set_tone_mapping(&mut self, tone_mapping: ToneMapping) -> RenderResult {
+    if self.tone_mapping == tone_mapping {
+      return Ok(());
+    }
+
+    self.tone_mapping = tone_mapping;
+    self.recompile_shaders()
+  }
+
+  pub fn clear(&self) {
+    let c = &self.clear_color;
+
+    self.ctx.clear_color(c.x, c.y, c.z, c.w);
+    self.ctx.clear_depth(self.get_clear_depth());
+    self.ctx.clear(true, true);
+  }
+
+  /// Keeps the color buffer, for passes drawing on top of a previous one
+  pub fn clear_depth(&self) {
+    self.ctx.clear_depth(self.get_clear_depth());
+    self.ctx.clear(false, true);
+  }
+
+  pub fn get_clear_depth(&self) -> f32 {
+    if self.reverse_z {
+      0.0
+    } else {
+      1.0
+    }
+  }
+
+  /// World matrices are taken as is, call scene.update_matrix_world() beforehand
   pub fn render_scene(&self, root_handle: Index, camera_handle: Index) {
-    let visible_items = self.scene.collect_visible_sub_items(root_handle);
-    let camera = self.cameras.get(camera_handle).unwrap();
+    if let Err(e) = self.try_render_scene(root_handle, camera_handle) {
+      error!("render error: {}", e);
+    }
+  }
+
+  pub fn try_render_scene(&self, root_handle: Index, camera_handle: Index) -> RenderResult {
+    self.render_scene_with_stats(root_handle, camera_handle)?;
+
+    Ok(())
+  }
+
+  /// Draws into one rectangle of the canvas, e.g. once per eye with side by side stereo
+  /// cameras. The scissor keeps the auto clear inside the rectangle, camera aspects are up
+  /// to the caller.
+  pub fn render_scene_viewport(
+    &self,
+    root_handle: Index,
+    camera_handle: Index,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+  ) -> RenderResult {
+    let viewport = self.ctx.get_viewport();
+    let scissor = self.ctx.get_scissor();
+    let scissor_test = self.ctx.is_enabled(Feature::ScissorTest);
+
+    self.ctx.viewport(x, y, width, height);
+    self.ctx.scissor(x, y, width, height);
+    self.ctx.set(Feature::ScissorTest, true);
+
+    let result = self.try_render_scene(root_handle, camera_handle);
+    let [x, y, width, height] = viewport;
+
+    self.ctx.viewport(x, y, width, height);
+
+    let [x, y, width, height] = scissor;
+
+    self.ctx.scissor(x, y, width, height);
+    self.ctx.set(Feature::ScissorTest, scissor_test);
+
+    result
+  }
+
+  pub fn render_scene_with_stats(
+    &self,
+    root_handle: Index,
+    camera_handle: Index,
+  ) -> Result<RenderStats, RenderError> {
+    if self.ctx.is_lost() {
+      return Ok(RenderStats::default());
+    }
+
+    let camera = self
+      .cameras
+      .get(camera_handle)
+      .ok_or(RenderError::MissingCamera(camera_handle))?;
+    let frustum = Frustum::from_matrix(&(camera.projection * camera.view));
+
+    if self.shadow_map.is_some() {
+      self.render_shadow_map(root_handle)?;
+    }
+
+    if self.auto_clear {
+      self.clear();
+    }
+
+    let camera_position = camera.get_position();
+    let distance = |item: &DrawItem| {
+      let m = &item.node.matrix_world;
+
+      (Vector3::new(m[12], m[13], m[14]) - camera_position).norm_squared()
+    };
+
+    let mut state = DrawState {
+      reverse_z: self.reverse_z,
+      ..Default::default()
+    };
+
+    let (mut transparent, mut opaque): (Vec<DrawItem>, Vec<DrawItem>) = self
+      .collect_draw_items(root_handle, &frustum, &mut state.stats)?
+      .into_iter()
+      .partition(|item| item.material.is_transparent());
+
+    opaque.sort_by(|a, b| {
+      a.node
+        .render_order
+        .cmp(&b.node.render_order)
+        .then_with(|| a.tag.cmp(&b.tag))
+    });
+    transparent.sort_by(|a, b| {
+      a.node.render_order.cmp(&b.node.render_order).then_with(|| {
+        distance(b)
+          .partial_cmp(&distance(a))
+          .unwrap_or(Ordering::Equal)
+      })
+    });
 
-    for handle in visible_items {
-      let node = self.scene.get_node(handle).unwrap();
-      let mesh = self.meshes.get(node.mesh.unwrap()).unwrap();
+    self.render_depth_prepass(&opaque, camera, &mut state)?;
+
+    for item in &opaque {
+      self.draw_call(item, camera, &mut state)?;
+    }
+
+    state.depth_prepassed = false;
+
+    if !transparent.is_empty() {
+      self
+        .ctx
+        .blend_func(BlendFactor::SrcAlpha, BlendFactor::OneMinusSrcAlpha);
+
+      for item in &transparent {
+        self.draw_call(item, camera, &mut state)?;
+      }
+    }
+
+    self.ctx.set(Feature::Blend, false);
+    self.ctx.depth_mask(true);
+    self.restore_front_face(&state);
+
+    Ok(state.stats)
+  }
+
+  /// Clears the target, renders into it and restores the default framebuffer and viewport
+  pub fn render_scene_to_target(
+    &self,
+    root_handle: Index,
+    camera_handle: Index,
+    target_handle: Index,
+  ) -> RenderResult {
+    let target = self
+      .targets
+      .get(target_handle)
+      .ok_or(RenderError::MissingTarget(target_handle))?;
+    let fb = self
+      .framebuffers
+      .get(target.get_draw_framebuffer())
+      .ok_or(RenderError::MissingTarget(target_handle))?;
+    let [x, y, width, height] = self.ctx.get_viewport();
+
+    self.ctx.bind_framebuffer(Some(fb));
+    self
+      .ctx
+      .viewport(0, 0, target.width as i32, target.height as i32);
+    self.ctx.clear_depth(self.get_clear_depth());
+    self.ctx.clear(true, true);
+
+    let result = self.try_render_scene(root_handle, camera_handle);
+
+    self.ctx.bind_framebuffer(None);
+    self.ctx.viewport(x, y, width, height);
+
+    result
+  }
+
+  pub fn collect_draw_items(
+    &self,
+    root_handle: Index,
+    frustum: &Frustum,
+    stats: &mut RenderStats,
+  ) -> Result<Vec<DrawItem<'_>>, RenderError> {
+    let mut items: Vec<DrawItem> = vec![];
+
+    for handle in self.scene.collect_visible_sub_items(root_handle) {
+      let node = self
+        .scene
+        .get_node(handle)
+        .ok_or(RenderError::MissingNode(handle))?;
+
+      let mesh_handle = match node.mesh {
+        Some(mesh_handle) => mesh_handle,
+        None => continue,
+      };
+
+      let mesh = self
+        .meshes
+        .get(mesh_handle)
+        .ok_or(RenderError::MissingMesh(mesh_handle))?;
 
       for primitive in &mesh.primitives {
-        if let Some(material_handle) = primitive.material {
-          let geometry = self.geometries.get(primitive.geometry).unwrap();
-          let material = self.materials.get(material_handle).unwrap();
+        let material_handle = node
+          .material_override
+          .or(primitive.material)
+          .unwrap_or(self.default_material);
+        let geometry = self
+          .geometries
+          .get(primitive.geometry)
+          .ok_or(RenderError::MissingGeometry(primitive.geometry))?;
 
-          self.draw_call(geometry, material, node, camera);
+        // skinned vertices can leave the bind pose bounds
+        if geometry.instances.is_none() && node.skin.is_none() {
+          let world = &node.matrix_world;
+          // the sphere rejects most off-screen nodes before transforming box corners
+          let outside = geometry
+            .bounding_sphere
+            .is_some_and(|sphere| !frustum.intersects_sphere(&sphere.transform(world)))
+            || geometry
+              .bounds
+              .is_some_and(|bounds| !frustum.intersects_aabb(&bounds.transform(world)));
+
+          if outside {
+            stats.culled += 1;
+            continue;
+          }
         }
+
+        let material = self
+          .materials
+          .get(material_handle)
+          .ok_or(RenderError::MissingMaterial(material_handle))?;
+
+        items.push(DrawItem {
+          node_handle: handle,
+          geometry_handle: primitive.geometry,
+          geometry,
+          material,
+          node,
+          tag: material.get_tag(),
+        });
       }
     }
+
+    Ok(items)
   }
 
-  pub fn draw_call(
-    &self,
-    geometry: &Geometry,
-    material: &Box<dyn Material>,
-    node: &Node,
-    camera: &Camera,
-  ) {
-    let tag = material.get_tag();
+  /// Passes leave the default winding behind for code drawing outside of draw_call
+  pub fn restore_front_face(&self, state: &DrawState) {
+    if state.front_face == Some(FrontFace::Cw) {
+      self.ctx.set_front_face(FrontFace::Ccw);
+    }
+  }
+
+  pub fn draw_call(&self, item: &DrawItem, camera: &Camera, state: &mut DrawState) -> RenderResult {
+    let DrawItem {
+      node_handle: _,
+      geometry_handle,
+      geometry,
+      material,
+      node,
+      tag,
+    } = item;
+
+    let shader = self
+      .shaders
+      .get(tag)
+      .ok_or_else(|| RenderError::MissingShader(tag.clone()))?;
+
+    if let (Some(buffer), false) = (&self.camera_block, state.camera_block) {
+      let mut data = Vec::with_capacity(32);
+
+      data.extend_from_slice(camera.projection.as_slice());
+      data.extend_from_slice(camera.view.as_slice());
+      self
+        .ctx
+        .update_uniform_buffer(buffer, CAMERA_BLOCK_BINDING, &data);
+      state.camera_block = true;
+    }
 
-    let shader = self.shaders.get(&tag).unwrap();
+    if state.tag.as_ref() != Some(tag) {
+      shader.bind();
+
+      // the camera is fixed for a pass, so plain uniforms only need setting once per bind
+      if !shader.uses_camera_block() {
+        shader.set_matrix4("projectionMatrix", &camera.projection);
+        shader.set_matrix4("viewMatrix", &camera.view);
+      }
 
-    shader.bind();
+      self.setup_lights(shader);
+      self.setup_environment(shader);
+
+      if !state.shadow_pass {
+        self.setup_shadow(shader);
+      }
+
+      state.stats.shader_binds += 1;
+      state.tag = Some(tag.clone());
+    }
 
     material.setup_shader(
       &self.ctx,
@@ -275,44 +1572,444 @@ impl Renderer {
       camera,
     );
 
+    self.setup_skin(shader, node);
+
     let params = material.params();
 
-    self.ctx.set(Feature::CullFace, params.cull_face);
-    self.ctx.set(Feature::DepthTest, params.depth_test);
-    self.ctx.depth_func(params.depth_func);
+    if state.cull_face != Some(params.cull_face) {
+      self.ctx.set(Feature::CullFace, params.cull_face);
+      state.cull_face = Some(params.cull_face);
+    }
+
+    if state.depth_test != Some(params.depth_test) {
+      self.ctx.set(Feature::DepthTest, params.depth_test);
+      state.depth_test = Some(params.depth_test);
+    }
+
+    if state.depth_write != Some(params.depth_write) {
+      self.ctx.depth_mask(params.depth_write);
+      state.depth_write = Some(params.depth_write);
+    }
+
+    if state.polygon_offset != Some(params.polygon_offset) {
+      if let Some((factor, units)) = params.polygon_offset {
+        self.ctx.set_polygon_offset(factor, units);
+      }
+
+      self
+        .ctx
+        .set(Feature::PolygonOffsetFill, params.polygon_offset.is_some());
+      state.polygon_offset = Some(params.polygon_offset);
+    }
+
+    let blend = material.is_transparent();
+
+    if state.blend != Some(blend) {
+      self.ctx.set(Feature::Blend, blend);
+      state.blend = Some(blend);
+    }
+
+    // Lequal rather than Equal, the color shaders aren't guaranteed to compute the same
+    // depth bits as the pre-pass ones and Equal would drop fragments that land a hair off
+    let depth_func = if state.depth_prepassed && is_depth_prepass_item(item) {
+      if state.reverse_z {
+        DepthFunc::Gequal
+      } else {
+        DepthFunc::Lequal
+      }
+    } else if state.reverse_z {
+      params.depth_func.reversed()
+    } else {
+      params.depth_func
+    };
+
+    if state.depth_func != Some(depth_func) {
+      self.ctx.depth_func(depth_func);
+      state.depth_func = Some(depth_func);
+    }
+
+    // negative scale turns the winding inside out
+    let front_face = if node.matrix_world.fixed_slice::<3, 3>(0, 0).determinant() < 0.0 {
+      FrontFace::Cw
+    } else {
+      FrontFace::Ccw
+    };
+
+    if state.front_face != Some(front_face) {
+      self.ctx.set_front_face(front_face);
+      state.front_face = Some(front_face);
+    }
+
+    // instance divisors are reset after every draw, so instanced geometry keeps the manual path
+    let use_vertex_array = self.ctx.has_vertex_arrays() && geometry.instances.is_none();
 
-    let mut attr_amount = 0;
+    let mut count;
+    let mut instance_location = None;
+
+    if use_vertex_array {
+      let mut vertex_arrays = self.vertex_arrays.borrow_mut();
+      let key = (*geometry_handle, tag.clone());
+
+      if let Some(vertex_array) = vertex_arrays.get(&key) {
+        self.ctx.bind_vertex_array(Some(vertex_array));
+        count = self.get_vertex_count(geometry, shader)?;
+      } else {
+        let vertex_array = self
+          .ctx
+          .create_vertex_array()
+          .ok_or_else(|| RenderError::MissingExtension("OES_vertex_array_object".to_string()))?;
+
+        self.ctx.bind_vertex_array(Some(&vertex_array));
+
+        let bound = match self.bind_attributes(geometry, shader, tag) {
+          Ok(bound) => {
+            for location in &bound.locations {
+              self.ctx.enable_attribute(*location);
+            }
+
+            bound
+          }
+          Err(e) => {
+            self.ctx.bind_vertex_array(None);
+            self.ctx.delete_vertex_array(Some(&vertex_array));
+            return Err(e);
+          }
+        };
+
+        vertex_arrays.insert(key, vertex_array);
+        count = bound.count;
+      }
+    } else {
+      let bound = self.bind_attributes(geometry, shader, tag)?;
+
+      self.ctx.switch_attributes(&bound.locations);
+
+      count = bound.count;
+      instance_location = bound.instance_location;
+    }
+
+    let draw_mode = geometry.draw_mode.unwrap_or(params.draw_mode);
+    let lines = draw_mode.is_lines() || (self.wireframe && geometry.wireframe.is_some());
+
+    if lines && state.line_width != Some(params.line_width) {
+      self.ctx.set_line_width(params.line_width);
+      state.line_width = Some(params.line_width);
+    }
+
+    if let (Some(location), Some(instances)) = (instance_location, &geometry.instances) {
+      return self.draw_instanced(
+        geometry,
+        location,
+        instances.count,
+        draw_mode,
+        count,
+        &mut state.stats,
+      );
+    }
+
+    let (indices_handle, draw_mode) = match (self.wireframe, geometry.wireframe) {
+      (true, Some(wireframe_handle)) => (Some(wireframe_handle), DrawMode::Lines),
+      _ => (geometry.indices, draw_mode),
+    };
+
+    if let Some(accessor_handle) = indices_handle {
+      let accessor = self
+        .accessors
+        .get(accessor_handle)
+        .ok_or(RenderError::MissingAccessor(accessor_handle))?;
+      let indices = self
+        .buffers
+        .get(accessor.buffer)
+        .ok_or(RenderError::MissingBuffer(accessor.buffer))?;
+      if let (TypedArrayKind::Uint32, false) = (
+        accessor.options.component_type,
+        self.ctx.has_element_index_uint(),
+      ) {
+        return Err(RenderError::MissingExtension(String::from(
+          "OES_element_index_uint",
+        )));
+      }
+
+      count = accessor.count;
+      self
+        .ctx
+        .bind_buffer(BufferTarget::ElementArrayBuffer, Some(indices));
+      self.ctx.draw_elements(
+        draw_mode,
+        count,
+        accessor.options.component_type,
+        accessor.options.offset,
+      );
+      state.stats.count_draw(draw_mode, count, 1);
+    } else {
+      self.ctx.draw_arrays(draw_mode, 0, count);
+      state.stats.count_draw(draw_mode, count, 1);
+    }
+
+    if use_vertex_array {
+      self.ctx.bind_vertex_array(None);
+    }
+
+    Ok(())
+  }
+
+  /// Enabling the returned locations is up to the caller, it differs with and without vertex arrays
+  fn bind_attributes(
+    &self,
+    geometry: &Geometry,
+    shader: &Shader,
+    tag: &str,
+  ) -> Result<BoundAttributes, RenderError> {
+    let mut locations = vec![];
     let mut count = 0;
+    let mut instance_location = None;
+
+    for (name, location) in shader.get_attribute_locations() {
+      if *name == AttributeName::InstanceMatrix {
+        if let Some(instances) = &geometry.instances {
+          let buffer = self
+            .buffers
+            .get(instances.buffer)
+            .ok_or(RenderError::MissingBuffer(instances.buffer))?;
+          self
+            .ctx
+            .bind_buffer(BufferTarget::ArrayBuffer, Some(buffer));
+          instance_location = shader.bind_matrix4_attribute(name);
+          locations.extend((0..4).map(|i| location + i));
+        }
+
+        continue;
+      }
 
-    for name in shader.get_attribute_locations().keys() {
       if let Some(accessor_handle) = geometry.attributes.get(name) {
-        let accessor = self.accessors.get(*accessor_handle).unwrap();
-        let buffer = self.buffers.get(accessor.buffer).unwrap();
+        let accessor = self
+          .accessors
+          .get(*accessor_handle)
+          .ok_or(RenderError::MissingAccessor(*accessor_handle))?;
+        let buffer = self
+          .buffers
+          .get(accessor.buffer)
+          .ok_or(RenderError::MissingBuffer(accessor.buffer))?;
         self
           .ctx
           .bind_buffer(BufferTarget::ArrayBuffer, Some(buffer));
         shader.bind_attribute(name, &accessor.options);
+        locations.push(*location);
 
         count = accessor.count;
+      } else {
+        if self.strict_attributes {
+          return Err(RenderError::MissingAttribute(tag.to_string(), name.clone()));
+        }
+
+        let key = (tag.to_string(), name.clone());
+
+        if self.missing_attributes.borrow_mut().insert(key) {
+          warn!("{:?} is missing from a geometry drawn with {}", name, tag);
+        }
       }
+    }
 
-      attr_amount += 1;
+    Ok(BoundAttributes {
+      locations,
+      count,
+      instance_location,
+    })
+  }
+
+  fn get_vertex_count(&self, geometry: &Geometry, shader: &Shader) -> Result<i32, RenderError> {
+    let mut count = 0;
+
+    for name in shader.get_attribute_locations().keys() {
+      if let Some(accessor_handle) = geometry.attributes.get(name) {
+        let accessor = self
+          .accessors
+          .get(*accessor_handle)
+          .ok_or(RenderError::MissingAccessor(*accessor_handle))?;
+
+        count = accessor.count;
+      }
     }
 
-    self.ctx.switch_attributes(attr_amount);
+    Ok(count)
+  }
+
+  fn draw_instanced(
+    &self,
+    geometry: &Geometry,
+    location: u32,
+    instances: i32,
+    draw_mode: DrawMode,
+    count: i32,
+    stats: &mut RenderStats,
+  ) -> RenderResult {
+    let missing = || RenderError::MissingExtension("ANGLE_instanced_arrays".to_string());
+
+    for i in 0..4 {
+      self
+        .ctx
+        .vertex_attrib_divisor(location + i, 1)
+        .ok_or_else(missing)?;
+    }
 
     if let Some(accessor_handle) = geometry.indices {
-      let accessor = self.accessors.get(accessor_handle).unwrap();
-      let indices = self.buffers.get(accessor.buffer).unwrap();
-      count = accessor.count;
+      let accessor = self
+        .accessors
+        .get(accessor_handle)
+        .ok_or(RenderError::MissingAccessor(accessor_handle))?;
+      let indices = self
+        .buffers
+        .get(accessor.buffer)
+        .ok_or(RenderError::MissingBuffer(accessor.buffer))?;
       self
         .ctx
         .bind_buffer(BufferTarget::ElementArrayBuffer, Some(indices));
+      self.ctx.draw_elements_instanced(
+        draw_mode,
+        accessor.count,
+        accessor.options.component_type,
+        accessor.options.offset,
+        instances,
+      );
+      stats.count_draw(draw_mode, accessor.count, instances);
+    } else {
       self
         .ctx
-        .draw_elements(params.draw_mode, count, accessor.options.component_type, 0);
-    } else {
-      self.ctx.draw_arrays(params.draw_mode, 0, count);
+        .draw_arrays_instanced(draw_mode, 0, count, instances);
+      stats.count_draw(draw_mode, count, instances);
     }
+
+    for i in 0..4 {
+      self.ctx.vertex_attrib_divisor(location + i, 0);
+    }
+
+    Ok(())
+  }
+}
+
+pub fn is_buffer_used(accessors: &Accessors, handle: Index) -> bool {
+  accessors
+    .iter()
+    .any(|(_, accessor)| accessor.buffer == handle)
+}
+
+pub fn is_geometry_used(meshes: &Meshes, handle: Index) -> bool {
+  meshes
+    .iter()
+    .flat_map(|(_, mesh)| &mesh.primitives)
+    .any(|primitive| primitive.geometry == handle)
+}
+
+/// Node overrides only count for nodes attached to the scene root
+pub fn is_material_used(meshes: &Meshes, scene: &Scene, handle: Index) -> bool {
+  let mut used = meshes
+    .iter()
+    .flat_map(|(_, mesh)| &mesh.primitives)
+    .any(|primitive| primitive.material == Some(handle));
+
+  scene.traverse(scene.get_root_handle(), |_, node| {
+    used |= node.material_override == Some(handle);
+  });
+
+  used
+}
+
+/// True when the last material with the tag went away and its shader can be deleted
+pub fn release_shader_ref(shader_refs: &mut ShaderRefs, tag: &str) -> bool {
+  match shader_refs.get_mut(tag) {
+    Some(count) if *count > 1 => {
+      *count -= 1;
+      false
+    }
+    Some(_) => {
+      shader_refs.remove(tag);
+      true
+    }
+    None => false,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn mesh_with(geometry: Index, material: Option<Index>) -> Mesh {
+    Mesh {
+      primitives: vec![Primitive { geometry, material }],
+      name: None,
+    }
+  }
+
+  #[test]
+  fn geometries_stay_in_use_until_their_meshes_go() {
+    let mut geometries: Arena<()> = Arena::new();
+    let geometry = geometries.insert(());
+    let unused = geometries.insert(());
+    let mut meshes = Meshes::new();
+    let mesh = meshes.insert(mesh_with(geometry, None));
+
+    assert!(is_geometry_used(&meshes, geometry));
+    assert!(!is_geometry_used(&meshes, unused));
+
+    meshes.remove(mesh);
+
+    assert!(!meshes.contains(mesh));
+    assert!(!is_geometry_used(&meshes, geometry));
+  }
+
+  #[test]
+  fn materials_count_meshes_and_node_overrides() {
+    let mut materials = Materials::new();
+    let on_mesh = materials.insert(PbrMaterial::new().boxed());
+    let on_node = materials.insert(PbrMaterial::new().boxed());
+    let unused = materials.insert(PbrMaterial::new().boxed());
+    let mut meshes = Meshes::new();
+    let mut scene = Scene::new();
+    let mut node = Node::new(Some(scene.get_root_handle()));
+
+    meshes.insert(mesh_with(Index::from_raw_parts(0, 0), Some(on_mesh)));
+    node.material_override = Some(on_node);
+
+    let node_handle = scene.insert(node);
+
+    assert!(is_material_used(&meshes, &scene, on_mesh));
+    assert!(is_material_used(&meshes, &scene, on_node));
+    assert!(!is_material_used(&meshes, &scene, unused));
+
+    scene.remove(node_handle);
+
+    assert!(!is_material_used(&meshes, &scene, on_node));
+  }
+
+  #[test]
+  fn buffers_stay_in_use_while_accessors_read_them() {
+    let mut buffers: Arena<()> = Arena::new();
+    let buffer = buffers.insert(());
+    let mut accessors = Accessors::new();
+    let accessor = accessors.insert(Accessor {
+      buffer,
+      count: 3,
+      options: AttributeOptions::new(TypedArrayKind::Float32, 3),
+      data: None,
+    });
+
+    assert!(is_buffer_used(&accessors, buffer));
+
+    accessors.remove(accessor);
+
+    assert!(!is_buffer_used(&accessors, buffer));
+  }
+
+  #[test]
+  fn shaders_go_with_the_last_material_of_their_tag() {
+    let mut shader_refs = ShaderRefs::new();
+
+    shader_refs.insert(String::from("pbr"), 2);
+
+    assert!(!release_shader_ref(&mut shader_refs, "pbr"));
+    assert_eq!(shader_refs.get("pbr"), Some(&1));
+    assert!(release_shader_ref(&mut shader_refs, "pbr"));
+    assert!(!shader_refs.contains_key("pbr"));
+    assert!(!release_shader_ref(&mut shader_refs, "pbr"));
   }
 }
@@ -1,17 +1,35 @@
 use anyhow::{anyhow, Result};
-use log::error;
 use na::{Matrix3, Matrix4, Vector2, Vector3, Vector4};
+use std::cell::RefCell;
 use std::collections::HashMap;
-use web_sys::{WebGlProgram, WebGlRenderingContext, WebGlShader, WebGlUniformLocation};
+use wasm_bindgen::JsCast;
+use web_sys::{
+  WebGl2RenderingContext, WebGlProgram, WebGlRenderingContext, WebGlShader, WebGlUniformLocation,
+};
 
 use super::context::TypedArrayKind;
 use super::define::Define;
+use super::renderer::RenderError;
 
-#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+/// Renderer define asking for the camera matrices as a WebGL2 uniform block
+pub const CAMERA_BLOCK_DEFINE: &str = "USE_CAMERA_BLOCK";
+pub const CAMERA_BLOCK_NAME: &str = "Camera";
+pub const CAMERA_BLOCK_BINDING: u32 = 0;
+
+/// Locations taken by the known attributes, custom ones are bound right after
+pub const FIXED_ATTRIBUTE_LOCATIONS: u32 = 12;
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub enum AttributeName {
   Position,
   Normal,
   Uv,
+  Uv2,
+  Tangent,
+  Color,
+  Joints,
+  Weights,
+  InstanceMatrix,
   Custom(String),
 }
 
@@ -21,9 +39,54 @@ impl AttributeName {
       "position" => AttributeName::Position,
       "normal" => AttributeName::Normal,
       "uv" => AttributeName::Uv,
+      "uv2" => AttributeName::Uv2,
+      "tangent" => AttributeName::Tangent,
+      "color" => AttributeName::Color,
+      "joints" => AttributeName::Joints,
+      "weights" => AttributeName::Weights,
+      "instanceMatrix" => AttributeName::InstanceMatrix,
       _ => AttributeName::Custom(name.to_string()),
     }
   }
+
+  pub fn get_name(&self) -> &str {
+    match self {
+      AttributeName::Position => "position",
+      AttributeName::Normal => "normal",
+      AttributeName::Uv => "uv",
+      AttributeName::Uv2 => "uv2",
+      AttributeName::Tangent => "tangent",
+      AttributeName::Color => "color",
+      AttributeName::Joints => "joints",
+      AttributeName::Weights => "weights",
+      AttributeName::InstanceMatrix => "instanceMatrix",
+      AttributeName::Custom(name) => name,
+    }
+  }
+
+  /// Same location in every program, so one vertex array fits any shader drawing the geometry.
+  /// The instance matrix spans four locations, one per column.
+  pub fn get_fixed_location(&self) -> Option<u32> {
+    match self {
+      AttributeName::Position => Some(0),
+      AttributeName::Normal => Some(1),
+      AttributeName::Uv => Some(2),
+      AttributeName::Uv2 => Some(3),
+      AttributeName::Tangent => Some(4),
+      AttributeName::Color => Some(5),
+      AttributeName::Joints => Some(6),
+      AttributeName::Weights => Some(7),
+      AttributeName::InstanceMatrix => Some(8),
+      AttributeName::Custom(_) => None,
+    }
+  }
+
+  fn get_location_count(&self) -> u32 {
+    match self {
+      AttributeName::InstanceMatrix => 4,
+      _ => 1,
+    }
+  }
 }
 
 #[derive(Debug)]
@@ -31,7 +94,9 @@ pub struct Shader {
   gl: WebGlRenderingContext,
   program: WebGlProgram,
   attribute_locations: HashMap<AttributeName, u32>,
-  uniform_locations: HashMap<String, WebGlUniformLocation>,
+  uniform_names: Vec<String>,
+  uniform_locations: RefCell<HashMap<String, Option<WebGlUniformLocation>>>,
+  camera_block: bool,
 }
 
 impl Shader {
@@ -40,23 +105,69 @@ impl Shader {
     vertex_src: &str,
     fragment_src: &str,
     defines: &[Define],
-  ) -> Result<Shader> {
-    let vert = add_header(vertex_src, defines, false);
-    let frag = add_header(fragment_src, defines, true);
-
-    let vert_shader = compile_shader(gl, WebGlRenderingContext::VERTEX_SHADER, &vert)?;
-    let frag_shader = compile_shader(gl, WebGlRenderingContext::FRAGMENT_SHADER, &frag)?;
-
-    let program = link_program(&gl, &vert_shader, &frag_shader)?;
+  ) -> Result<Shader, RenderError> {
+    // uniform blocks need GLSL ES 3.00, so the sources get upgraded along with the define
+    let webgl2 = gl.dyn_ref::<WebGl2RenderingContext>();
+    let camera_block = webgl2.is_some()
+      && defines
+        .iter()
+        .any(|define| define.name == CAMERA_BLOCK_DEFINE);
+    let defines: Vec<Define> = defines
+      .iter()
+      .filter(|define| camera_block || define.name != CAMERA_BLOCK_DEFINE)
+      .cloned()
+      .collect();
+    let defines = defines.as_slice();
+
+    let (vert, frag) = if camera_block {
+      (
+        format!(
+          "#version 300 es\n{}",
+          add_header(&upgrade_source(vertex_src, false), defines, false)
+        ),
+        format!(
+          "#version 300 es\n{}",
+          add_header(&upgrade_source(fragment_src, true), defines, true)
+        ),
+      )
+    } else {
+      (
+        add_header(vertex_src, defines, false),
+        add_header(fragment_src, defines, true),
+      )
+    };
+
+    let with_defines = || format!("defines: [{}]", get_define_names(defines));
+    let compile_error =
+      |e: anyhow::Error| RenderError::ShaderCompile(with_defines(), format!("{:#}", e));
+
+    let vert_shader =
+      compile_shader(gl, WebGlRenderingContext::VERTEX_SHADER, &vert).map_err(compile_error)?;
+    let frag_shader =
+      compile_shader(gl, WebGlRenderingContext::FRAGMENT_SHADER, &frag).map_err(compile_error)?;
+
+    let program = link_program(&gl, &vert_shader, &frag_shader)
+      .map_err(|e| RenderError::ShaderLink(with_defines(), format!("{:#}", e)))?;
+
+    if let (true, Some(gl)) = (camera_block, webgl2) {
+      let index = gl.get_uniform_block_index(&program, CAMERA_BLOCK_NAME);
+
+      // the block is optimized out when no stage reads the matrices
+      if index != WebGl2RenderingContext::INVALID_INDEX {
+        gl.uniform_block_binding(&program, index, CAMERA_BLOCK_BINDING);
+      }
+    }
 
     let attribute_locations = collect_attributes(gl, &program);
-    let uniform_locations = collect_uniforms(gl, &program);
+    let uniform_names = collect_uniform_names(gl, &program);
 
     Ok(Shader {
       gl: gl.clone(),
       program,
       attribute_locations,
-      uniform_locations,
+      uniform_names,
+      uniform_locations: RefCell::new(HashMap::new()),
+      camera_block,
     })
   }
 
@@ -64,6 +175,15 @@ impl Shader {
     self.gl.use_program(Some(&self.program));
   }
 
+  pub fn get_program(&self) -> &WebGlProgram {
+    &self.program
+  }
+
+  /// True when the camera matrices come from the shared uniform buffer
+  pub fn uses_camera_block(&self) -> bool {
+    self.camera_block
+  }
+
   pub fn bind_attribute(&self, name: &AttributeName, attribute: &AttributeOptions) -> Option<()> {
     let location = self.attribute_locations.get(name)?;
 
@@ -79,87 +199,131 @@ impl Shader {
     Some(())
   }
 
+  pub fn bind_matrix4_attribute(&self, name: &AttributeName) -> Option<u32> {
+    let location = self.attribute_locations.get(name)?;
+
+    for i in 0..4 {
+      self.gl.vertex_attrib_pointer_with_i32(
+        *location + i,
+        4,
+        WebGlRenderingContext::FLOAT,
+        false,
+        64,
+        16 * i as i32,
+      );
+    }
+
+    Some(*location)
+  }
+
   pub fn set_bool(&self, name: &str, v: bool) -> Option<()> {
-    let location = self.uniform_locations.get(name)?;
+    let location = self.get_uniform_location(name)?;
 
-    self.gl.uniform1i(Some(location), if v { 1 } else { 0 });
+    self.gl.uniform1i(Some(&location), if v { 1 } else { 0 });
 
     Some(())
   }
 
   pub fn set_float(&self, name: &str, v: f32) -> Option<()> {
-    let location = self.uniform_locations.get(name)?;
+    let location = self.get_uniform_location(name)?;
 
-    self.gl.uniform1f(Some(location), v);
+    self.gl.uniform1f(Some(&location), v);
 
     Some(())
   }
 
   pub fn set_integer(&self, name: &str, v: i32) -> Option<()> {
-    let location = self.uniform_locations.get(name)?;
+    let location = self.get_uniform_location(name)?;
 
-    self.gl.uniform1i(Some(location), v);
+    self.gl.uniform1i(Some(&location), v);
 
     Some(())
   }
 
   pub fn set_vector4(&self, name: &str, v: &Vector4<f32>) -> Option<()> {
-    let location = self.uniform_locations.get(name)?;
+    let location = self.get_uniform_location(name)?;
 
-    self.gl.uniform4f(Some(location), v.x, v.y, v.z, v.w);
+    self.gl.uniform4f(Some(&location), v.x, v.y, v.z, v.w);
 
     Some(())
   }
 
   pub fn set_vector3(&self, name: &str, v: &Vector3<f32>) -> Option<()> {
-    let location = self.uniform_locations.get(name)?;
+    let location = self.get_uniform_location(name)?;
 
-    self.gl.uniform3f(Some(location), v.x, v.y, v.z);
+    self.gl.uniform3f(Some(&location), v.x, v.y, v.z);
 
     Some(())
   }
 
   pub fn set_vector2(&self, name: &str, v: &Vector2<f32>) -> Option<()> {
-    let location = self.uniform_locations.get(name)?;
+    let location = self.get_uniform_location(name)?;
 
-    self.gl.uniform2f(Some(location), v.x, v.y);
+    self.gl.uniform2f(Some(&location), v.x, v.y);
 
     Some(())
   }
 
   pub fn set_matrix4(&self, name: &str, m: &Matrix4<f32>) -> Option<()> {
-    let location = self.uniform_locations.get(name)?;
+    let location = self.get_uniform_location(name)?;
 
     self
       .gl
-      .uniform_matrix4fv_with_f32_array(Some(location), false, &m.data.as_slice());
+      .uniform_matrix4fv_with_f32_array(Some(&location), false, &m.data.as_slice());
 
     Some(())
   }
 
   pub fn set_matrix3(&self, name: &str, m: &Matrix3<f32>) -> Option<()> {
-    let location = self.uniform_locations.get(name)?;
+    let location = self.get_uniform_location(name)?;
 
     self
       .gl
-      .uniform_matrix3fv_with_f32_array(Some(location), false, &m.data.as_slice());
+      .uniform_matrix3fv_with_f32_array(Some(&location), false, &m.data.as_slice());
 
     Some(())
   }
 
   pub fn set_matrix4_data(&self, name: &str, data: &[f32]) -> Option<()> {
-    let location = self.uniform_locations.get(name)?;
+    let location = self.get_uniform_location(name)?;
 
     self
       .gl
-      .uniform_matrix4fv_with_f32_array(Some(location), false, data);
+      .uniform_matrix4fv_with_f32_array(Some(&location), false, data);
 
     Some(())
   }
 
+  /// Looks the uniform up once and remembers the result, unknown names included.
+  pub fn get_uniform_location(&self, name: &str) -> Option<WebGlUniformLocation> {
+    let mut uniform_locations = self.uniform_locations.borrow_mut();
+
+    if let Some(location) = uniform_locations.get(name) {
+      return location.clone();
+    }
+
+    let location = self.gl.get_uniform_location(&self.program, name);
+    uniform_locations.insert(name.to_string(), location.clone());
+
+    location
+  }
+
   pub fn get_attribute_locations(&self) -> &HashMap<AttributeName, u32> {
     &self.attribute_locations
   }
+
+  /// Active attributes after linking, sorted
+  pub fn attribute_names(&self) -> Vec<AttributeName> {
+    let mut names: Vec<AttributeName> = self.attribute_locations.keys().cloned().collect();
+
+    names.sort();
+    names
+  }
+
+  /// Active uniforms after linking, sorted. Arrays are reported once as `name[0]`
+  pub fn uniform_names(&self) -> Vec<String> {
+    self.uniform_names.clone()
+  }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -205,12 +369,18 @@ pub fn compile_shader(
       .get_shader_info_log(&shader)
       .unwrap_or_else(|| String::from("Unknown error creating shader"));
 
-    error!(
-      "{}",
-      &format!("\n{}\n\n{}\n", message, add_row_numbers(source))
-    );
-
-    Err(anyhow!("shader compile error"))
+    let kind = if shader_type == WebGlRenderingContext::VERTEX_SHADER {
+      "vertex"
+    } else {
+      "fragment"
+    };
+
+    Err(anyhow!(
+      "{} shader compile error\n{}\n\n{}",
+      kind,
+      message,
+      add_row_numbers(source)
+    ))
   }
 }
 
@@ -225,16 +395,72 @@ pub fn link_program(
 
   gl.attach_shader(&program, vert_shader);
   gl.attach_shader(&program, frag_shader);
-  gl.link_program(&program);
+
+  let max_locations = gl
+    .get_parameter(WebGlRenderingContext::MAX_VERTEX_ATTRIBS)
+    .ok()
+    .and_then(|value| value.as_f64())
+    .unwrap_or(8.0) as u32;
+  let fixed = [
+    AttributeName::Position,
+    AttributeName::Normal,
+    AttributeName::Uv,
+    AttributeName::Uv2,
+    AttributeName::Tangent,
+    AttributeName::Color,
+    AttributeName::Joints,
+    AttributeName::Weights,
+    AttributeName::InstanceMatrix,
+  ];
+
+  // binding past the limit is an error, those attributes fall back to linker locations
+  for name in &fixed {
+    if let Some(location) = name.get_fixed_location() {
+      if location + name.get_location_count() <= max_locations {
+        gl.bind_attrib_location(&program, location, name.get_name());
+      }
+    }
+  }
+
+  link_and_check(gl, &program)?;
+
+  // custom names are only known from the linked program, so they take a second link
+  let mut custom: Vec<String> = collect_attributes(gl, &program)
+    .into_keys()
+    .filter_map(|name| match name {
+      AttributeName::Custom(name) => Some(name),
+      _ => None,
+    })
+    .collect();
+
+  if !custom.is_empty() {
+    custom.sort();
+
+    for (i, name) in custom.iter().enumerate() {
+      let location = FIXED_ATTRIBUTE_LOCATIONS + i as u32;
+
+      if location < max_locations {
+        gl.bind_attrib_location(&program, location, name);
+      }
+    }
+
+    link_and_check(gl, &program)?;
+  }
+
+  Ok(program)
+}
+
+fn link_and_check(gl: &WebGlRenderingContext, program: &WebGlProgram) -> Result<()> {
+  gl.link_program(program);
 
   if gl
-    .get_program_parameter(&program, WebGlRenderingContext::LINK_STATUS)
+    .get_program_parameter(program, WebGlRenderingContext::LINK_STATUS)
     .as_bool()
     .unwrap_or(false)
   {
-    Ok(program)
+    Ok(())
   } else {
-    Err(anyhow!(gl.get_program_info_log(&program).unwrap_or_else(
+    Err(anyhow!(gl.get_program_info_log(program).unwrap_or_else(
       || String::from("Unknown error creating program object")
     )))
   }
@@ -264,26 +490,79 @@ pub fn collect_attributes(
   locations
 }
 
-pub fn collect_uniforms(
-  gl: &WebGlRenderingContext,
-  program: &WebGlProgram,
-) -> HashMap<String, WebGlUniformLocation> {
-  let num_attributes = gl
+pub fn collect_uniform_names(gl: &WebGlRenderingContext, program: &WebGlProgram) -> Vec<String> {
+  let num_uniforms = gl
     .get_program_parameter(program, WebGlRenderingContext::ACTIVE_UNIFORMS)
     .as_f64()
     .unwrap_or(0.0) as u32;
 
-  let mut locations: HashMap<String, WebGlUniformLocation> = HashMap::new();
+  let mut names: Vec<String> = (0..num_uniforms)
+    .filter_map(|i| gl.get_active_uniform(program, i))
+    .map(|info| info.name())
+    .collect();
 
-  for i in 0..num_attributes {
-    if let Some(info) = gl.get_active_uniform(program, i) {
-      if let Some(loc) = gl.get_uniform_location(program, &info.name()) {
-        locations.insert(info.name(), loc);
-      }
+  names.sort();
+  names
+}
+
+/// Rewrites GLSL ES 1.00 into 3.00, the version line is left to the caller
+pub fn upgrade_source(src: &str, fragment: bool) -> String {
+  let replacements: &[(&str, &str)] = if fragment {
+    &[
+      ("varying", "in"),
+      ("texture2D", "texture"),
+      ("textureCube", "texture"),
+      ("gl_FragColor", "fragColor"),
+    ]
+  } else {
+    &[
+      ("attribute", "in"),
+      ("varying", "out"),
+      ("texture2D", "texture"),
+      ("textureCube", "texture"),
+    ]
+  };
+
+  let mut result = String::with_capacity(src.len());
+  let mut word = String::new();
+
+  let flush = |word: &mut String, result: &mut String| {
+    let replaced = replacements
+      .iter()
+      .find(|(from, _)| from == word)
+      .map_or(word.as_str(), |(_, to)| to);
+
+    result.push_str(replaced);
+    word.clear();
+  };
+
+  for c in src.chars() {
+    if c.is_ascii_alphanumeric() || c == '_' {
+      word.push(c);
+    } else {
+      flush(&mut word, &mut result);
+      result.push(c);
     }
   }
 
-  locations
+  flush(&mut word, &mut result);
+
+  if fragment {
+    result.insert_str(0, "out vec4 fragColor;\n\n");
+  }
+
+  result
+}
+
+pub fn get_define_names(defines: &[Define]) -> String {
+  defines
+    .iter()
+    .map(|define| match &define.value {
+      Some(value) => format!("{}={}", define.name, value),
+      None => define.name.clone(),
+    })
+    .collect::<Vec<String>>()
+    .join(", ")
 }
 
 pub fn add_row_numbers(src: &str) -> String {
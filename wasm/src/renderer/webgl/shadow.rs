@@ -0,0 +1,245 @@
+use generational_arena::Index;
+use na::{Matrix4, Point3, Vector3};
+use std::cell::Cell;
+
+use super::context::{Feature, TextureKind};
+use super::frustum::Frustum;
+use super::material::material::bind_texture;
+use super::material::ShadowMaterial;
+use super::renderer::{
+  Aabb, Camera, DepthAttachment, DrawItem, DrawState, RenderError, RenderResult, Renderer, Sampler,
+  ShadowMap,
+};
+use super::shader::Shader;
+
+// the environment map takes unit 6
+pub const SHADOW_MAP_UNIT: u32 = 7;
+
+impl Renderer {
+  /// Casts shadows from the active directional light into a square depth map
+  pub fn enable_shadows(&mut self, size: u32, bias: f32) -> RenderResult {
+    self.disable_shadows()?;
+
+    let target =
+      self.bake_render_target(size, size, Sampler::framebuffer(), DepthAttachment::Texture)?;
+
+    let materials = [
+      self.try_bake_material(ShadowMaterial::new(false, false).boxed())?,
+      self.try_bake_material(ShadowMaterial::new(true, false).boxed())?,
+      self.try_bake_material(ShadowMaterial::new(false, true).boxed())?,
+      self.try_bake_material(ShadowMaterial::new(true, true).boxed())?,
+    ];
+
+    self.shadow_map = Some(ShadowMap {
+      target,
+      size,
+      bias,
+      materials,
+      matrix: Cell::new(Matrix4::identity()),
+    });
+
+    self.recompile_shaders()
+  }
+
+  pub fn disable_shadows(&mut self) -> RenderResult {
+    let shadow_map = match self.shadow_map.take() {
+      Some(shadow_map) => shadow_map,
+      None => return Ok(()),
+    };
+
+    self.remove_render_target(shadow_map.target);
+
+    for handle in shadow_map.materials {
+      self.remove_material(handle);
+    }
+
+    self.recompile_shaders()
+  }
+
+  /// Union of the world bounds of every visible primitive with known bounds
+  pub fn compute_scene_bounds(&self, root_handle: Index) -> Option<Aabb> {
+    let mut result: Option<Aabb> = None;
+
+    for handle in self.scene.collect_visible_sub_items(root_handle) {
+      let node = match self.scene.get_node(handle) {
+        Some(node) => node,
+        None => continue,
+      };
+
+      let mesh = match node.mesh.and_then(|handle| self.meshes.get(handle)) {
+        Some(mesh) => mesh,
+        None => continue,
+      };
+
+      for primitive in &mesh.primitives {
+        let bounds = match self
+          .geometries
+          .get(primitive.geometry)
+          .and_then(|geometry| geometry.bounds.as_ref())
+        {
+          Some(bounds) => bounds.transform(&node.matrix_world),
+          None => continue,
+        };
+
+        result = Some(match result {
+          Some(result) => result.union(&bounds),
+          None => bounds,
+        });
+      }
+    }
+
+    result
+  }
+
+  /// Orthographic camera looking along the light direction, fitting the bounding sphere
+  pub fn get_light_camera(&self, direction: &Vector3<f32>, bounds: &Aabb) -> Camera {
+    let radius = match bounds.size().norm() * 0.5 {
+      r if r > f32::EPSILON => r,
+      _ => 1.0,
+    };
+
+    let direction = direction.normalize();
+    let up = if direction.y.abs() > 0.99 {
+      Vector3::z()
+    } else {
+      Vector3::y()
+    };
+
+    let target = Point3::from(bounds.center());
+    let eye = target - direction * radius * 2.0;
+
+    Camera::orthographic(-radius, radius, -radius, radius, radius, radius * 3.0)
+      .look_at(&eye, &target, &up)
+  }
+
+  pub fn render_shadow_map(&self, root_handle: Index) -> RenderResult {
+    let shadow_map = match &self.shadow_map {
+      Some(shadow_map) => shadow_map,
+      None => return Ok(()),
+    };
+
+    let (light, bounds) = match (
+      self.get_active_light(),
+      self.compute_scene_bounds(root_handle),
+    ) {
+      (Some(light), Some(bounds)) => (light, bounds),
+      _ => return Ok(()),
+    };
+
+    let camera = self.get_light_camera(&light.direction, &bounds);
+    let target = self
+      .targets
+      .get(shadow_map.target)
+      .ok_or(RenderError::MissingTarget(shadow_map.target))?;
+    let fb = self
+      .framebuffers
+      .get(target.fb)
+      .ok_or(RenderError::MissingTarget(shadow_map.target))?;
+
+    let mut materials = Vec::with_capacity(shadow_map.materials.len());
+
+    for handle in shadow_map.materials {
+      materials.push(
+        self
+          .materials
+          .get(handle)
+          .ok_or(RenderError::MissingMaterial(handle))?,
+      );
+    }
+
+    let frustum = Frustum::from_matrix(&(camera.projection * camera.view));
+    let mut state = DrawState {
+      shadow_pass: true,
+      ..Default::default()
+    };
+    let items = self.collect_draw_items(root_handle, &frustum, &mut state.stats)?;
+
+    let [x, y, width, height] = self.ctx.get_viewport();
+    // rendering to a target binds its framebuffer before the shadow pass runs
+    let framebuffer = self.ctx.get_framebuffer();
+    // a viewport render scissors the canvas, the shadow map needs all of its texels
+    let scissor = self.ctx.is_enabled(Feature::ScissorTest);
+
+    self.ctx.bind_framebuffer(Some(fb));
+    self
+      .ctx
+      .viewport(0, 0, target.width as i32, target.height as i32);
+    self.ctx.set(Feature::ScissorTest, false);
+    self.ctx.color_mask(false);
+    // the last frame left the depth texture on its unit, sampling it now is a feedback loop
+    self.ctx.active_texture(SHADOW_MAP_UNIT);
+    self.ctx.bind_texture(TextureKind::Texture2d, None);
+    // the light camera always uses the standard depth range
+    self.ctx.clear_depth(1.0);
+    self.ctx.clear(true, true);
+
+    let mut result = Ok(());
+
+    // blended surfaces don't cast shadows, alpha masked ones draw their own material so
+    // discarded texels let light through
+    for item in items.iter().filter(|item| !item.material.is_transparent()) {
+      result = if item.material.is_alpha_masked() {
+        self.draw_call(item, &camera, &mut state)
+      } else {
+        let variant =
+          item.geometry.instances.is_some() as usize | (item.node.skin.is_some() as usize) << 1;
+        let material = materials[variant];
+        let shadow_item = DrawItem {
+          material,
+          tag: material.get_tag(),
+          ..*item
+        };
+
+        self.draw_call(&shadow_item, &camera, &mut state)
+      };
+
+      if result.is_err() {
+        break;
+      }
+    }
+
+    self.ctx.bind_framebuffer(framebuffer.as_ref());
+    self.ctx.viewport(x, y, width, height);
+    self.ctx.set(Feature::ScissorTest, scissor);
+    self.ctx.color_mask(true);
+    self.ctx.set(Feature::Blend, false);
+    self.ctx.depth_mask(true);
+    self.restore_front_face(&state);
+
+    shadow_map.matrix.set(camera.projection * camera.view);
+
+    result
+  }
+
+  pub fn setup_shadow(&self, shader: &Shader) {
+    let shadow_map = match &self.shadow_map {
+      Some(shadow_map) => shadow_map,
+      None => return,
+    };
+
+    let depth_texture = match self
+      .targets
+      .get(shadow_map.target)
+      .and_then(|target| target.depth_texture)
+    {
+      Some(depth_texture) => depth_texture,
+      None => return,
+    };
+
+    shader.set_matrix4("shadowMatrix", &shadow_map.matrix.get());
+    shader.set_float("shadowBias", shadow_map.bias);
+    shader.set_float("shadowMapSize", shadow_map.size as f32);
+
+    bind_texture(
+      &self.ctx,
+      &self.images,
+      &self.textures,
+      &self.samplers,
+      shader,
+      depth_texture,
+      TextureKind::Texture2d,
+      "shadowMap",
+      SHADOW_MAP_UNIT,
+    );
+  }
+}
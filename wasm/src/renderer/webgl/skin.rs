@@ -0,0 +1,49 @@
+use generational_arena::Index;
+use na::Matrix4;
+
+use super::renderer::{Renderer, Skin};
+use super::shader::Shader;
+use crate::scene::node::Node;
+
+/// Joints past the limit are left out of the uniform array
+pub const MAX_JOINTS: usize = 64;
+
+impl Renderer {
+  pub fn insert_skin(&mut self, skin: Skin) -> Index {
+    self.skins.insert(skin)
+  }
+
+  pub fn remove_skin(&mut self, handle: Index) -> Option<()> {
+    self.skins.remove(handle)?;
+
+    Some(())
+  }
+
+  /// Joint matrices come from the current world matrices, update them before rendering
+  pub fn setup_skin(&self, shader: &Shader, node: &Node) -> Option<()> {
+    let skin = self.skins.get(node.skin?)?;
+    let node_inverse = node
+      .matrix_world
+      .try_inverse()
+      .unwrap_or_else(Matrix4::identity);
+
+    for (i, joint_handle) in skin.joints.iter().take(MAX_JOINTS).enumerate() {
+      let joint = match self.scene.get_node(*joint_handle) {
+        Some(joint) => joint,
+        None => continue,
+      };
+      let inverse_bind_matrix = skin
+        .inverse_bind_matrices
+        .get(i)
+        .cloned()
+        .unwrap_or_else(Matrix4::identity);
+
+      shader.set_matrix4(
+        &format!("jointMatrices[{}]", i),
+        &(node_inverse * joint.matrix_world * inverse_bind_matrix),
+      );
+    }
+
+    Some(())
+  }
+}
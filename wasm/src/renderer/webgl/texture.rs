@@ -1,8 +1,12 @@
+use anyhow::{anyhow, Result};
 use generational_arena::Index;
-use web_sys::{HtmlImageElement, WebGlTexture};
+use js_sys::{Array, Uint8Array};
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Blob, BlobPropertyBag, HtmlImageElement, ImageBitmap, WebGlTexture};
 
-use super::context::{TextureFormat, TextureKind, TypedArrayKind};
-use super::renderer::{Renderer, Sampler, Texture};
+use super::context::{CompressedTextureFormat, TextureFormat, TextureKind, TypedArrayKind};
+use super::renderer::{RenderError, Renderer, Sampler, Texture};
 
 impl Renderer {
   pub fn bake_2d_texture(
@@ -11,6 +15,7 @@ impl Renderer {
     sampler: Sampler,
     image: &HtmlImageElement,
   ) -> Index {
+    let sampler = sampler.fit_size(image.natural_width(), image.natural_height());
     let webgl_texture = self.ctx.create_texture().unwrap();
 
     self
@@ -29,19 +34,149 @@ impl Renderer {
       )
       .unwrap();
 
-    self.ctx.generate_mipmap(TextureKind::Texture2d);
+    if sampler.mipmaps {
+      self.ctx.generate_mipmap(TextureKind::Texture2d);
+    }
 
     self.ctx.bind_texture(TextureKind::Texture2d, None);
 
     self.compose_texture(webgl_texture, sampler)
   }
 
+  pub fn bake_2d_image_bitmap(
+    &mut self,
+    format: TextureFormat,
+    sampler: Sampler,
+    image: &ImageBitmap,
+  ) -> Result<Index> {
+    let sampler = sampler.fit_size(image.width(), image.height());
+    let webgl_texture = self.ctx.create_texture().unwrap();
+
+    self
+      .ctx
+      .bind_texture(TextureKind::Texture2d, Some(&webgl_texture));
+
+    let result = self.ctx.texture_image_bitmap_data(
+      TextureKind::Texture2d,
+      0,
+      format,
+      format,
+      TypedArrayKind::Uint8,
+      image,
+    );
+
+    if result.is_ok() && sampler.mipmaps {
+      self.ctx.generate_mipmap(TextureKind::Texture2d);
+    }
+
+    self.ctx.bind_texture(TextureKind::Texture2d, None);
+
+    if let Err(e) = result {
+      self.ctx.delete_texture(Some(&webgl_texture));
+      return Err(e);
+    }
+
+    Ok(self.compose_texture(webgl_texture, sampler))
+  }
+
+  /// Decodes PNG/JPEG bytes with the browser and uploads them as an RGBA texture. The glTF
+  /// loader stays synchronous and decodes embedded images with the image crate instead.
+  pub async fn create_texture_from_bytes(&mut self, bytes: &[u8], mime: &str) -> Result<Index> {
+    let image = decode_image_bitmap(bytes, mime).await?;
+
+    self.bake_2d_image_bitmap(TextureFormat::RGBA, Sampler::default(), &image)
+  }
+
+  /// Levels are transcoded blocks from the full size down, mipmaps can't be generated
+  /// for compressed data so a single level disables mip filtering
+  pub fn create_compressed_texture(
+    &mut self,
+    format: CompressedTextureFormat,
+    sampler: Sampler,
+    width: u32,
+    height: u32,
+    levels: &[&[u8]],
+  ) -> Result<Index, RenderError> {
+    if !self.ctx.supports_compressed_format(format) {
+      return Err(RenderError::MissingExtension(
+        format.get_extension_name().to_string(),
+      ));
+    }
+
+    let mut sampler = sampler;
+
+    sampler.mipmaps &= levels.len() > 1;
+
+    let sampler = sampler.fit_size(width, height);
+    let webgl_texture = self.ctx.create_texture().unwrap();
+
+    self
+      .ctx
+      .bind_texture(TextureKind::Texture2d, Some(&webgl_texture));
+
+    for (level, data) in levels.iter().enumerate() {
+      self.ctx.compressed_texture_data(
+        TextureKind::Texture2d,
+        level as i32,
+        format,
+        (width >> level).max(1) as i32,
+        (height >> level).max(1) as i32,
+        data,
+      );
+    }
+
+    self.ctx.bind_texture(TextureKind::Texture2d, None);
+
+    Ok(self.compose_texture(webgl_texture, sampler))
+  }
+
+  pub fn bake_2d_image_data(
+    &mut self,
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    data: &[u8],
+    mipmaps: bool,
+  ) -> Index {
+    let webgl_texture = self.ctx.create_texture().unwrap();
+
+    self
+      .ctx
+      .bind_texture(TextureKind::Texture2d, Some(&webgl_texture));
+
+    self
+      .ctx
+      .texture_data(
+        TextureKind::Texture2d,
+        0,
+        format,
+        width as i32,
+        height as i32,
+        0,
+        format,
+        data,
+      )
+      .unwrap();
+
+    if mipmaps && width.is_power_of_two() && height.is_power_of_two() {
+      self.ctx.generate_mipmap(TextureKind::Texture2d);
+    }
+
+    self.ctx.bind_texture(TextureKind::Texture2d, None);
+
+    self.insert_image(webgl_texture)
+  }
+
   pub fn bake_cube_map_texture(
     &mut self,
     format: TextureFormat,
     sampler: Sampler,
     src: &[(TextureKind, &HtmlImageElement)],
   ) -> Index {
+    let sampler = match src.first() {
+      Some((_, image)) => sampler.fit_size(image.natural_width(), image.natural_height()),
+      None => sampler,
+    };
     let webgl_texture = self.ctx.create_texture().unwrap();
 
     self
@@ -55,13 +190,58 @@ impl Renderer {
         .unwrap();
     }
 
-    self.ctx.generate_mipmap(TextureKind::CubeMap);
+    if sampler.mipmaps {
+      self.ctx.generate_mipmap(TextureKind::CubeMap);
+    }
 
     self.ctx.bind_texture(TextureKind::CubeMap, None);
 
     self.compose_texture(webgl_texture, sampler)
   }
 
+  /// Faces go in GL order: +X, -X, +Y, -Y, +Z, -Z.
+  pub fn bake_cube_map(
+    &mut self,
+    format: TextureFormat,
+    faces: [&HtmlImageElement; 6],
+  ) -> Result<Index, RenderError> {
+    let size = faces[0].natural_width();
+
+    if faces
+      .iter()
+      .any(|face| face.natural_width() != size || face.natural_height() != size)
+    {
+      return Err(RenderError::MismatchedCubeMapFaces);
+    }
+
+    let kinds = [
+      TextureKind::CubeMapPX,
+      TextureKind::CubeMapNX,
+      TextureKind::CubeMapPY,
+      TextureKind::CubeMapNY,
+      TextureKind::CubeMapPZ,
+      TextureKind::CubeMapNZ,
+    ];
+
+    let src: Vec<(TextureKind, &HtmlImageElement)> =
+      kinds.iter().copied().zip(faces.iter().copied()).collect();
+
+    Ok(self.bake_cube_map_texture(format, Sampler::default(), &src))
+  }
+
+  /// Deletes the texture together with its image and sampler
+  pub fn remove_texture(&mut self, handle: Index) -> Option<()> {
+    let texture = self.textures.remove(handle)?;
+
+    if let Some(image) = self.images.remove(texture.source) {
+      self.ctx.delete_texture(Some(&image));
+    }
+
+    self.samplers.remove(texture.sampler);
+
+    Some(())
+  }
+
   pub fn compose_texture(&mut self, image: WebGlTexture, sampler: Sampler) -> Index {
     let image_handle = self.insert_image(image);
     let sampler_handle = self.insert_sampler(sampler);
@@ -74,3 +254,21 @@ impl Renderer {
     self.insert_texture(texture)
   }
 }
+
+pub async fn decode_image_bitmap(bytes: &[u8], mime: &str) -> Result<ImageBitmap> {
+  let parts = Array::of1(&Uint8Array::from(bytes));
+  let blob =
+    Blob::new_with_u8_array_sequence_and_options(&parts, BlobPropertyBag::new().type_(mime))
+      .map_err(|e| anyhow!("{:?}", e))?;
+
+  let window = web_sys::window().ok_or_else(|| anyhow!("no window to decode images with"))?;
+  let promise = window
+    .create_image_bitmap_with_blob(&blob)
+    .map_err(|e| anyhow!("{:?}", e))?;
+
+  let image = JsFuture::from(promise)
+    .await
+    .map_err(|e| anyhow!("unable to decode {} image: {:?}", mime, e))?;
+
+  Ok(image.unchecked_into::<ImageBitmap>())
+}
@@ -1,8 +1,8 @@
 use generational_arena::Index;
-use na::{Isometry3, Point2, Point3, Vector3};
+use na::{Point2, Point3, Vector3};
 use std::f32::consts::PI;
 
-use super::renderer::Renderer;
+use super::renderer::{LookAt, Renderer};
 
 pub struct Turntable {
   pub roll: f32,
@@ -53,11 +53,12 @@ impl Turntable {
       r * self.roll.cos().abs() * self.pitch.cos(),
     ) + self.center;
 
-    let view =
-      Isometry3::look_at_rh(&position, &Point3::from(self.center), &Vector3::y()).to_homogeneous();
-
     let camera = renderer.cameras.get_mut(camera_handle).unwrap();
 
-    camera.view = view;
+    camera.set_look_at(LookAt {
+      eye: position,
+      target: Point3::from(self.center),
+      up: Vector3::y(),
+    });
   }
 }
@@ -0,0 +1,177 @@
+use generational_arena::Index;
+
+use super::context::{BufferTarget, DrawMode, TypedArrayKind};
+use super::gltf::narrow_indices;
+use super::mesh::as_bytes;
+use super::renderer::{Accessor, Renderer};
+use super::shader::AttributeName;
+
+impl Renderer {
+  pub fn set_wireframe(&mut self, wireframe: bool) {
+    if wireframe {
+      let handles: Vec<Index> = self.geometries.iter().map(|(handle, _)| handle).collect();
+
+      for handle in handles {
+        self.bake_wireframe(handle);
+      }
+    }
+
+    self.wireframe = wireframe;
+  }
+
+  pub fn bake_wireframe(&mut self, geometry_handle: Index) -> Option<Index> {
+    let geometry = self.geometries.get(geometry_handle)?;
+
+    if let Some(wireframe_handle) = geometry.wireframe {
+      return Some(wireframe_handle);
+    }
+
+    let draw_mode = geometry.draw_mode.unwrap_or(DrawMode::Triangles);
+
+    let indices = match geometry.indices {
+      Some(accessor_handle) => read_indices(self.accessors.get(accessor_handle)?)?,
+      None => {
+        let position_handle = geometry.attributes.get(&AttributeName::Position)?;
+        let count = self.accessors.get(*position_handle)?.count as u32;
+
+        (0..count).collect()
+      }
+    };
+
+    // points and lines have no edges to extract
+    let lines = get_triangle_edges(&get_triangle_list(&indices, draw_mode)?);
+    let count = lines.len() as i32;
+    let wireframe_handle = if self.ctx.has_element_index_uint() {
+      self.bake_buffer_accessor(BufferTarget::ElementArrayBuffer, &lines, 1, count)
+    } else {
+      // same limit as the source indices, anything past 16 bits can't be drawn
+      let lines = narrow_indices(as_bytes(&lines)).ok()?;
+
+      self.bake_buffer_accessor(BufferTarget::ElementArrayBuffer, &lines, 1, count)
+    };
+
+    self.geometries.get_mut(geometry_handle)?.wireframe = Some(wireframe_handle);
+
+    Some(wireframe_handle)
+  }
+}
+
+pub fn read_indices(accessor: &Accessor) -> Option<Vec<u32>> {
+  let data = accessor.data.as_ref()?;
+
+  let indices = match accessor.options.component_type {
+    TypedArrayKind::Uint8 => data.iter().map(|i| *i as u32).collect(),
+    TypedArrayKind::Uint16 => data
+      .chunks_exact(2)
+      .map(|c| u16::from_le_bytes([c[0], c[1]]) as u32)
+      .collect(),
+    TypedArrayKind::Uint32 => data
+      .chunks_exact(4)
+      .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+      .collect(),
+    _ => return None,
+  };
+
+  Some(indices)
+}
+
+/// Unrolls strips and fans into a plain triangle list, None for non-triangle modes.
+/// Strip triangles alternate winding so every other one gets swapped back to CCW, and
+/// degenerate triangles used to stitch strips together are dropped.
+pub fn get_triangle_list(indices: &[u32], draw_mode: DrawMode) -> Option<Vec<u32>> {
+  let triangles: Vec<[u32; 3]> = match draw_mode {
+    DrawMode::Triangles => indices
+      .chunks_exact(3)
+      .map(|t| [t[0], t[1], t[2]])
+      .collect(),
+    DrawMode::TriangleStrip => indices
+      .windows(3)
+      .enumerate()
+      .map(|(i, t)| match i % 2 {
+        0 => [t[0], t[1], t[2]],
+        _ => [t[1], t[0], t[2]],
+      })
+      .collect(),
+    DrawMode::TriangleFan => indices
+      .windows(2)
+      .skip(1)
+      .map(|t| [indices[0], t[0], t[1]])
+      .collect(),
+    DrawMode::Points | DrawMode::Lines | DrawMode::LineLoop | DrawMode::LineStrip => return None,
+  };
+
+  Some(
+    triangles
+      .into_iter()
+      .filter(|[a, b, c]| draw_mode == DrawMode::Triangles || (a != b && b != c && c != a))
+      .flatten()
+      .collect(),
+  )
+}
+
+pub fn get_triangle_edges(indices: &[u32]) -> Vec<u32> {
+  let mut lines = Vec::with_capacity(indices.len() * 2);
+
+  for triangle in indices.chunks_exact(3) {
+    let (a, b, c) = (triangle[0], triangle[1], triangle[2]);
+
+    lines.extend_from_slice(&[a, b, b, c, c, a]);
+  }
+
+  lines
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn edges_narrow_to_16_bit_until_an_index_overflows() {
+    let lines = get_triangle_edges(&[0, 1, 2]);
+
+    assert_eq!(
+      narrow_indices(as_bytes(&lines)).unwrap(),
+      vec![0u16, 1, 1, 2, 2, 0]
+    );
+
+    let lines = get_triangle_edges(&[0, 1, 70_000]);
+
+    assert!(narrow_indices(as_bytes(&lines)).is_err());
+  }
+
+  #[test]
+  fn strip_edges_follow_each_triangle() {
+    let list = get_triangle_list(&[0, 1, 2, 3, 4], DrawMode::TriangleStrip).unwrap();
+
+    assert_eq!(list, vec![0, 1, 2, 2, 1, 3, 2, 3, 4]);
+    assert_eq!(
+      get_triangle_edges(&list),
+      vec![0, 1, 1, 2, 2, 0, 2, 1, 1, 3, 3, 2, 2, 3, 3, 4, 4, 2]
+    );
+  }
+
+  #[test]
+  fn fan_edges_share_the_first_vertex() {
+    let list = get_triangle_list(&[0, 1, 2, 3], DrawMode::TriangleFan).unwrap();
+
+    assert_eq!(list, vec![0, 1, 2, 0, 2, 3]);
+    assert_eq!(
+      get_triangle_edges(&list),
+      vec![0, 1, 1, 2, 2, 0, 0, 2, 2, 3, 3, 0]
+    );
+  }
+
+  #[test]
+  fn stitched_strips_drop_degenerate_triangles() {
+    let strip = [0, 1, 2, 3, 3, 4, 4, 5, 6, 7];
+    let list = get_triangle_list(&strip, DrawMode::TriangleStrip).unwrap();
+
+    assert_eq!(list, vec![0, 1, 2, 2, 1, 3, 4, 5, 6, 6, 5, 7]);
+    assert_eq!(get_triangle_edges(&list).len(), 4 * 6);
+  }
+
+  #[test]
+  fn line_modes_have_no_triangles() {
+    assert_eq!(get_triangle_list(&[0, 1, 2], DrawMode::LineStrip), None);
+  }
+}
@@ -0,0 +1,257 @@
+use generational_arena::Index;
+use na::{Quaternion, UnitQuaternion, Vector3, Vector4};
+use std::collections::HashMap;
+
+use super::node::Node;
+use super::scene::Scene;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+  Linear,
+  Step,
+}
+
+#[derive(Debug, Clone)]
+pub enum ChannelValues {
+  Translation(Vec<Vector3<f32>>),
+  Rotation(Vec<UnitQuaternion<f32>>),
+  Scale(Vec<Vector3<f32>>),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ChannelValue {
+  Translation(Vector3<f32>),
+  Rotation(UnitQuaternion<f32>),
+  Scale(Vector3<f32>),
+}
+
+impl ChannelValue {
+  pub fn apply(&self, node: &mut Node) {
+    match *self {
+      ChannelValue::Translation(translation) => node.set_translation(translation),
+      ChannelValue::Rotation(rotation) => node.set_rotation(rotation),
+      ChannelValue::Scale(scale) => node.set_scale(scale),
+    };
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct Channel {
+  pub node: Index,
+  pub interpolation: Interpolation,
+  pub times: Vec<f32>,
+  pub values: ChannelValues,
+}
+
+impl Channel {
+  /// Returns the keyframes surrounding time and the blend factor between them
+  fn get_keys(&self, time: f32) -> Option<(usize, usize, f32)> {
+    let last = self.times.len().checked_sub(1)?;
+    let next = self.times.partition_point(|t| *t <= time);
+
+    if next == 0 {
+      return Some((0, 0, 0.0));
+    }
+
+    if next > last {
+      return Some((last, last, 0.0));
+    }
+
+    let prev = next - 1;
+    let span = self.times[next] - self.times[prev];
+    let factor = match self.interpolation {
+      Interpolation::Step => 0.0,
+      Interpolation::Linear if span > 0.0 => (time - self.times[prev]) / span,
+      Interpolation::Linear => 0.0,
+    };
+
+    Some((prev, next, factor))
+  }
+
+  pub fn get_value(&self, time: f32) -> Option<ChannelValue> {
+    let (prev, next, factor) = self.get_keys(time)?;
+
+    let value = match &self.values {
+      ChannelValues::Translation(values) => {
+        ChannelValue::Translation(values.get(prev)?.lerp(values.get(next)?, factor))
+      }
+      ChannelValues::Rotation(values) => {
+        let (a, b) = (values.get(prev)?, values.get(next)?);
+
+        ChannelValue::Rotation(a.try_slerp(b, factor, 1.0e-6).unwrap_or(*a))
+      }
+      ChannelValues::Scale(values) => {
+        ChannelValue::Scale(values.get(prev)?.lerp(values.get(next)?, factor))
+      }
+    };
+
+    Some(value)
+  }
+
+  pub fn sample(&self, time: f32, scene: &mut Scene) -> Option<()> {
+    let value = self.get_value(time)?;
+
+    value.apply(scene.get_node_mut(self.node)?);
+
+    Some(())
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct Animation {
+  pub name: Option<String>,
+  pub channels: Vec<Channel>,
+  pub duration: f32,
+}
+
+impl Animation {
+  pub fn new(name: Option<String>, channels: Vec<Channel>) -> Self {
+    let duration = channels
+      .iter()
+      .filter_map(|channel| channel.times.last())
+      .fold(0.0, |a: f32, b| a.max(*b));
+
+    Animation {
+      name,
+      channels,
+      duration,
+    }
+  }
+
+  /// Writes the pose at time into the target nodes, times outside the clip clamp to its ends
+  pub fn sample(&self, time: f32, scene: &mut Scene) {
+    for channel in &self.channels {
+      channel.sample(time, scene);
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoopMode {
+  Repeat,
+  Clamp,
+}
+
+#[derive(Debug, Clone)]
+pub struct MixerClip {
+  pub animation: Animation,
+  pub weight: f32,
+  pub time: f32,
+  pub loop_mode: LoopMode,
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+enum Property {
+  Translation,
+  Rotation,
+  Scale,
+}
+
+/// Weighted sum of one node property, quaternions are summed as 4d vectors
+struct Blend {
+  sum: Vector4<f32>,
+  weight: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AnimationMixer {
+  pub clips: Vec<MixerClip>,
+}
+
+impl AnimationMixer {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Returns the clip index used by the other setters
+  pub fn add_clip(&mut self, animation: Animation, weight: f32, loop_mode: LoopMode) -> usize {
+    self.clips.push(MixerClip {
+      animation,
+      weight,
+      time: 0.0,
+      loop_mode,
+    });
+
+    self.clips.len() - 1
+  }
+
+  pub fn set_weight(&mut self, clip: usize, weight: f32) -> Option<()> {
+    self.clips.get_mut(clip)?.weight = weight;
+
+    Some(())
+  }
+
+  pub fn set_time(&mut self, clip: usize, time: f32) -> Option<()> {
+    self.clips.get_mut(clip)?.time = time;
+
+    Some(())
+  }
+
+  pub fn set_loop_mode(&mut self, clip: usize, loop_mode: LoopMode) -> Option<()> {
+    self.clips.get_mut(clip)?.loop_mode = loop_mode;
+
+    Some(())
+  }
+
+  /// Advances every clip by dt and writes the blended pose into the target nodes.
+  /// Weights are normalized per node property, so a property driven by a single clip gets its full value.
+  pub fn update(&mut self, dt: f32, scene: &mut Scene) {
+    let mut blends: HashMap<(Index, Property), Blend> = HashMap::new();
+
+    for clip in &mut self.clips {
+      let duration = clip.animation.duration;
+
+      clip.time = match clip.loop_mode {
+        LoopMode::Repeat if duration > 0.0 => (clip.time + dt).rem_euclid(duration),
+        LoopMode::Repeat => 0.0,
+        LoopMode::Clamp => (clip.time + dt).clamp(0.0, duration),
+      };
+
+      if clip.weight <= 0.0 {
+        continue;
+      }
+
+      for channel in &clip.animation.channels {
+        let (property, value) = match channel.get_value(clip.time) {
+          Some(ChannelValue::Translation(v)) => (Property::Translation, v.push(0.0)),
+          Some(ChannelValue::Rotation(q)) => (Property::Rotation, q.coords),
+          Some(ChannelValue::Scale(v)) => (Property::Scale, v.push(0.0)),
+          None => continue,
+        };
+
+        let blend = blends.entry((channel.node, property)).or_insert(Blend {
+          sum: Vector4::zeros(),
+          weight: 0.0,
+        });
+
+        // q and -q are the same rotation, keep them in one hemisphere so they don't cancel out
+        let sign = if property == Property::Rotation && blend.sum.dot(&value) < 0.0 {
+          -1.0
+        } else {
+          1.0
+        };
+
+        blend.sum += value * sign * clip.weight;
+        blend.weight += clip.weight;
+      }
+    }
+
+    for ((handle, property), blend) in blends {
+      let node = match scene.get_node_mut(handle) {
+        Some(node) => node,
+        None => continue,
+      };
+
+      let value = blend.sum / blend.weight;
+
+      match property {
+        Property::Translation => ChannelValue::Translation(value.xyz()),
+        Property::Rotation => {
+          ChannelValue::Rotation(UnitQuaternion::new_normalize(Quaternion::from(value)))
+        }
+        Property::Scale => ChannelValue::Scale(value.xyz()),
+      }
+      .apply(node);
+    }
+  }
+}
@@ -1,2 +1,4 @@
+pub mod animation;
 pub mod node;
 pub mod scene;
+pub mod snapshot;
@@ -1,14 +1,23 @@
 use generational_arena::Index;
-use na::{Isometry3, Matrix4, UnitQuaternion, Vector3, Vector4};
+use na::{Isometry3, Matrix3, Matrix4, Translation3, UnitQuaternion, Vector3, Vector4};
 
 #[derive(Debug, Clone)]
 pub struct Node {
   pub parent: Option<Index>,
   pub children: Vec<Index>,
   pub matrix_local: Matrix4<f32>,
+  pub translation: Vector3<f32>,
+  pub rotation: UnitQuaternion<f32>,
+  pub scale: Vector3<f32>,
   pub matrix_world: Matrix4<f32>,
+  pub dirty: bool,
   pub mesh: Option<Index>,
+  pub skin: Option<Index>,
+  /// Replaces the material of every primitive, e.g. with a debug material
+  pub material_override: Option<Index>,
   pub visible: bool,
+  /// Lower values draw first within the opaque and transparent passes
+  pub render_order: i32,
   pub name: Option<String>,
 }
 
@@ -18,12 +27,75 @@ impl Node {
       parent,
       children: vec![],
       matrix_local: Matrix4::identity(),
+      translation: Vector3::zeros(),
+      rotation: UnitQuaternion::identity(),
+      scale: Vector3::new(1.0, 1.0, 1.0),
       matrix_world: Matrix4::identity(),
+      dirty: true,
       mesh: None,
+      skin: None,
+      material_override: None,
       visible: true,
+      render_order: 0,
       name: None,
     }
   }
+
+  pub fn set_matrix_local(&mut self, matrix_local: Matrix4<f32>) -> &mut Self {
+    let (translation, rotation, scale) = decompose_matrix(&matrix_local);
+
+    self.translation = translation;
+    self.rotation = rotation;
+    self.scale = scale;
+    self.matrix_local = matrix_local;
+    self.dirty = true;
+    self
+  }
+
+  pub fn set_translation(&mut self, translation: Vector3<f32>) -> &mut Self {
+    self.translation = translation;
+    self.recompose()
+  }
+
+  pub fn set_rotation(&mut self, rotation: UnitQuaternion<f32>) -> &mut Self {
+    self.rotation = rotation;
+    self.recompose()
+  }
+
+  pub fn set_rotation_euler(&mut self, x: f32, y: f32, z: f32) -> &mut Self {
+    self.set_rotation(UnitQuaternion::from_euler_angles(x, y, z))
+  }
+
+  pub fn set_scale(&mut self, scale: Vector3<f32>) -> &mut Self {
+    self.scale = scale;
+    self.recompose()
+  }
+
+  /// Hiding a node hides its whole subtree regardless of the children flags
+  pub fn set_visible(&mut self, visible: bool) -> &mut Self {
+    self.visible = visible;
+    self
+  }
+
+  pub fn set_material_override(&mut self, material: Option<Index>) -> &mut Self {
+    self.material_override = material;
+    self
+  }
+
+  pub fn set_render_order(&mut self, render_order: i32) -> &mut Self {
+    self.render_order = render_order;
+    self
+  }
+
+  fn recompose(&mut self) -> &mut Self {
+    self.matrix_local = compose_matrix(
+      Some(self.translation),
+      Some(self.rotation),
+      Some(self.scale),
+    );
+    self.dirty = true;
+    self
+  }
 }
 
 pub fn compose_matrix(
@@ -35,8 +107,77 @@ pub fn compose_matrix(
   let r = rotation.unwrap_or(UnitQuaternion::identity());
   let s = scale.unwrap_or(Vector3::new(1.0, 1.0, 1.0));
 
-  let isometry = Isometry3::new(p, r.scaled_axis());
+  let isometry = Isometry3::from_parts(Translation3::from(p), r);
   let node_scale_matrix = Matrix4::from_diagonal(&Vector4::new(s.x, s.y, s.z, 1.0));
 
   isometry.to_homogeneous() * node_scale_matrix
 }
+
+pub fn decompose_matrix(
+  matrix: &Matrix4<f32>,
+) -> (Vector3<f32>, UnitQuaternion<f32>, Vector3<f32>) {
+  let translation = Vector3::new(matrix[12], matrix[13], matrix[14]);
+  let mut basis: Matrix3<f32> = matrix.fixed_slice::<3, 3>(0, 0).into();
+
+  let mut scale = Vector3::new(
+    basis.column(0).norm(),
+    basis.column(1).norm(),
+    basis.column(2).norm(),
+  );
+
+  if basis.determinant() < 0.0 {
+    scale.x = -scale.x;
+  }
+
+  for i in 0..3 {
+    if scale[i] != 0.0 {
+      let mut column = basis.column_mut(i);
+      column /= scale[i];
+    }
+  }
+
+  let rotation = UnitQuaternion::from_matrix(&basis);
+
+  (translation, rotation, scale)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn setters_recompose_trs_and_mark_dirty() {
+    let mut node = Node::new(None);
+
+    node.dirty = false;
+    node
+      .set_translation(Vector3::new(1.0, 2.0, 3.0))
+      .set_scale(Vector3::new(2.0, 2.0, 2.0));
+
+    assert!(node.dirty);
+    assert_eq!(
+      node.matrix_local,
+      Matrix4::new_translation(&Vector3::new(1.0, 2.0, 3.0)) * Matrix4::new_scaling(2.0)
+    );
+  }
+
+  #[test]
+  fn decompose_inverts_compose() {
+    let translation = Vector3::new(1.0, -2.0, 3.0);
+    let rotation = UnitQuaternion::from_euler_angles(0.3, -1.1, 0.7);
+    let scale = Vector3::new(2.0, 0.5, 3.0);
+    let matrix = compose_matrix(Some(translation), Some(rotation), Some(scale));
+
+    let (t, r, s) = decompose_matrix(&matrix);
+
+    assert!((t - translation).norm() < 1e-5);
+    assert!(r.angle_to(&rotation) < 1e-4);
+    assert!((s - scale).norm() < 1e-5);
+    assert!(
+      (compose_matrix(Some(t), Some(r), Some(s)) - matrix)
+        .abs()
+        .max()
+        < 1e-5
+    );
+  }
+}
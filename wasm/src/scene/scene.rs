@@ -1,5 +1,6 @@
 use generational_arena::{Arena, Index};
 use na::Matrix4;
+use std::collections::HashMap;
 
 use super::node::Node;
 
@@ -7,6 +8,7 @@ use super::node::Node;
 pub struct Scene {
   root_handle: Index,
   nodes: Arena<Node>,
+  names: HashMap<String, Vec<Index>>,
 }
 
 impl Scene {
@@ -15,13 +17,22 @@ impl Scene {
     let root_object = Node::new(None);
     let root_handle = nodes.insert(root_object);
 
-    Scene { nodes, root_handle }
+    Scene {
+      nodes,
+      root_handle,
+      names: HashMap::new(),
+    }
   }
 
   pub fn insert(&mut self, object: Node) -> Index {
     let parent_handle_option = object.parent;
+    let name = object.name.clone();
     let handle = self.nodes.insert(object);
 
+    if let Some(name) = name {
+      self.names.entry(name).or_default().push(handle);
+    }
+
     if let Some(parent_handle) = parent_handle_option {
       let parent = self.nodes.get_mut(parent_handle).unwrap();
       parent.children.push(handle);
@@ -30,20 +41,69 @@ impl Scene {
     handle
   }
 
-  pub fn set_parent(&mut self, child_handle: Index, parent_handle: Index) {
-    if let Some(current_parent_handle) = self.get_parent_handle(child_handle) {
-      let parent = self.get_node_mut(current_parent_handle).unwrap();
+  /// Moves the child under the new parent (the root when None) keeping its local transform.
+  /// Returns None when a handle is missing or the parent lies inside the child's subtree.
+  pub fn set_parent(&mut self, child_handle: Index, parent_handle: Option<Index>) -> Option<()> {
+    let parent_handle = parent_handle.unwrap_or(self.root_handle);
+
+    if self.is_ancestor(child_handle, parent_handle) || self.get_node(parent_handle).is_none() {
+      return None;
+    }
+
+    let child = self.get_node_mut(child_handle)?;
+    let current_parent_handle = child.parent.replace(parent_handle);
+
+    child.dirty = true;
+
+    if let Some(current_parent_handle) = current_parent_handle {
+      if let Some(parent) = self.get_node_mut(current_parent_handle) {
+        parent.children.retain(|ch| *ch != child_handle);
+      }
+    }
+
+    self
+      .get_node_mut(parent_handle)?
+      .children
+      .push(child_handle);
+
+    Some(())
+  }
+
+  /// Moves the child under the new parent (the root when None) keeping its world transform.
+  /// Returns None when a handle is missing or the parent lies inside the child's subtree.
+  pub fn reparent(&mut self, child_handle: Index, parent_handle: Option<Index>) -> Option<()> {
+    let parent_handle = parent_handle.unwrap_or(self.root_handle);
+
+    if self.is_ancestor(child_handle, parent_handle) {
+      return None;
+    }
+
+    let child_world = self.compute_matrix_world(child_handle)?;
+    let parent_world = self.compute_matrix_world(parent_handle)?;
 
-      parent.children.retain(|ch| *ch != child_handle);
+    // a degenerate parent can't be undone, the child keeps its local transform then
+    if let Some(parent_inverse) = parent_world.try_inverse() {
+      self
+        .get_node_mut(child_handle)?
+        .set_matrix_local(parent_inverse * child_world);
     }
 
-    let child = self.get_node_mut(child_handle).unwrap();
+    self.set_parent(child_handle, Some(parent_handle))
+  }
 
-    child.parent = Some(parent_handle);
+  /// A node counts as its own ancestor
+  pub fn is_ancestor(&self, ancestor_handle: Index, handle: Index) -> bool {
+    let mut current = Some(handle);
 
-    let parent = self.get_node_mut(parent_handle).unwrap();
+    while let Some(current_handle) = current {
+      if current_handle == ancestor_handle {
+        return true;
+      }
 
-    parent.children.push(child_handle);
+      current = self.get_parent_handle(current_handle);
+    }
+
+    false
   }
 
   fn remove_subtree(&mut self, handle: Index) -> Option<()> {
@@ -54,19 +114,58 @@ impl Scene {
       self.remove_subtree(child_handle);
     }
 
-    self.nodes.remove(handle);
+    if let Some(node) = self.nodes.remove(handle) {
+      if let Some(name) = node.name {
+        self.forget_name(&name, handle);
+      }
+    }
+
+    Some(())
+  }
+
+  fn forget_name(&mut self, name: &str, handle: Index) {
+    if let Some(handles) = self.names.get_mut(name) {
+      handles.retain(|h| *h != handle);
+
+      if handles.is_empty() {
+        self.names.remove(name);
+      }
+    }
+  }
+
+  /// Renames a node keeping the name lookup in sync, prefer it over writing `name` directly
+  pub fn set_node_name(&mut self, handle: Index, name: Option<String>) -> Option<()> {
+    let node = self.nodes.get_mut(handle)?;
+    let previous = std::mem::replace(&mut node.name, name.clone());
+
+    if let Some(previous) = previous {
+      self.forget_name(&previous, handle);
+    }
+
+    if let Some(name) = name {
+      self.names.entry(name).or_default().push(handle);
+    }
 
     Some(())
   }
 
+  pub fn find_node_by_name(&self, name: &str) -> Option<Index> {
+    self.names.get(name)?.first().copied()
+  }
+
+  pub fn find_nodes_by_name(&self, name: &str) -> Vec<Index> {
+    self.names.get(name).cloned().unwrap_or_default()
+  }
+
   pub fn remove(&mut self, handle: Index) -> Option<()> {
     if handle == self.root_handle {
       panic!("cant remove root node");
     }
 
+    let parent_handle = self.get_parent_handle(handle)?;
+
     self.remove_subtree(handle);
 
-    let parent_handle = self.get_parent_handle(handle)?;
     let parent = self.nodes.get_mut(parent_handle).unwrap();
 
     parent
@@ -77,7 +176,41 @@ impl Scene {
   }
 
   pub fn update_matrix_world(&mut self) {
-    self.update_matrix_world_subtree(self.root_handle);
+    self.update_world_matrices(self.root_handle);
+  }
+
+  /// Recomputes world matrices only for dirty nodes and their descendants
+  pub fn update_world_matrices(&mut self, handle: Index) {
+    let parent_matrix_world = match self.get_parent_handle(handle) {
+      Some(parent_handle) => self.get_node(parent_handle).unwrap().matrix_world,
+      None => Matrix4::identity(),
+    };
+
+    self.update_world_matrices_subtree(handle, parent_matrix_world, false);
+  }
+
+  fn update_world_matrices_subtree(
+    &mut self,
+    handle: Index,
+    parent_matrix_world: Matrix4<f32>,
+    parent_changed: bool,
+  ) -> Option<()> {
+    let node = self.get_node_mut(handle)?;
+    let changed = parent_changed || node.dirty;
+
+    if changed {
+      node.matrix_world = parent_matrix_world * node.matrix_local;
+      node.dirty = false;
+    }
+
+    let matrix_world = node.matrix_world;
+    let children = node.children.clone();
+
+    for child_handle in children {
+      self.update_world_matrices_subtree(child_handle, matrix_world, changed);
+    }
+
+    Some(())
   }
 
   pub fn update_matrix_world_subtree(&mut self, handle: Index) {
@@ -91,12 +224,22 @@ impl Scene {
     let children = node.children.clone();
 
     node.matrix_world = matrix_world;
+    node.dirty = false;
 
     for child_handle in children {
       self.update_matrix_world_subtree(child_handle);
     }
   }
 
+  pub fn compute_matrix_world(&self, handle: Index) -> Option<Matrix4<f32>> {
+    let node = self.get_node(handle)?;
+
+    match node.parent {
+      Some(parent_handle) => Some(self.compute_matrix_world(parent_handle)? * node.matrix_local),
+      None => Some(node.matrix_local),
+    }
+  }
+
   pub fn collect_visible_items(&self) -> Vec<Index> {
     let mut items: Vec<Index> = vec![];
 
@@ -129,6 +272,58 @@ impl Scene {
     Some(())
   }
 
+  /// Depth-first walk visiting each node before its children
+  pub fn traverse(&self, root: Index, mut f: impl FnMut(Index, &Node)) {
+    self.traverse_subtree(root, &mut f);
+  }
+
+  fn traverse_subtree(&self, handle: Index, f: &mut impl FnMut(Index, &Node)) -> Option<()> {
+    let node = self.get_node(handle)?;
+
+    f(handle, node);
+
+    for child_handle in &node.children {
+      self.traverse_subtree(*child_handle, f);
+    }
+
+    Some(())
+  }
+
+  pub fn traverse_mut(&mut self, root: Index, mut f: impl FnMut(Index, &mut Node)) {
+    self.traverse_subtree_mut(root, &mut f);
+  }
+
+  fn traverse_subtree_mut(
+    &mut self,
+    handle: Index,
+    f: &mut impl FnMut(Index, &mut Node),
+  ) -> Option<()> {
+    let node = self.get_node_mut(handle)?;
+
+    f(handle, node);
+
+    let children = node.children.clone();
+
+    for child_handle in children {
+      self.traverse_subtree_mut(child_handle, f);
+    }
+
+    Some(())
+  }
+
+  /// A node is visible only when it and all of its ancestors are
+  pub fn is_visible(&self, handle: Index) -> bool {
+    let node = match self.get_node(handle) {
+      Some(node) => node,
+      None => return false,
+    };
+
+    match node.parent {
+      Some(parent_handle) => node.visible && self.is_visible(parent_handle),
+      None => node.visible,
+    }
+  }
+
   pub fn get_node(&self, handle: Index) -> Option<&Node> {
     self.nodes.get(handle)
   }
@@ -146,3 +341,237 @@ impl Scene {
     self.root_handle
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use na::Vector3;
+
+  fn assert_matrix_eq(a: &Matrix4<f32>, b: &Matrix4<f32>) {
+    assert!((a - b).abs().max() < 1e-5, "{} != {}", a, b);
+  }
+
+  /// root -> parent -> child, the parent translated along x
+  fn scene_with_chain() -> (Scene, Index, Index) {
+    let mut scene = Scene::new();
+    let mut parent = Node::new(Some(scene.get_root_handle()));
+
+    parent.set_translation(Vector3::new(1.0, 0.0, 0.0));
+
+    let parent_handle = scene.insert(parent);
+    let child_handle = scene.insert(Node::new(Some(parent_handle)));
+
+    scene.update_matrix_world();
+
+    (scene, parent_handle, child_handle)
+  }
+
+  #[test]
+  fn set_parent_keeps_the_local_transform() {
+    let (mut scene, parent_handle, child_handle) = scene_with_chain();
+    let other_handle = scene.insert(Node::new(Some(scene.get_root_handle())));
+
+    scene
+      .get_node_mut(child_handle)
+      .unwrap()
+      .set_translation(Vector3::new(0.0, 2.0, 0.0));
+    assert_eq!(scene.set_parent(child_handle, Some(other_handle)), Some(()));
+    scene.update_matrix_world();
+
+    let child = scene.get_node(child_handle).unwrap();
+
+    assert_eq!(child.parent, Some(other_handle));
+    assert_matrix_eq(&child.matrix_world, &child.matrix_local);
+    assert!(!scene
+      .get_node(parent_handle)
+      .unwrap()
+      .children
+      .contains(&child_handle));
+    assert_eq!(
+      scene.get_node(other_handle).unwrap().children,
+      vec![child_handle]
+    );
+  }
+
+  #[test]
+  fn set_parent_rejects_cycles() {
+    let (mut scene, parent_handle, child_handle) = scene_with_chain();
+    let root_handle = scene.get_root_handle();
+
+    assert_eq!(scene.set_parent(parent_handle, Some(child_handle)), None);
+    assert_eq!(scene.set_parent(parent_handle, Some(parent_handle)), None);
+    assert_eq!(scene.set_parent(root_handle, Some(child_handle)), None);
+    assert_eq!(scene.get_parent_handle(parent_handle), Some(root_handle));
+    assert_eq!(
+      scene.get_node(parent_handle).unwrap().children,
+      vec![child_handle]
+    );
+  }
+
+  #[test]
+  fn update_world_matrices_propagates_dirty_nodes() {
+    let (mut scene, parent_handle, child_handle) = scene_with_chain();
+
+    scene
+      .get_node_mut(child_handle)
+      .unwrap()
+      .set_translation(Vector3::new(0.0, 1.0, 0.0));
+    assert!(scene.get_node(child_handle).unwrap().dirty);
+    scene.update_matrix_world();
+
+    let parent = scene.get_node(parent_handle).unwrap();
+    let child = scene.get_node(child_handle).unwrap();
+
+    assert!(!parent.dirty && !child.dirty);
+    assert_matrix_eq(
+      &child.matrix_world,
+      &Matrix4::new_translation(&Vector3::new(1.0, 1.0, 0.0)),
+    );
+  }
+
+  #[test]
+  fn reparent_keeps_the_world_transform() {
+    let (mut scene, parent_handle, child_handle) = scene_with_chain();
+    let mut other = Node::new(Some(scene.get_root_handle()));
+
+    other.set_translation(Vector3::new(0.0, 0.0, 5.0));
+
+    let other_handle = scene.insert(other);
+
+    scene.update_matrix_world();
+
+    let before = scene.get_node(child_handle).unwrap().matrix_world;
+
+    assert_eq!(scene.reparent(child_handle, Some(other_handle)), Some(()));
+    scene.update_matrix_world();
+
+    let child = scene.get_node(child_handle).unwrap();
+
+    assert_eq!(child.parent, Some(other_handle));
+    assert_matrix_eq(&child.matrix_world, &before);
+    assert_matrix_eq(
+      &child.matrix_local,
+      &Matrix4::new_translation(&Vector3::new(1.0, 0.0, -5.0)),
+    );
+    assert!(!scene
+      .get_node(parent_handle)
+      .unwrap()
+      .children
+      .contains(&child_handle));
+  }
+
+  #[test]
+  fn reparent_rejects_cycles() {
+    let (mut scene, parent_handle, child_handle) = scene_with_chain();
+
+    assert_eq!(scene.reparent(parent_handle, Some(child_handle)), None);
+    assert_eq!(scene.reparent(child_handle, Some(child_handle)), None);
+    assert_eq!(scene.get_parent_handle(child_handle), Some(parent_handle));
+  }
+
+  #[test]
+  fn collect_visible_sub_items_prunes_hidden_subtrees() {
+    let (mut scene, parent_handle, child_handle) = scene_with_chain();
+    let mesh = Some(Index::from_raw_parts(0, 0));
+    let mut sibling = Node::new(Some(scene.get_root_handle()));
+
+    sibling.mesh = mesh;
+
+    let sibling_handle = scene.insert(sibling);
+
+    scene.get_node_mut(parent_handle).unwrap().mesh = mesh;
+    scene.get_node_mut(child_handle).unwrap().mesh = mesh;
+
+    let root_handle = scene.get_root_handle();
+
+    assert_eq!(
+      scene.collect_visible_sub_items(root_handle),
+      vec![parent_handle, child_handle, sibling_handle]
+    );
+
+    scene
+      .get_node_mut(parent_handle)
+      .unwrap()
+      .set_visible(false);
+
+    assert_eq!(
+      scene.collect_visible_sub_items(root_handle),
+      vec![sibling_handle]
+    );
+    assert!(scene.collect_visible_sub_items(parent_handle).is_empty());
+  }
+
+  #[test]
+  fn moving_a_parent_moves_its_clean_child() {
+    let (mut scene, parent_handle, child_handle) = scene_with_chain();
+
+    scene
+      .get_node_mut(child_handle)
+      .unwrap()
+      .set_translation(Vector3::new(0.0, 1.0, 0.0));
+    scene.update_matrix_world();
+
+    // only the parent changes, the child stays clean and still follows
+    scene
+      .get_node_mut(parent_handle)
+      .unwrap()
+      .set_translation(Vector3::new(0.0, 0.0, 3.0));
+    assert!(!scene.get_node(child_handle).unwrap().dirty);
+    scene.update_matrix_world();
+
+    assert_matrix_eq(
+      &scene.get_node(child_handle).unwrap().matrix_world,
+      &Matrix4::new_translation(&Vector3::new(0.0, 1.0, 3.0)),
+    );
+  }
+
+  #[test]
+  fn traverse_visits_parents_before_children() {
+    let (mut scene, parent_handle, child_handle) = scene_with_chain();
+    let root_handle = scene.get_root_handle();
+    let sibling_handle = scene.insert(Node::new(Some(root_handle)));
+    let mut visited = vec![];
+
+    scene.traverse(root_handle, |handle, _| visited.push(handle));
+
+    assert_eq!(
+      visited,
+      vec![root_handle, parent_handle, child_handle, sibling_handle]
+    );
+
+    let mut count = 0;
+
+    scene.traverse_mut(parent_handle, |_, node| {
+      node.render_order = 1;
+      count += 1;
+    });
+
+    assert_eq!(count, 2);
+    assert_eq!(scene.get_node(child_handle).unwrap().render_order, 1);
+    assert_eq!(scene.get_node(sibling_handle).unwrap().render_order, 0);
+  }
+
+  #[test]
+  fn hiding_a_parent_hides_every_descendant() {
+    let (mut scene, parent_handle, child_handle) = scene_with_chain();
+    let mesh = Some(Index::from_raw_parts(0, 0));
+    let grandchild_handle = scene.insert(Node::new(Some(child_handle)));
+
+    for handle in &[parent_handle, child_handle, grandchild_handle] {
+      scene.get_node_mut(*handle).unwrap().mesh = mesh;
+    }
+
+    scene
+      .get_node_mut(parent_handle)
+      .unwrap()
+      .set_visible(false);
+
+    let visible = scene.collect_visible_items();
+
+    assert!(visible.is_empty());
+    assert!(scene.get_node(grandchild_handle).unwrap().visible);
+    assert!(!scene.is_visible(grandchild_handle));
+    assert!(!scene.is_visible(parent_handle));
+    assert!(scene.is_visible(scene.get_root_handle()));
+  }
+}
@@ -0,0 +1,294 @@
+use anyhow::{bail, Result};
+use generational_arena::Index;
+use na::{Matrix4, Quaternion, UnitQuaternion, Vector3};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::node::Node;
+use super::scene::Scene;
+
+pub const SNAPSHOT_MAGIC: &[u8; 4] = b"SCNS";
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Arena a node reference points into, raw parts alone collide across arenas
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum HandleKind {
+  Mesh,
+  Skin,
+  Material,
+}
+
+/// Raw parts of a handle into one of the renderer arenas
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct HandleRef {
+  pub kind: HandleKind,
+  pub index: usize,
+  pub generation: u64,
+}
+
+impl HandleRef {
+  pub fn new(kind: HandleKind, handle: Index) -> Self {
+    let (index, generation) = handle.into_raw_parts();
+
+    HandleRef {
+      kind,
+      index,
+      generation,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeSnapshot {
+  /// Position of the parent in `SceneSnapshot::nodes`, always before the node itself
+  pub parent: Option<usize>,
+  pub matrix_local: [f32; 16],
+  pub translation: [f32; 3],
+  /// Quaternion as (i, j, k, w)
+  pub rotation: [f32; 4],
+  pub scale: [f32; 3],
+  pub mesh: Option<HandleRef>,
+  pub skin: Option<HandleRef>,
+  pub material_override: Option<HandleRef>,
+  pub visible: bool,
+  pub render_order: i32,
+  pub name: Option<String>,
+}
+
+/// Plain data copy of the graph under the scene root, the root comes first. GL resources
+/// aren't part of it, only the handles nodes refer them by.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneSnapshot {
+  pub version: u32,
+  pub nodes: Vec<NodeSnapshot>,
+}
+
+impl SceneSnapshot {
+  /// Magic and version as a header, so stale files fail before decoding, then the
+  /// bincode payload
+  pub fn to_bytes(&self) -> Result<Vec<u8>> {
+    let mut bytes = SNAPSHOT_MAGIC.to_vec();
+
+    bytes.extend_from_slice(&self.version.to_le_bytes());
+    bincode::serialize_into(&mut bytes, self)?;
+
+    Ok(bytes)
+  }
+
+  pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+    if bytes.len() < 8 || &bytes[0..4] != SNAPSHOT_MAGIC {
+      bail!("not a scene snapshot");
+    }
+
+    let version = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+
+    if version > SNAPSHOT_VERSION {
+      bail!(
+        "scene snapshot version {} is newer than the supported {}",
+        version,
+        SNAPSHOT_VERSION
+      );
+    }
+
+    Ok(bincode::deserialize(&bytes[8..])?)
+  }
+}
+
+impl Scene {
+  /// Nodes detached from the root are left out
+  pub fn to_snapshot(&self) -> SceneSnapshot {
+    let mut positions: HashMap<Index, usize> = HashMap::new();
+    let mut nodes = vec![];
+
+    self.traverse(self.get_root_handle(), |handle, node| {
+      let q = node.rotation.quaternion();
+
+      positions.insert(handle, nodes.len());
+      nodes.push(NodeSnapshot {
+        parent: node
+          .parent
+          .and_then(|parent| positions.get(&parent).copied()),
+        matrix_local: copy_array(node.matrix_local.as_slice()),
+        translation: copy_array(node.translation.as_slice()),
+        rotation: [q.i, q.j, q.k, q.w],
+        scale: copy_array(node.scale.as_slice()),
+        mesh: node.mesh.map(|h| HandleRef::new(HandleKind::Mesh, h)),
+        skin: node.skin.map(|h| HandleRef::new(HandleKind::Skin, h)),
+        material_override: node
+          .material_override
+          .map(|h| HandleRef::new(HandleKind::Material, h)),
+        visible: node.visible,
+        render_order: node.render_order,
+        name: node.name.clone(),
+      });
+    });
+
+    SceneSnapshot {
+      version: SNAPSHOT_VERSION,
+      nodes,
+    }
+  }
+
+  /// Node handles are fresh, the returned ones follow the snapshot order. Mesh, skin and
+  /// material references go through remap, since the caller reloads those resources and
+  /// their handles change; the kind tells which arena a reference belongs to. References
+  /// it maps to None are dropped.
+  pub fn from_snapshot(
+    snapshot: &SceneSnapshot,
+    mut remap: impl FnMut(HandleRef) -> Option<Index>,
+  ) -> Result<(Scene, Vec<Index>)> {
+    let mut scene = Scene::new();
+    let mut handles: Vec<Index> = Vec::with_capacity(snapshot.nodes.len());
+
+    for (i, node_snapshot) in snapshot.nodes.iter().enumerate() {
+      let parent = match (i, node_snapshot.parent) {
+        (0, None) => None,
+        (0, Some(_)) => bail!("scene snapshot root has a parent"),
+        (_, Some(parent)) if parent < i => Some(handles[parent]),
+        (_, _) => bail!("scene snapshot node {} has no valid parent", i),
+      };
+
+      let mut node = Node::new(parent);
+      let [x, y, z, w] = node_snapshot.rotation;
+
+      node.matrix_local = Matrix4::from_column_slice(&node_snapshot.matrix_local);
+      node.translation = Vector3::from(node_snapshot.translation);
+      node.rotation = UnitQuaternion::new_unchecked(Quaternion::new(w, x, y, z));
+      node.scale = Vector3::from(node_snapshot.scale);
+      node.mesh = node_snapshot.mesh.and_then(&mut remap);
+      node.skin = node_snapshot.skin.and_then(&mut remap);
+      node.material_override = node_snapshot.material_override.and_then(&mut remap);
+      node.visible = node_snapshot.visible;
+      node.render_order = node_snapshot.render_order;
+      node.name = node_snapshot.name.clone();
+
+      let handle = match parent {
+        Some(_) => scene.insert(node),
+        None => {
+          let root_handle = scene.get_root_handle();
+          let name = node.name.take();
+
+          // names are indexed on insert, the root replaced in place registers its own
+          *scene.get_node_mut(root_handle).unwrap() = node;
+          scene.set_node_name(root_handle, name);
+          root_handle
+        }
+      };
+
+      handles.push(handle);
+    }
+
+    Ok((scene, handles))
+  }
+}
+
+fn copy_array<const N: usize>(slice: &[f32]) -> [f32; N] {
+  let mut array = [0.0; N];
+
+  array.copy_from_slice(slice);
+  array
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn build_scene() -> (Scene, Index, Index) {
+    let mut scene = Scene::new();
+    let mut parent = Node::new(Some(scene.get_root_handle()));
+
+    parent
+      .set_translation(Vector3::new(1.0, 2.0, 3.0))
+      .set_rotation_euler(0.1, 0.2, 0.3)
+      .set_scale(Vector3::new(2.0, 1.0, 0.5));
+    parent.mesh = Some(Index::from_raw_parts(0, 0));
+    parent.material_override = Some(Index::from_raw_parts(0, 0));
+
+    let parent_handle = scene.insert(parent);
+    let mut child = Node::new(Some(parent_handle));
+
+    child.set_translation(Vector3::new(0.0, -1.0, 0.0));
+    child.visible = false;
+    child.render_order = 4;
+
+    let child_handle = scene.insert(child);
+
+    scene.set_node_name(child_handle, Some(String::from("child")));
+
+    (scene, parent_handle, child_handle)
+  }
+
+  #[test]
+  fn snapshot_round_trip_keeps_transforms_and_hierarchy() {
+    let (scene, parent_handle, child_handle) = build_scene();
+    let snapshot = scene.to_snapshot();
+    let (loaded, handles) = Scene::from_snapshot(&snapshot, |handle| {
+      Some(Index::from_raw_parts(handle.index, handle.generation))
+    })
+    .unwrap();
+
+    assert_eq!(handles.len(), 3);
+    assert_eq!(loaded.to_snapshot(), snapshot);
+
+    for (original, handle) in [(parent_handle, handles[1]), (child_handle, handles[2])] {
+      let a = scene.get_node(original).unwrap();
+      let b = loaded.get_node(handle).unwrap();
+
+      assert_eq!(a.matrix_local, b.matrix_local);
+      assert_eq!(a.translation, b.translation);
+      assert_eq!(a.rotation, b.rotation);
+      assert_eq!(a.scale, b.scale);
+    }
+
+    assert_eq!(loaded.get_parent_handle(handles[2]), Some(handles[1]));
+    assert_eq!(
+      loaded.get_parent_handle(handles[1]),
+      Some(loaded.get_root_handle())
+    );
+    assert_eq!(loaded.find_node_by_name("child"), Some(handles[2]));
+  }
+
+  #[test]
+  fn remap_tells_references_apart_by_kind() {
+    let (scene, _, _) = build_scene();
+    let mesh = Index::from_raw_parts(5, 1);
+    let material = Index::from_raw_parts(7, 2);
+    let (loaded, handles) =
+      Scene::from_snapshot(&scene.to_snapshot(), |handle| match handle.kind {
+        HandleKind::Mesh => Some(mesh),
+        HandleKind::Material => Some(material),
+        HandleKind::Skin => None,
+      })
+      .unwrap();
+    let parent = loaded.get_node(handles[1]).unwrap();
+
+    assert_eq!(parent.mesh, Some(mesh));
+    assert_eq!(parent.material_override, Some(material));
+    assert_eq!(parent.skin, None);
+  }
+
+  #[test]
+  fn bytes_round_trip() {
+    let (scene, _, _) = build_scene();
+    let snapshot = scene.to_snapshot();
+    let bytes = snapshot.to_bytes().unwrap();
+
+    assert_eq!(&bytes[0..4], SNAPSHOT_MAGIC);
+    assert_eq!(SceneSnapshot::from_bytes(&bytes).unwrap(), snapshot);
+  }
+
+  #[test]
+  fn bytes_reject_bad_magic_and_newer_versions() {
+    let (scene, _, _) = build_scene();
+    let bytes = scene.to_snapshot().to_bytes().unwrap();
+
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] = b'X';
+    assert!(SceneSnapshot::from_bytes(&bad_magic).is_err());
+    assert!(SceneSnapshot::from_bytes(&bytes[0..6]).is_err());
+
+    let mut newer = bytes;
+    newer[4..8].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+    assert!(SceneSnapshot::from_bytes(&newer).is_err());
+  }
+}
@@ -0,0 +1,152 @@
+use generational_arena::Index;
+use na::{Point3, Vector3};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::result::Result as StdResult;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlCanvasElement, WebGlRenderingContext};
+
+use crate::renderer::webgl::context::Context;
+use crate::renderer::webgl::orbit_controls::OrbitControls;
+use crate::renderer::webgl::renderer::{Camera, DirectionalLight, Projection, Renderer};
+
+fn to_js_error<E: std::fmt::Display>(e: E) -> JsValue {
+  JsValue::from_str(&e.to_string())
+}
+
+/// Minimal browser facing wrapper, owns the renderer and every arena behind it
+#[wasm_bindgen]
+pub struct Viewer {
+  renderer: Renderer,
+  canvas: HtmlCanvasElement,
+  camera_handle: Index,
+  controls: OrbitControls,
+  model: Option<Index>,
+  fov: f32,
+}
+
+#[wasm_bindgen]
+impl Viewer {
+  #[wasm_bindgen(constructor)]
+  pub fn new(canvas: HtmlCanvasElement) -> StdResult<Viewer, JsValue> {
+    // WebGL2 goes through the WebGL1 bindings too, Context switches to the core APIs
+    let gl = match canvas.get_context("webgl2")? {
+      Some(gl) => gl,
+      None => canvas
+        .get_context("webgl")?
+        .ok_or_else(|| JsValue::from_str("webgl is not supported"))?,
+    }
+    .unchecked_into::<WebGlRenderingContext>();
+
+    let mut renderer = Renderer::new(Context::new(gl));
+
+    renderer.set_clear_color(1.0, 1.0, 1.0, 1.0);
+    renderer.insert_light(DirectionalLight::new(
+      Vector3::new(-0.5, -1.0, -0.3),
+      Vector3::new(1.0, 1.0, 1.0),
+      1.0,
+    ));
+
+    let camera_handle = renderer
+      .cameras
+      .insert(Camera::perspective(PI / 4.0, 1.0, 0.01, 1000.0));
+
+    Ok(Viewer {
+      renderer,
+      canvas,
+      camera_handle,
+      controls: OrbitControls::new(Point3::origin(), 5.0),
+      model: None,
+      fov: PI / 4.0,
+    })
+  }
+
+  /// Replaces the current model, buffers must be embedded in the GLB
+  pub fn load_gltf(&mut self, bytes: &[u8]) -> StdResult<(), JsValue> {
+    let handle = self
+      .renderer
+      .load_gltf_from_slice(bytes, &HashMap::new())
+      .map_err(to_js_error)?
+      .root
+      .ok_or_else(|| JsValue::from_str("gltf has no scenes"))?;
+    let root_handle = self.renderer.scene.get_root_handle();
+
+    if let Some(model) = self.model.take() {
+      self.renderer.scene.remove(model);
+    }
+
+    self.renderer.scene.set_parent(handle, None);
+    self.renderer.scene.update_matrix_world();
+    self.model = Some(handle);
+
+    if let Some(bounds) = self.renderer.compute_scene_bounds(root_handle) {
+      let center = (bounds.min + bounds.max) / 2.0;
+      let radius = (bounds.max - bounds.min).norm() / 2.0;
+
+      self.controls.target = Point3::from(center);
+      self.controls.radius = radius / (self.fov / 2.0).sin();
+    }
+
+    Ok(())
+  }
+
+  pub fn set_camera_perspective(&mut self, fov: f32, near: f32, far: f32) {
+    let aspect = self.get_aspect();
+
+    self.fov = fov;
+
+    if let Some(camera) = self.renderer.cameras.get_mut(self.camera_handle) {
+      camera.set_projection(Projection::Perspective {
+        fov_y: fov,
+        aspect,
+        near,
+        far,
+      });
+    }
+  }
+
+  /// Orbits the camera around the model, angles are in radians
+  pub fn rotate(&mut self, delta_yaw: f32, delta_pitch: f32) {
+    self.controls.rotate(delta_yaw, delta_pitch);
+  }
+
+  pub fn zoom(&mut self, delta: f32) {
+    self.controls.zoom(delta);
+  }
+
+  pub fn render(&mut self) -> StdResult<(), JsValue> {
+    let width = self.canvas.width();
+    let height = self.canvas.height();
+    let aspect = self.get_aspect();
+
+    self
+      .renderer
+      .ctx
+      .viewport(0, 0, width as i32, height as i32);
+    self.renderer.clear();
+    self.renderer.scene.update_matrix_world();
+
+    let camera = self
+      .renderer
+      .cameras
+      .get_mut(self.camera_handle)
+      .ok_or_else(|| JsValue::from_str("camera was removed"))?;
+
+    camera.set_aspect(aspect);
+    self.controls.update_camera(camera);
+
+    let root_handle = self.renderer.scene.get_root_handle();
+
+    self
+      .renderer
+      .try_render_scene(root_handle, self.camera_handle)
+      .map_err(to_js_error)
+  }
+}
+
+impl Viewer {
+  fn get_aspect(&self) -> f32 {
+    self.canvas.width() as f32 / self.canvas.height().max(1) as f32
+  }
+}